@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use serde::{Deserialize, Serialize};
 
 use tauri::Manager;
@@ -5,78 +7,254 @@ use tauri::Manager;
 use tokio::sync::Mutex;
 
 use crate::{
+	audio_cache::AudioCache,
+	audio_requests::{AudioRequestId, AudioRequests, CancellationToken},
+	backup::{self, BackupInfo},
+	error::{Error, Result},
+	integrity::DataVerification,
 	learning_data::{
 		FinishedTask,
 		LearningData, 
 		LearningTask,
 		LearningWord, 
+		SentenceChanges,
 		SentenceId
 	},
-	options::{Options, WeightFactors, WordMemoryParameters},
+	options::{Options, SentenceDifficultyParameters, WeightFactors, WordMemoryParameters, OPTIONS_SAVE_FILE},
+	scheduler::SchedulingAlgorithm,
 	sentence_audio::AudioLoader,
 	source_data::{
+		self,
 		SourceData,
 		SourceDataDownloadStatus,
+		SourceDataImportInfo,
 		SourceDataInfo,
 		LANGUAGES,
-	}
+	},
+	voice_models::VoiceModelInfo,
+	voices::{self, VoiceInfo},
+	word_selection::WordSelection,
 };
 
 //----------------------------------------------------------------
 
+// Progress is saved after this many finished tasks, so that little is lost if the app crashes.
+const AUTOSAVE_TASK_COUNT: u32 = 10;
+
+/*
+	Saves everything that changes while learning and makes a backup if it's time for one.
+*/
+fn save_progress(options: &Options, learning_data: &mut LearningData) -> Result<()> {
+	options.save()?;
+	learning_data.save_words_to_file(options.language_index)?;
+	// The progress itself has been saved, so a failed backup is not worth bothering the user with.
+	if let Err(error) = backup::create_backup_if_due(options) {
+		eprintln!("Could not create backup: {}", error);
+	}
+	Ok(())
+}
+
+//----------------------------------------------------------------
+
 struct AppState {
 	options: Mutex<Options>,
 	learning_data: Mutex<LearningData>,
 	audio_loader: Mutex<AudioLoader>,
+	// Shared with the audio loader. It is only locked briefly, so it's a standard mutex that can be used without waiting for
+	// the audio loader.
+	audio_cache: Arc<std::sync::Mutex<AudioCache>>,
+	audio_requests: AudioRequests,
+	// Cancels the audio prefetch that is running, see start_audio_prefetch.
+	audio_prefetch_token: std::sync::Mutex<CancellationToken>,
 }
 
 impl AppState {
-	fn new(app: tauri::AppHandle, source_data: &SourceData) -> Self {
+	fn new(app: tauri::AppHandle, source_data: &SourceData) -> Result<Self> {
 		let options = Options::new(source_data.language_index);
 
-		let learning_data = LearningData::load_from_source_data(&source_data, &options);
-		learning_data.save_sentences_to_file(source_data.language_index);
-
-		Self {
+		let mut learning_data = LearningData::load_from_source_data(source_data, &options)?;
+		learning_data.save_sentences_to_file(source_data.language_index)?;
+		options.save()?;
+
+		let audio_cache = Arc::new(std::sync::Mutex::new(AudioCache::open(options.audio_cache_size_limit_bytes)));
+		let audio_loader = Mutex::new(AudioLoader::new(
+			&app, 
+			options.current_voices(), 
+			audio_cache.clone(), 
+			options.synthesis_thread_count, 
+			options.voice_model_estimated_memory_budget_bytes
+		)?);
+		Ok(Self {
 			options: Mutex::new(options),
 			learning_data: Mutex::new(learning_data),
-			audio_loader: Mutex::new(AudioLoader::new(app, source_data.language_index)),
-		}
+			audio_loader,
+			audio_cache,
+			audio_requests: AudioRequests::default(),
+			audio_prefetch_token: Default::default(),
+		})
 	}
-	fn load(app: tauri::AppHandle, options: Options) -> Self {
-		let learning_data = Mutex::new(LearningData::load_from_file(options.language_index));
-		let audio_loader = Mutex::new(AudioLoader::new(app, options.language_index));
-		Self {
+	fn load(app: tauri::AppHandle, options: Options) -> Result<Self> {
+		let learning_data = Mutex::new(LearningData::load_from_file(options.language_index, &options)?);
+		let audio_cache = Arc::new(std::sync::Mutex::new(AudioCache::open(options.audio_cache_size_limit_bytes)));
+		let audio_loader = Mutex::new(AudioLoader::new(
+			&app, 
+			options.current_voices(), 
+			audio_cache.clone(), 
+			options.synthesis_thread_count, 
+			options.voice_model_estimated_memory_budget_bytes
+		)?);
+		Ok(Self {
 			options: Mutex::new(options),
 			learning_data,
 			audio_loader,
-		}
+			audio_cache,
+			audio_requests: AudioRequests::default(),
+			audio_prefetch_token: Default::default(),
+		})
 	}
-	fn save(&self) {
+	fn save(&self) -> Result<()> {
 		let options = self.options.blocking_lock();
-		options.save();
-		self.learning_data.blocking_lock().save_words_to_file(options.language_index)
+		save_progress(&options, &mut self.learning_data.blocking_lock())?;
+		// Saves which cached clips were used recently.
+		self.audio_cache.lock().unwrap().save().map_err(|error| Error::from(error).context("saving the audio cache"))
 	}
 }
 
 //----------------------------------------------------------------
 
 #[tauri::command]
-fn next_task(state: tauri::State<AppState>) -> LearningTask {
-	state.learning_data.blocking_lock().next_task()
+fn next_task(state: tauri::State<AppState>) -> Result<LearningTask> {
+	let options = state.options.blocking_lock();
+	state.learning_data.blocking_lock().next_task(&options)
 }
 
 #[tauri::command]
-fn finish_task(state: tauri::State<AppState>, task: FinishedTask) {
-	state.learning_data.blocking_lock().finish_task(task, &state.options.blocking_lock())
+fn finish_task(state: tauri::State<AppState>, task: FinishedTask) -> Result<()> {
+	let options = state.options.blocking_lock();
+	let mut learning_data = state.learning_data.blocking_lock();
+	learning_data.finish_task(task, &options);
+
+	if learning_data.unsaved_task_count() >= AUTOSAVE_TASK_COUNT {
+		save_progress(&options, &mut learning_data)?;
+	}
+	Ok(())
 }
 
 //----------------------------------------------------------------
 
+/*
+	Once the audio of the sentence has been loaded, the audio of the upcoming tasks is loaded into the cache in the 
+	background. A cancelled load is followed by a new one, so it doesn't start a prefetch.
+*/
 #[tauri::command]
-async fn load_sentence_audio(app: tauri::AppHandle, window: tauri::Window, sentence_id: SentenceId, sentence: String) {
-	app.state::<AppState>().audio_loader.lock().await
-		.load_audio_for_sentence(&window, sentence_id, sentence).await;
+async fn load_sentence_audio(
+	app: tauri::AppHandle, 
+	window: tauri::Window, 
+	request_id: AudioRequestId, 
+	sentence_id: SentenceId, 
+	sentence: String
+) -> Result<()> {
+	let state = app.state::<AppState>();
+	let token = state.audio_requests.start(window.label(), request_id);
+	// Synthesis jobs of the prefetch that haven't started yet are skipped, so that the sentence being shown doesn't wait 
+	// for long.
+	stop_audio_prefetch(&state);
+
+	let sources = state.audio_loader.lock().await.sources();
+	let result = sources.load_audio_for_sentence(&window, request_id, &token, sentence_id, sentence).await;
+	state.audio_requests.finish(window.label(), request_id);
+	if !matches!(result, Err(Error::Cancelled)) {
+		let upcoming_sentences = state.learning_data.lock().await.upcoming_tasks()
+			.map(|task| (task.sentence_id, task.sentence.clone()))
+			.collect();
+		start_audio_prefetch(&app, upcoming_sentences);
+	}
+	result
+}
+
+/*
+	Loads the audio of the sentences into the audio cache in the background, one sentence at a time so that the synthesis 
+	pool isn't filled up with prefetch jobs. Starting a prefetch stops the previous one.
+*/
+fn start_audio_prefetch(app: &tauri::AppHandle, sentences: Vec<(SentenceId, String)>) {
+	let state = app.state::<AppState>();
+	let token = CancellationToken::default();
+	std::mem::replace(&mut *state.audio_prefetch_token.lock().unwrap(), token.clone()).cancel();
+
+	let app = app.clone();
+	tauri::async_runtime::spawn(async move {
+		let sources = app.state::<AppState>().audio_loader.lock().await.sources();
+		for (sentence_id, sentence) in sentences {
+			if token.is_cancelled() {
+				return;
+			}
+			let result = sources.prefetch_audio_for_sentence(sentence_id, &sentence, &token).await;
+			match result {
+				Ok(()) => {}
+				Err(Error::Cancelled) => return,
+				Err(error) => eprintln!("Could not prefetch audio for '{}': {}", sentence, error),
+			}
+		}
+	});
+}
+
+fn stop_audio_prefetch(state: &AppState) {
+	state.audio_prefetch_token.lock().unwrap().cancel();
+}
+
+/*
+	Cancels a load_sentence_audio call of the window. The clips that it already sent are ignored by the front-end since it
+	has moved on to another request.
+*/
+#[tauri::command]
+fn cancel_sentence_audio(state: tauri::State<AppState>, window: tauri::Window, request_id: AudioRequestId) -> Result<()> {
+	state.audio_requests.cancel(window.label(), request_id);
+	Ok(())
+}
+
+/*
+	Removes all cached sentence audio and returns the number of bytes freed.
+*/
+#[tauri::command]
+fn clear_audio_cache(state: tauri::State<AppState>) -> Result<u64> {
+	Ok(state.audio_cache.lock().unwrap().clear()?)
+}
+
+#[tauri::command]
+fn set_audio_cache_size_limit(state: tauri::State<AppState>, limit_bytes: u64) -> Result<()> {
+	state.options.blocking_lock().audio_cache_size_limit_bytes = limit_bytes;
+	state.audio_cache.lock().unwrap().set_size_limit(limit_bytes)?;
+	Ok(())
+}
+
+#[tauri::command]
+async fn set_synthesis_thread_count(app: tauri::AppHandle, thread_count: usize) -> Result<()> {
+	if thread_count == 0 {
+		return Err(Error::InvalidRequest("at least one synthesis thread is needed".to_owned()));
+	}
+	let state = app.state::<AppState>();
+	let mut options = state.options.lock().await;
+	options.synthesis_thread_count = thread_count;
+	state.audio_loader.lock().await.set_synthesis_thread_count(thread_count);
+	Ok(())
+}
+
+/*
+	The models of the enabled voices of the current language, with how long they took to load and about how much memory 
+	they take up.
+*/
+#[tauri::command]
+async fn get_voice_models(state: tauri::State<'_, AppState>) -> Result<Vec<VoiceModelInfo>> {
+	Ok(state.audio_loader.lock().await.voice_model_info())
+}
+
+#[tauri::command]
+async fn set_voice_model_memory_budget(app: tauri::AppHandle, estimated_budget_bytes: u64) -> Result<()> {
+	let state = app.state::<AppState>();
+	let mut options = state.options.lock().await;
+	options.voice_model_estimated_memory_budget_bytes = estimated_budget_bytes;
+	state.audio_loader.lock().await.set_model_memory_budget(estimated_budget_bytes);
+	Ok(())
 }
 
 //----------------------------------------------------------------
@@ -85,96 +263,472 @@ async fn load_sentence_audio(app: tauri::AppHandle, window: tauri::Window, sente
 struct FrontendOptions {
 	current_language: &'static str,
 	saved_languages: Vec<&'static str>,
+	// The translation languages of the current language.
+	translation_languages: Vec<&'static str>,
 	weight_factors: WeightFactors,
 	word_memory_parameters: WordMemoryParameters,
+	sentence_difficulty: SentenceDifficultyParameters,
+	word_selection: WordSelection,
+	scheduling_algorithm: SchedulingAlgorithm,
+	audio_cache_size_limit_bytes: u64,
+	// How much space the cached audio takes up right now.
+	audio_cache_size_bytes: u64,
+	synthesis_thread_count: usize,
+	voice_model_estimated_memory_budget_bytes: u64,
 }
 
 #[tauri::command]
-fn get_options(state: tauri::State<AppState>) -> FrontendOptions {
+fn get_options(state: tauri::State<AppState>) -> Result<FrontendOptions> {
 	let options = state.options.blocking_lock();
+	let translation_languages = state.learning_data.blocking_lock().translation_languages();
 
-	FrontendOptions { 
+	Ok(FrontendOptions { 
 		current_language: LANGUAGES[options.language_index].name, 
 		saved_languages: options.saved_languages.iter().map(|&i| LANGUAGES[i].name).collect(), 
+		translation_languages: translation_languages.into_iter().map(|i| LANGUAGES[i].name).collect(),
 		weight_factors: options.weight_factors, 
 		word_memory_parameters: options.word_memory_parameters,
-	}
+		sentence_difficulty: options.sentence_difficulty,
+		word_selection: options.word_selection,
+		scheduling_algorithm: options.scheduling_algorithm,
+		audio_cache_size_limit_bytes: options.audio_cache_size_limit_bytes,
+		audio_cache_size_bytes: state.audio_cache.lock().unwrap().size_bytes(),
+		synthesis_thread_count: options.synthesis_thread_count,
+		voice_model_estimated_memory_budget_bytes: options.voice_model_estimated_memory_budget_bytes,
+	})
 }
 
 #[tauri::command]
-fn set_weight_factors(state: tauri::State<AppState>, factors: WeightFactors) {
+fn set_weight_factors(state: tauri::State<AppState>, factors: WeightFactors) -> Result<()> {
 	state.options.blocking_lock().weight_factors = factors;
+	Ok(())
 }
 
 #[tauri::command]
-fn set_word_memory_parameters(state: tauri::State<AppState>, parameters: WordMemoryParameters) {
+fn set_word_memory_parameters(state: tauri::State<AppState>, parameters: WordMemoryParameters) -> Result<()> {
 	state.options.blocking_lock().word_memory_parameters = parameters;
+	Ok(())
+}
+
+#[tauri::command]
+fn set_sentence_difficulty_parameters(state: tauri::State<AppState>, parameters: SentenceDifficultyParameters) -> Result<()> {
+	parameters.check()?;
+	state.options.blocking_lock().sentence_difficulty = parameters;
+	Ok(())
+}
+
+#[tauri::command]
+fn set_word_selection(state: tauri::State<AppState>, selection: WordSelection) -> Result<()> {
+	state.options.blocking_lock().word_selection = selection;
+	state.learning_data.blocking_lock().set_word_selection(selection);
+	Ok(())
+}
+
+#[tauri::command]
+fn set_random_seed(state: tauri::State<AppState>, seed: Option<u64>) -> Result<()> {
+	state.options.blocking_lock().random_seed = seed;
+	state.learning_data.blocking_lock().set_random_seed(seed);
+	Ok(())
+}
+
+#[tauri::command]
+fn set_scheduling_algorithm(state: tauri::State<AppState>, algorithm: SchedulingAlgorithm) -> Result<()> {
+	state.options.blocking_lock().scheduling_algorithm = algorithm;
+	Ok(())
 }
 
 //----------------------------------------------------------------
 
 #[tauri::command]
-fn get_language_list() -> Vec<&'static str> {
-	LANGUAGES.iter().map(|language| language.name).collect()
+fn get_language_list() -> Result<Vec<&'static str>> {
+	Ok(LANGUAGES.iter().map(|language| language.name).collect())
 }
 
 #[tauri::command]
-async fn set_current_language(app: tauri::AppHandle, language_name: String) {
+async fn set_current_language(app: tauri::AppHandle, language_name: String) -> Result<()> {
 	let state = app.state::<AppState>();
 
 	let mut options = state.options.lock().await;
 
 	if language_name == LANGUAGES[options.language_index].name {
-		return;
+		return Ok(());
 	}
 	
 	if let Some(&language_index) = options.saved_languages.iter().find(|&&i| LANGUAGES[i].name == language_name) {
+		// The upcoming sentences are of the previous language.
+		stop_audio_prefetch(&state);
 		let mut learning_data = state.learning_data.lock().await;
-		learning_data.save_words_to_file(options.language_index);
+		learning_data.save_words_to_file(options.language_index)?;
+		// Nothing is changed unless the new language could be loaded.
+		*learning_data = LearningData::load_from_file(language_index, &options)?;
 		options.language_index = language_index;
-		*learning_data = LearningData::load_from_file(language_index);
 
-		state.audio_loader.lock().await.set_language(language_index);
+		state.audio_loader.lock().await.set_voices(options.current_voices());
+	}
+	Ok(())
+}
+
+#[derive(Serialize)]
+struct RemovedLanguage {
+	freed_bytes: u64,
+	// The language that is current after the removal.
+	current_language: &'static str,
+}
+
+/*
+	Deletes the saved data of a language together with the voices that no other saved language uses. If the language is the 
+	current one, another saved language is loaded first. The only saved language can't be removed.
+*/
+#[tauri::command]
+async fn remove_language(app: tauri::AppHandle, language_name: String) -> Result<RemovedLanguage> {
+	let state = app.state::<AppState>();
+
+	let mut options = state.options.lock().await;
+	let language_index = source_data::language_index(&language_name)?;
+	let Ok(saved_position) = options.saved_languages.binary_search(&language_index) else {
+		return Err(Error::InvalidRequest(format!("{} has not been added", language_name)));
+	};
+	let Some(&next_language_index) = options.saved_languages.iter().find(|&&i| i != language_index) else {
+		return Err(Error::InvalidRequest("the only language can't be removed".to_owned()));
+	};
+
+	if options.language_index == language_index {
+		stop_audio_prefetch(&state);
+		let mut learning_data = state.learning_data.lock().await;
+		// The progress of the removed language isn't saved since its files are about to be deleted.
+		*learning_data = LearningData::load_from_file(next_language_index, &options)?;
+		options.language_index = next_language_index;
+		state.audio_loader.lock().await.set_voices(options.current_voices());
 	}
+
+	// The options are saved first so that they never refer to a language whose files are gone.
+	options.saved_languages.remove(saved_position);
+	options.enabled_voices.remove(&language_index);
+	options.save()?;
+
+	let enabled_voices = options.voices_in_use_except(language_index);
+	let freed_bytes = LearningData::remove_files(language_index)? 
+		+ source_data::remove_unused_voices(language_index, &options.saved_languages, &enabled_voices)?;
+
+	Ok(RemovedLanguage { freed_bytes, current_language: LANGUAGES[options.language_index].name })
 }
 
 //----------------------------------------------------------------
 
 #[tauri::command]
-async fn download_language_data(app: tauri::AppHandle, window: tauri::Window, info: SourceDataInfo) {
+async fn download_language_data(app: tauri::AppHandle, window: tauri::Window, info: SourceDataInfo) -> Result<()> {
 	let source_data = SourceData::download(info, |status| {
-		window.emit("download_status", &status).unwrap();
-	}).await;
+		// The status is only for showing progress, so the download goes on even if it can't be shown.
+		if let Err(error) = window.emit("download_status", &status) {
+			eprintln!("Could not send download status: {}", error);
+		}
+	}).await?;
+
+	window.emit("download_status", &SourceDataDownloadStatus::Loading)?;
+
+	add_new_language_data(app, source_data).await?;
+
+	window.emit("download_status", &SourceDataDownloadStatus::Finished)?;
+	Ok(())
+}
+
+/*
+	Downloads sentences for the translation languages that are new and removes the sentences of the ones that are left out,
+	without touching the progress on the words of the current language. Reports its progress like download_language_data.
+	Words that end up in no sentence are dropped the next time they are chosen for review, like words that never were in one.
+	Translations saved before their language was recorded aren't listed, so their language is downloaded again if it is 
+	requested and they are removed otherwise.
+*/
+#[tauri::command]
+async fn set_translation_languages(app: tauri::AppHandle, window: tauri::Window, translation_languages: Vec<String>) -> Result<()> {
+	let state = app.state::<AppState>();
+
+	let language_index = state.options.lock().await.language_index;
+	let current_languages = state.learning_data.lock().await.translation_languages();
+
+	let requested_languages = translation_languages.iter()
+		.map(|name| source_data::language_index(name))
+		.collect::<Result<Vec<usize>>>()?;
+	if requested_languages.is_empty() {
+		return Err(Error::InvalidRequest("at least one translation language is needed".to_owned()));
+	}
+
+	let added_languages: Vec<String> = requested_languages.iter()
+		.filter(|i| !current_languages.contains(i))
+		.map(|&i| LANGUAGES[i].name.to_owned())
+		.collect();
+
+	// The download can take a while, so the state is only locked once the lists are here.
+	let sentence_lists = if added_languages.is_empty() {
+		Vec::new()
+	} else {
+		let info = SourceDataInfo { 
+			target_language: LANGUAGES[language_index].name.to_owned(), 
+			translation_languages: added_languages,
+		};
+		SourceData::download_sentence_lists(info, |status| {
+			if let Err(error) = window.emit("download_status", &status) {
+				eprintln!("Could not send download status: {}", error);
+			}
+		}).await?
+	};
+
+	window.emit("download_status", &SourceDataDownloadStatus::Loading)?;
+
+	let options = state.options.lock().await;
+	if options.language_index != language_index {
+		// The current language was changed during the download.
+		return Err(Error::Cancelled);
+	}
+	let mut learning_data = state.learning_data.lock().await;
+	learning_data.set_translation_languages(&requested_languages, &sentence_lists)?;
+	learning_data.save_sentences_to_file(language_index)?;
+
+	window.emit("download_status", &SourceDataDownloadStatus::Finished)?;
+	Ok(())
+}
 
-	window.emit("download_status", &SourceDataDownloadStatus::Loading).unwrap();
+/*
+	Downloads new exports of the sentence lists of the current language and updates the saved sentences to match them, 
+	keeping the progress on words. Reports its progress like download_language_data and returns what changed.
+*/
+#[tauri::command]
+async fn refresh_sentences(app: tauri::AppHandle, window: tauri::Window) -> Result<SentenceChanges> {
+	let state = app.state::<AppState>();
+
+	let language_index = state.options.lock().await.language_index;
+	let translation_languages = state.learning_data.lock().await.translation_languages();
+	if translation_languages.is_empty() {
+		return Err(Error::MissingResource(
+			"the translation languages of the sentences are unknown, add them again in the options first".to_owned()
+		));
+	}
+
+	let info = SourceDataInfo { 
+		target_language: LANGUAGES[language_index].name.to_owned(), 
+		translation_languages: translation_languages.iter().map(|&i| LANGUAGES[i].name.to_owned()).collect(),
+	};
+	let sentence_lists = SourceData::download_sentence_lists(info, |status| {
+		if let Err(error) = window.emit("download_status", &status) {
+			eprintln!("Could not send download status: {}", error);
+		}
+	}).await?;
 
-	add_new_language_data(app, source_data).await;
+	window.emit("download_status", &SourceDataDownloadStatus::Loading)?;
 
-	window.emit("download_status", &SourceDataDownloadStatus::Finished).unwrap();
+	let options = state.options.lock().await;
+	if options.language_index != language_index {
+		return Err(Error::Cancelled);
+	}
+	let mut learning_data = state.learning_data.lock().await;
+	let changes = learning_data.refresh_sentences(&sentence_lists);
+	learning_data.save_sentences_to_file(language_index)?;
+
+	window.emit("download_status", &SourceDataDownloadStatus::Finished)?;
+	Ok(changes)
 }
 
-async fn add_new_language_data(app: tauri::AppHandle, source_data: SourceData) {
+/*
+	Builds the language data from local files instead of downloading it. Reports the same statuses as download_language_data 
+	from the point where the data is loaded.
+*/
+#[tauri::command]
+async fn import_language_data(app: tauri::AppHandle, window: tauri::Window, info: SourceDataImportInfo) -> Result<()> {
+	window.emit("download_status", &SourceDataDownloadStatus::Loading)?;
+
+	let source_data = SourceData::import(info)?;
+	add_new_language_data(app, source_data).await?;
+
+	window.emit("download_status", &SourceDataDownloadStatus::Finished)?;
+	Ok(())
+}
+
+async fn add_new_language_data(app: tauri::AppHandle, source_data: SourceData) -> Result<()> {
 	if let Some(state) = app.try_state::<AppState>() {
+		stop_audio_prefetch(&state);
 		let mut options = state.options.lock().await;
 		let mut learning_data = state.learning_data.lock().await;
 
-		learning_data.save_words_to_file(options.language_index);
+		let mut new_learning_data = LearningData::load_from_source_data(&source_data, &options)?;
+		// Save sentences immediately.
+		// Sentences are saved only when necessary while words and their weights are saved every time the app closes.
+		new_learning_data.save_sentences_to_file(source_data.language_index)?;
+
+		learning_data.save_words_to_file(options.language_index)?;
+		*learning_data = new_learning_data;
 		options.language_index = source_data.language_index;
 		
 		if let Err(i) = options.saved_languages.binary_search(&source_data.language_index) {
 			options.saved_languages.insert(i, source_data.language_index);
 		}
+		options.enabled_voices.entry(source_data.language_index)
+			.or_insert_with(|| voices::initial_voices(source_data.language_index));
 		
-		*learning_data = LearningData::load_from_source_data(&source_data, &options);
-		// Save sentences immediately.
-		// Sentences are saved only when necessary while words and their weights are saved every time the app closes.
-		learning_data.save_sentences_to_file(options.language_index);
-
-		state.audio_loader.lock().await.set_language(options.language_index);
+		state.audio_loader.lock().await.set_voices(options.current_voices());
 	}
 	else {
-		app.manage(AppState::new(app.clone(), &source_data));
+		app.manage(AppState::new(app.clone(), &source_data)?);
 	}
+	Ok(())
+}
+
+//----------------------------------------------------------------
+
+#[tauri::command]
+fn list_backups() -> Result<Vec<BackupInfo>> {
+	Ok(backup::list_backups()?)
+}
+
+/*
+	Also works when the save data could not be loaded at startup, in which case the front-end calls retry_loading afterwards.
+	If the restored progress can't be loaded, the progress from before is put back.
+*/
+#[tauri::command]
+async fn restore_backup(app: tauri::AppHandle, name: String) -> Result<()> {
+	let Some(state) = app.try_state::<AppState>() else {
+		backup::restore_backup(&name)?;
+		return Ok(());
+	};
+	stop_audio_prefetch(&state);
+
+	let mut options = state.options.lock().await;
+	let mut learning_data = state.learning_data.lock().await;
+
+	// Back up the current progress first so that restoring can be undone.
+	options.save()?;
+	learning_data.save_words_to_file(options.language_index)?;
+	let previous_backup = backup::create_backup(&options)?;
+
+	let restored_options = backup::restore_backup(&name)?;
+	let restored_data = match LearningData::load_from_file(restored_options.language_index, &restored_options) {
+		Ok(restored_data) => restored_data,
+		Err(error) => {
+			backup::restore_backup(&previous_backup)?;
+			return Err(error.context(format!("restoring backup {}", name)));
+		}
+	};
+	*learning_data = restored_data;
+	*options = restored_options;
+
+	state.audio_loader.lock().await.set_voices(options.current_voices());
+	Ok(())
+}
+
+//----------------------------------------------------------------
+
+/*
+	Lists the voices of the current language with their quality and size.
+*/
+#[tauri::command]
+async fn get_voices(app: tauri::AppHandle) -> Result<Vec<VoiceInfo>> {
+	let (language_index, enabled_voices) = {
+		let options = app.state::<AppState>().options.lock().await;
+		(options.language_index, options.current_voices().to_vec())
+	};
+	voices::list_voices(language_index, &enabled_voices).await
+}
+
+#[derive(Clone, Serialize)]
+struct VoiceDownloadProgress<'a> {
+	voice: &'a str,
+	progress: f32,
+}
+
+/*
+	Downloads a voice of the current language and enables it. The progress is sent as voice_download_progress events.
+*/
+#[tauri::command]
+async fn download_voice(app: tauri::AppHandle, window: tauri::Window, voice: String) -> Result<()> {
+	// The language is fixed before the download so that the voice ends up with the right one even if it is switched meanwhile.
+	let language_index = app.state::<AppState>().options.lock().await.language_index;
+
+	voices::download_voice(language_index, &voice, |progress| {
+		if let Err(error) = window.emit("voice_download_progress", VoiceDownloadProgress { voice: &voice, progress }) {
+			eprintln!("Could not send voice download progress: {}", error);
+		}
+	}).await?;
+
+	enable_voice(&app, language_index, &voice, true).await
+}
+
+/*
+	Disables a voice of the current language and deletes its files unless another language has it enabled. Returns the 
+	number of bytes freed.
+*/
+#[tauri::command]
+async fn delete_voice(app: tauri::AppHandle, voice: String) -> Result<u64> {
+	let state = app.state::<AppState>();
+	let language_index = state.options.lock().await.language_index;
+
+	enable_voice(&app, language_index, &voice, false).await?;
+
+	let options = state.options.lock().await;
+	voices::delete_voice(language_index, &voice, &options.voices_in_use_except(language_index))
+}
+
+#[tauri::command]
+async fn set_voice_enabled(app: tauri::AppHandle, voice: String, enabled: bool) -> Result<()> {
+	let language_index = app.state::<AppState>().options.lock().await.language_index;
+	enable_voice(&app, language_index, &voice, enabled).await
+}
+
+async fn enable_voice(app: &tauri::AppHandle, language_index: usize, voice: &str, enabled: bool) -> Result<()> {
+	voices::check_voice(language_index, voice)?;
+	if enabled && !voices::is_installed(voice) {
+		return Err(Error::InvalidRequest(format!("{} has not been downloaded", voice)));
+	}
+
+	let state = app.state::<AppState>();
+	let mut options = state.options.lock().await;
+
+	let enabled_voices = options.enabled_voices.entry(language_index).or_default();
+	enabled_voices.retain(|enabled_voice| enabled_voice != voice);
+	if enabled {
+		enabled_voices.push(voice.to_owned());
+		// Kept with the preferred voices first so that the voices are always tried in the same order.
+		enabled_voices.sort_by_key(|enabled_voice| voices::preference(language_index, enabled_voice));
+	}
+	options.save()?;
+
+	if options.language_index == language_index {
+		state.audio_loader.lock().await.set_voices(options.current_voices());
+	}
+	Ok(())
+}
+
+//----------------------------------------------------------------
+
+/*
+	Checks the save files, voices and backups of the saved languages. Damaged voices are downloaded again and a damaged 
+	sentence index is rebuilt, anything else is only reported.
+*/
+#[tauri::command]
+async fn verify_installed_data(app: tauri::AppHandle) -> Result<DataVerification> {
+	let state = app.state::<AppState>();
+	let saved_languages = {
+		let options = state.options.lock().await;
+		// The progress is saved first so that the files are complete.
+		save_progress(&options, &mut *state.learning_data.lock().await)?;
+		options.saved_languages.clone()
+	};
+
+	let mut verification = DataVerification::default();
+	verification.add(OPTIONS_SAVE_FILE, Options::load().err().map(|error| error.to_string()), false);
+	for &language_index in &saved_languages {
+		LearningData::verify_files(language_index, &mut verification);
+	}
+
+	// Languages with the same code share their voices, which only need to be checked once.
+	let mut checked_codes = Vec::new();
+	for &language_index in &saved_languages {
+		if !checked_codes.contains(&LANGUAGES[language_index].id_2) {
+			checked_codes.push(LANGUAGES[language_index].id_2);
+			voices::verify_voices(language_index, &mut verification).await?;
+		}
+	}
+
+	backup::verify_backups(&mut verification)?;
+	Ok(verification)
 }
 
 //----------------------------------------------------------------
@@ -186,16 +740,16 @@ struct WordData {
 }
 
 #[tauri::command]
-fn get_word_data(state: tauri::State<AppState>) -> WordData {
+fn get_word_data(state: tauri::State<AppState>) -> Result<WordData> {
 	let learning_data = state.learning_data.blocking_lock();
 	let words = &learning_data.words().words;
 
-	WordData {
+	Ok(WordData {
 		words: words.clone(),
 		max_weight: words.iter()
 			.max_by(|&a, &b| a.weight.total_cmp(&b.weight))
 			.map_or(1., |word| word.weight)
-	}
+	})
 }
 
 //----------------------------------------------------------------
@@ -204,43 +758,106 @@ pub fn run() {
 	tauri::Builder::default()
 		.setup(|app| { start_app(app); Ok(()) })
 		.invoke_handler(tauri::generate_handler![
+			cancel_sentence_audio,
+			clear_audio_cache,
+			delete_voice,
 			download_language_data,
+			download_voice,
 			finish_task,
 			get_language_list,
 			get_options,
+			get_startup_error,
+			get_voice_models,
+			get_voices,
 			get_word_data,
+			import_language_data,
+			list_backups,
 			load_sentence_audio,
 			next_task, 
+			refresh_sentences,
+			remove_language,
+			restore_backup,
+			retry_loading,
+			set_audio_cache_size_limit,
 			set_current_language,
+			set_random_seed,
+			set_scheduling_algorithm,
+			set_sentence_difficulty_parameters,
+			set_synthesis_thread_count,
+			set_translation_languages,
+			set_voice_enabled,
+			set_voice_model_memory_budget,
 			set_weight_factors,
 			set_word_memory_parameters,
+			set_word_selection,
+			verify_installed_data,
 		])
 		.on_window_event(handle_window_event)
 		.run(tauri::generate_context!())
 		.unwrap();
 }
 
+/*
+	Returns None if no language has been added yet.
+*/
+fn load_app_state(app: tauri::AppHandle) -> Result<Option<AppState>> {
+	let Some(options) = Options::load()? else {
+		return Ok(None);
+	};
+	AppState::load(app, options).map(Some)
+}
+
+// The error from loading the save data at startup, which is shown on the load error page.
+struct StartupError(std::sync::Mutex<Option<Error>>);
+
 fn start_app(app: &tauri::App) {
-	if let Some(options) = Options::load() {
-		app.manage(AppState::load(app.app_handle(), options));
-		tauri::WindowBuilder::new(app, "main", tauri::WindowUrl::App("learn".into()))
-			.center()
-			.inner_size(700., 600.)
-			.title("Gurksaft").build().unwrap();
+	let (url, height) = match load_app_state(app.app_handle()) {
+		Ok(Some(state)) => {
+			app.manage(state);
+			("learn", 600.)
+		}
+		Ok(None) => ("add-language", 450.),
+		Err(error) => {
+			eprintln!("Could not load save data: {}", error);
+			app.manage(StartupError(std::sync::Mutex::new(Some(error))));
+			("load-error", 450.)
+		}
+	};
+
+	tauri::WindowBuilder::new(app, "main", tauri::WindowUrl::App(url.into()))
+		.center()
+		.inner_size(700., height)
+		.title("Gurksaft")
+		.build().unwrap();
+}
+
+#[tauri::command]
+fn get_startup_error(app: tauri::AppHandle) -> Result<Option<Error>> {
+	Ok(app.try_state::<StartupError>().and_then(|error| error.0.lock().unwrap().clone()))
+}
+
+#[tauri::command]
+fn retry_loading(app: tauri::AppHandle) -> Result<()> {
+	if app.try_state::<AppState>().is_some() {
+		return Ok(());
 	}
-	else {
-		tauri::WindowBuilder::new(app, "main", tauri::WindowUrl::App("add-language".into()))
-			.center()
-			.inner_size(700., 450.)
-			.title("Gurksaft")
-			.build().unwrap();
+	let state = load_app_state(app.clone())?.ok_or_else(|| Error::MissingResource("saved options".to_owned()))?;
+	app.manage(state);
+
+	if let Some(error) = app.try_state::<StartupError>() {
+		*error.0.lock().unwrap() = None;
 	}
+	Ok(())
 }
 
 fn handle_window_event(event: tauri::GlobalWindowEvent) {
 	if let tauri::WindowEvent::Destroyed = event.event() {
 		if let Some(app) = event.window().try_state::<AppState>() {
-			app.save();
+			app.audio_requests.cancel_window(event.window().label());
+			stop_audio_prefetch(&app);
+			if let Err(error) = app.save() {
+				eprintln!("Could not save: {}", error);
+			}
 		}
 	}
 }
@@ -0,0 +1,331 @@
+use std::{
+	collections::HashMap,
+	fs, io,
+	path::{Path, PathBuf},
+};
+
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	learning_data::{SentenceId, SAVE_DIRECTORY},
+	save_file::{self, SaveFormat},
+	sentence_audio::AudioId,
+};
+
+//----------------------------------------------------------------
+
+const AUDIO_CACHE_DIRECTORY_NAME: &str = "audio_cache";
+const INDEX_FILE_NAME: &str = "index";
+
+pub const DEFAULT_SIZE_LIMIT_BYTES: u64 = 500_000_000;
+// Saving the index writes all of it, so it is only saved after this many new clips or when the app closes.
+const INSERTS_PER_SAVE: u32 = 20;
+
+/*
+	What a cached audio clip is. Generated speech also depends on the sentence text, which can change when the sentences are
+	refreshed, so the text is part of the key and changed sentences are simply generated again.
+*/
+pub enum AudioKey<'a> {
+	Recording { sentence_id: SentenceId, audio_id: AudioId },
+	Speech { sentence_id: SentenceId, voice: &'a str, speaker: Option<&'a str>, sentence: &'a str },
+}
+
+impl AudioKey<'_> {
+	/*
+		Voice and speaker names can contain characters that aren't allowed in file names, so the file is named by a digest
+		of the key instead.
+	*/
+	fn file_name(&self) -> String {
+		let key = match self {
+			AudioKey::Recording { sentence_id, audio_id } => format!("recording/{}/{}", sentence_id, audio_id),
+			AudioKey::Speech { sentence_id, voice, speaker, sentence } => {
+				format!("speech/{}/{}/{}/{}", sentence_id, voice, speaker.unwrap_or(""), sentence)
+			}
+		};
+		Md5::digest(key.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+	}
+}
+
+//----------------------------------------------------------------
+
+#[derive(Default, Deserialize, Serialize)]
+struct CacheIndex {
+	entries: HashMap<String, CacheEntry>,
+	// Increases every time a clip is used, which orders the entries from least to most recently used.
+	use_counter: u64,
+}
+
+#[derive(Deserialize, Serialize)]
+struct CacheEntry {
+	size_bytes: u64,
+	last_used: u64,
+}
+
+impl SaveFormat for CacheIndex {
+	const VERSION: u32 = 1;
+}
+
+/*
+	Downloaded recordings and generated speech kept in save_data so that sentences that come back play right away, even
+	offline. When the clips take more space than the size limit, the least recently used ones are removed.
+*/
+pub struct AudioCache {
+	directory: PathBuf,
+	index: CacheIndex,
+	size_limit_bytes: u64,
+	// Whether the index has changed since it was saved. Using a clip only changes the order, which is saved with the next
+	// change or when the app closes rather than every time.
+	index_changed: bool,
+	unsaved_insert_count: u32,
+}
+
+impl AudioCache {
+	pub fn open(size_limit_bytes: u64) -> Self {
+		Self::open_in(Path::new(SAVE_DIRECTORY).join(AUDIO_CACHE_DIRECTORY_NAME), size_limit_bytes)
+	}
+
+	/*
+		An index that can't be read means that nothing is known about the clips, so they are removed and the cache starts
+		out empty. Clips that were added after the index was last saved are removed as well. Losing the cache is never worth
+		failing over.
+	*/
+	fn open_in(directory: PathBuf, size_limit_bytes: u64) -> Self {
+		let index = match save_file::load::<CacheIndex>(directory.join(INDEX_FILE_NAME)) {
+			Ok(index) => index,
+			Err(error) => {
+				if !error.is_missing_file() {
+					eprintln!("Could not load the audio cache index, clearing the cache: {}", error);
+				}
+				if let Err(error) = remove_clips(&directory) {
+					eprintln!("Could not clear the audio cache: {}", error);
+				}
+				CacheIndex::default()
+			}
+		};
+		if let Err(error) = remove_untracked_clips(&directory, &index) {
+			eprintln!("Could not remove audio that isn't in the cache index: {}", error);
+		}
+		let mut cache = Self { directory, index, size_limit_bytes, index_changed: false, unsaved_insert_count: 0 };
+		if let Err(error) = cache.evict() {
+			eprintln!("Could not shrink the audio cache: {}", error);
+		}
+		cache
+	}
+
+	pub fn size_bytes(&self) -> u64 {
+		self.index.entries.values().map(|entry| entry.size_bytes).sum()
+	}
+
+	/*
+		Returns the clip if it is cached. A clip whose file has gone missing is forgotten.
+	*/
+	pub fn get(&mut self, key: &AudioKey) -> Option<Vec<u8>> {
+		let file_name = key.file_name();
+		self.index.entries.get(&file_name)?;
+
+		match fs::read(self.directory.join(&file_name)) {
+			Ok(data) => {
+				self.index.use_counter += 1;
+				if let Some(entry) = self.index.entries.get_mut(&file_name) {
+					entry.last_used = self.index.use_counter;
+				}
+				self.index_changed = true;
+				Some(data)
+			}
+			Err(error) => {
+				eprintln!("Could not read cached audio {}: {}", file_name, error);
+				self.index.entries.remove(&file_name);
+				self.index_changed = true;
+				None
+			}
+		}
+	}
+
+	pub fn insert(&mut self, key: &AudioKey, data: &[u8]) -> io::Result<()> {
+		if data.len() as u64 > self.size_limit_bytes {
+			return Ok(());
+		}
+
+		let file_name = key.file_name();
+		save_file::write_atomic(self.directory.join(&file_name), data)?;
+		self.index.use_counter += 1;
+		self.index.entries.insert(file_name, CacheEntry { size_bytes: data.len() as u64, last_used: self.index.use_counter });
+		self.index_changed = true;
+		self.evict()?;
+
+		self.unsaved_insert_count += 1;
+		if self.unsaved_insert_count >= INSERTS_PER_SAVE {
+			self.save()?;
+		}
+		Ok(())
+	}
+
+	pub fn set_size_limit(&mut self, size_limit_bytes: u64) -> io::Result<()> {
+		self.size_limit_bytes = size_limit_bytes;
+		self.evict()?;
+		self.save()
+	}
+
+	/*
+		Removes all clips and returns the number of bytes freed.
+	*/
+	pub fn clear(&mut self) -> io::Result<u64> {
+		let freed_bytes = self.size_bytes();
+		remove_clips(&self.directory)?;
+		self.index = CacheIndex::default();
+		self.index_changed = true;
+		self.save()?;
+		Ok(freed_bytes)
+	}
+
+	pub fn save(&mut self) -> io::Result<()> {
+		if self.index_changed {
+			save_file::save(self.directory.join(INDEX_FILE_NAME), &self.index)?;
+			self.index_changed = false;
+			self.unsaved_insert_count = 0;
+		}
+		Ok(())
+	}
+
+	fn evict(&mut self) -> io::Result<()> {
+		let mut size_bytes = self.size_bytes();
+		if size_bytes <= self.size_limit_bytes {
+			return Ok(());
+		}
+
+		let mut entries: Vec<(String, u64, u64)> = self.index.entries.iter()
+			.map(|(file_name, entry)| (file_name.clone(), entry.last_used, entry.size_bytes))
+			.collect();
+		entries.sort_by_key(|&(_, last_used, _)| last_used);
+
+		for (file_name, _, entry_size_bytes) in entries {
+			if size_bytes <= self.size_limit_bytes {
+				break;
+			}
+			save_file::remove(self.directory.join(&file_name))?;
+			self.index.entries.remove(&file_name);
+			size_bytes -= entry_size_bytes;
+		}
+		self.index_changed = true;
+		Ok(())
+	}
+}
+
+/*
+	Removes the files in the cache directory, including the index.
+*/
+fn remove_clips(directory: &Path) -> io::Result<()> {
+	let entries = match fs::read_dir(directory) {
+		Ok(entries) => entries,
+		Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+		Err(error) => return Err(error),
+	};
+
+	for entry in entries {
+		let path = entry?.path();
+		if path.is_file() {
+			save_file::remove(&path)?;
+		}
+	}
+	Ok(())
+}
+
+fn remove_untracked_clips(directory: &Path, index: &CacheIndex) -> io::Result<()> {
+	let entries = match fs::read_dir(directory) {
+		Ok(entries) => entries,
+		Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+		Err(error) => return Err(error),
+	};
+
+	for entry in entries {
+		let entry = entry?;
+		let file_name = entry.file_name().to_string_lossy().into_owned();
+		if file_name != INDEX_FILE_NAME && !index.entries.contains_key(&file_name) && entry.path().is_file() {
+			save_file::remove(entry.path())?;
+		}
+	}
+	Ok(())
+}
+
+//----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn test_directory(name: &str) -> PathBuf {
+		let directory = std::env::temp_dir().join(format!("gurksaft_audio_cache_test_{}_{}", name, std::process::id()));
+		let _ = fs::remove_dir_all(&directory);
+		directory
+	}
+
+	fn recording(audio_id: AudioId) -> AudioKey<'static> {
+		AudioKey::Recording { sentence_id: 1, audio_id }
+	}
+
+	#[test]
+	fn least_recently_used_clips_are_evicted() {
+		let directory = test_directory("eviction");
+		let mut cache = AudioCache::open_in(directory.clone(), 10);
+
+		cache.insert(&recording(1), b"aaaa").unwrap();
+		cache.insert(&recording(2), b"bbbb").unwrap();
+		assert_eq!(cache.get(&recording(1)).unwrap(), b"aaaa");
+		cache.insert(&recording(3), b"cccc").unwrap();
+
+		assert_eq!(cache.size_bytes(), 8);
+		assert!(cache.get(&recording(2)).is_none());
+		assert!(cache.get(&recording(1)).is_some());
+
+		// Bigger than the whole cache, so it isn't kept.
+		cache.insert(&recording(4), b"dddddddddddd").unwrap();
+		assert!(cache.get(&recording(4)).is_none());
+
+		cache.set_size_limit(4).unwrap();
+		assert_eq!(cache.size_bytes(), 4);
+		assert!(cache.get(&recording(1)).is_some());
+
+		fs::remove_dir_all(&directory).unwrap();
+	}
+
+	#[test]
+	fn clips_are_kept_between_sessions() {
+		let directory = test_directory("sessions");
+		let speech = AudioKey::Speech { sentence_id: 5, voice: "fi_FI/harri/medium", speaker: None, sentence: "Hei." };
+		let changed_speech = AudioKey::Speech { sentence_id: 5, voice: "fi_FI/harri/medium", speaker: None, sentence: "Moi." };
+
+		let mut cache = AudioCache::open_in(directory.clone(), 100);
+		cache.insert(&speech, b"wave").unwrap();
+		cache.save().unwrap();
+		drop(cache);
+
+		let mut cache = AudioCache::open_in(directory.clone(), 100);
+		assert_eq!(cache.get(&speech).unwrap(), b"wave");
+		assert!(cache.get(&changed_speech).is_none());
+
+		assert_eq!(cache.clear().unwrap(), 4);
+		assert!(AudioCache::open_in(directory.clone(), 100).get(&speech).is_none());
+		fs::remove_dir_all(&directory).unwrap();
+	}
+
+	#[test]
+	fn index_is_saved_in_batches() {
+		let directory = test_directory("batches");
+		let mut cache = AudioCache::open_in(directory.clone(), 1000);
+		for audio_id in 0..INSERTS_PER_SAVE {
+			cache.insert(&recording(audio_id as AudioId), b"clip").unwrap();
+		}
+		// Not saved, as if the app had crashed.
+		cache.insert(&recording(100), b"late").unwrap();
+		drop(cache);
+
+		let mut cache = AudioCache::open_in(directory.clone(), 1000);
+		assert_eq!(cache.size_bytes(), 4 * INSERTS_PER_SAVE as u64);
+		assert!(cache.get(&recording(0)).is_some());
+		assert!(cache.get(&recording(100)).is_none());
+		// The clip that wasn't in the index doesn't take up space without being counted.
+		assert_eq!(fs::read_dir(&directory).unwrap().count(), INSERTS_PER_SAVE as usize + 1);
+		fs::remove_dir_all(&directory).unwrap();
+	}
+}
@@ -0,0 +1,150 @@
+use std::{
+	collections::HashMap,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex,
+	},
+};
+
+//----------------------------------------------------------------
+
+/*
+	Chosen by the front-end for every call to load_sentence_audio, counting up in each window. The audio clips are sent
+	together with the ID so that the front-end can drop clips of requests it has moved on from.
+*/
+pub type AudioRequestId = u32;
+
+/*
+	Shared between whoever wants to stop some work and the work itself, which checks it between steps.
+*/
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+	pub fn cancel(&self) {
+		self.0.store(true, Ordering::SeqCst);
+	}
+
+	pub fn is_cancelled(&self) -> bool {
+		self.0.load(Ordering::SeqCst)
+	}
+}
+
+//----------------------------------------------------------------
+
+/*
+	The cancellation tokens of the audio requests by window. A window only shows one sentence at a time, so starting a
+	request cancels the earlier ones of the same window and only the latest request of each window is kept.
+*/
+#[derive(Default)]
+pub struct AudioRequests(Mutex<HashMap<String, WindowRequests>>);
+
+#[derive(Default)]
+struct WindowRequests {
+	// The latest request that has started and not finished yet.
+	running: Option<(AudioRequestId, CancellationToken)>,
+	// A request that was cancelled before it started. The IDs start over when the window is reloaded, so this is forgotten
+	// as soon as any other request starts, in case it was a cancel that arrived after its request had finished.
+	cancelled_before_start: Option<AudioRequestId>,
+}
+
+impl AudioRequests {
+	/*
+		Returns the token of a request that is starting. A request can be cancelled before it starts since commands don't
+		necessarily run in the order they were sent, and a request that is older than the running one is cancelled right away.
+	*/
+	pub fn start(&self, window_label: &str, request_id: AudioRequestId) -> CancellationToken {
+		let mut requests = self.0.lock().unwrap();
+		let window = requests.entry(window_label.to_owned()).or_default();
+		let token = CancellationToken::default();
+		match &window.running {
+			Some((running_id, running_token)) if *running_id == request_id => return running_token.clone(),
+			Some((running_id, _)) if *running_id > request_id => {
+				token.cancel();
+				return token;
+			}
+			_ => {}
+		}
+
+		if window.cancelled_before_start.take() == Some(request_id) {
+			token.cancel();
+		}
+		if let Some((_, earlier_token)) = window.running.replace((request_id, token.clone())) {
+			earlier_token.cancel();
+		}
+		token
+	}
+
+	pub fn cancel(&self, window_label: &str, request_id: AudioRequestId) {
+		let mut requests = self.0.lock().unwrap();
+		let window = requests.entry(window_label.to_owned()).or_default();
+		match &window.running {
+			Some((running_id, token)) if *running_id == request_id => token.cancel(),
+			// Already cancelled by the newer request.
+			Some((running_id, _)) if *running_id > request_id => {}
+			_ => window.cancelled_before_start = Some(request_id),
+		}
+	}
+
+	/*
+		Cancels the request of a window that is closing.
+	*/
+	pub fn cancel_window(&self, window_label: &str) {
+		if let Some((_, token)) = self.0.lock().unwrap().remove(window_label).and_then(|window| window.running) {
+			token.cancel();
+		}
+	}
+
+	pub fn finish(&self, window_label: &str, request_id: AudioRequestId) {
+		let mut requests = self.0.lock().unwrap();
+		let Some(window) = requests.get_mut(window_label) else { return };
+		if window.running.as_ref().is_some_and(|(running_id, _)| *running_id == request_id) {
+			window.running = None;
+		}
+	}
+}
+
+//----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn requests_are_cancelled_individually() {
+		let requests = AudioRequests::default();
+
+		let first = requests.start("main", 1);
+		let other_window = requests.start("other", 1);
+		let second = requests.start("main", 2);
+		assert!(first.is_cancelled());
+		assert!(!second.is_cancelled());
+		assert!(!other_window.is_cancelled());
+
+		// A cancel for a request that has finished doesn't affect the next one.
+		requests.finish("main", 2);
+		requests.cancel("main", 2);
+		assert!(!requests.start("main", 3).is_cancelled());
+
+		// Neither does a cancel for an earlier request.
+		requests.cancel("main", 2);
+		assert!(!requests.start("main", 3).is_cancelled());
+		requests.finish("main", 3);
+
+		// A cancel that arrives before its request starts still cancels it.
+		requests.cancel("main", 4);
+		assert!(requests.start("main", 4).is_cancelled());
+		assert!(requests.start("main", 3).is_cancelled());
+		requests.finish("main", 4);
+
+		// The IDs start over when the window is reloaded, and a late cancel from before doesn't cancel the new requests.
+		requests.cancel("main", 4);
+		for request_id in 1..=5 {
+			assert!(!requests.start("main", request_id).is_cancelled());
+			requests.finish("main", request_id);
+		}
+
+		requests.cancel_window("other");
+		assert!(other_window.is_cancelled());
+	}
+}
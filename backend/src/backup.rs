@@ -0,0 +1,368 @@
+use std::{
+	fs, io,
+	path::{Path, PathBuf},
+};
+
+use chrono::prelude::*;
+
+use serde::Serialize;
+
+use crate::{
+	error::{Error, Result},
+	integrity::DataVerification,
+	learning_data::{LearningData, LearningWords, SAVE_DIRECTORY},
+	options::{Options, OPTIONS_SAVE_FILE},
+	save_file,
+	source_data::LANGUAGES,
+};
+
+//----------------------------------------------------------------
+
+/*
+	Backups are copies of the files that contain the user's progress, which are the options and the word files of all
+	saved languages. Sentences are not included since they can be downloaded again. Each backup is a directory in
+	save_data/backups named after the time it was made.
+*/
+const BACKUP_DIRECTORY_NAME: &str = "backups";
+const BACKUP_NAME_FORMAT: &str = "%Y-%m-%d_%H-%M-%S";
+// Backups are made at most this often when saving.
+const BACKUP_INTERVAL_HOURS: i64 = 24;
+const MAX_BACKUP_COUNT: usize = 10;
+
+//----------------------------------------------------------------
+
+#[derive(Debug, Serialize)]
+pub struct BackupInfo {
+	pub name: String,
+	pub time: DateTime<Utc>,
+	// The languages whose words are included in the backup.
+	pub languages: Vec<&'static str>,
+}
+
+fn backup_directory() -> PathBuf {
+	Path::new(SAVE_DIRECTORY).join(BACKUP_DIRECTORY_NAME)
+}
+
+fn progress_files(options: &Options) -> Vec<PathBuf> {
+	std::iter::once(PathBuf::from(OPTIONS_SAVE_FILE))
+		.chain(options.saved_languages.iter().map(|&i| PathBuf::from(LearningData::words_file_name(i))))
+		.collect()
+}
+
+fn parse_backup_time(name: &str) -> Option<DateTime<Utc>> {
+	NaiveDateTime::parse_from_str(name, BACKUP_NAME_FORMAT).ok().map(|time| time.and_utc())
+}
+
+//----------------------------------------------------------------
+
+/*
+	Returns the name of the backup.
+*/
+pub fn create_backup(options: &Options) -> io::Result<String> {
+	create_backup_in(&backup_directory(), &progress_files(options), Utc::now())
+}
+
+/*
+	Creates a backup unless one was made recently. Called whenever the progress is saved.
+*/
+pub fn create_backup_if_due(options: &Options) -> io::Result<()> {
+	let now = Utc::now();
+	let has_recent_backup = list_backups_in(&backup_directory())?.first()
+		.is_some_and(|backup| now - backup.time < chrono::Duration::hours(BACKUP_INTERVAL_HOURS));
+	if !has_recent_backup {
+		create_backup_in(&backup_directory(), &progress_files(options), now)?;
+	}
+	Ok(())
+}
+
+/*
+	Returns the backups with the newest one first.
+*/
+pub fn list_backups() -> io::Result<Vec<BackupInfo>> {
+	list_backups_in(&backup_directory())
+}
+
+/*
+	Copies the files in the backup back into the save directory and returns the restored options. Nothing is copied unless
+	all files in the backup can be read. Languages that have been removed since the backup was made are left out, since 
+	their sentences are gone. The caller is responsible for loading the words again.
+*/
+pub fn restore_backup(name: &str) -> Result<Options> {
+	let saved_languages: Vec<usize> = (0..LANGUAGES.len()).filter(|&i| LearningData::has_saved_sentences(i)).collect();
+	restore_backup_in(&backup_directory(), name, Path::new(SAVE_DIRECTORY), &saved_languages)
+}
+
+/*
+	Checks that the files in every backup can be read, so that a damaged backup is found before it is needed.
+*/
+pub fn verify_backups(verification: &mut DataVerification) -> io::Result<()> {
+	verify_backups_in(&backup_directory(), verification)
+}
+
+//----------------------------------------------------------------
+
+fn create_backup_in(directory: &Path, files: &[PathBuf], time: DateTime<Utc>) -> io::Result<String> {
+	let name = time.format(BACKUP_NAME_FORMAT).to_string();
+	let backup_path = directory.join(&name);
+	if backup_path.exists() {
+		return Ok(name);
+	}
+
+	// The files are copied to a temporary directory first so that an interrupted backup never looks like a complete one.
+	let temporary_path = directory.join(name.clone() + ".tmp");
+	if temporary_path.exists() {
+		fs::remove_dir_all(&temporary_path)?;
+	}
+	fs::create_dir_all(&temporary_path)?;
+
+	for file in files {
+		let Some(file_name) = file.file_name() else { continue };
+		match fs::copy(file, temporary_path.join(file_name)) {
+			// Words that have never been saved have nothing to back up.
+			Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+			result => { result?; }
+		}
+	}
+	fs::rename(&temporary_path, &backup_path)?;
+
+	remove_old_backups(directory)?;
+	Ok(name)
+}
+
+fn remove_old_backups(directory: &Path) -> io::Result<()> {
+	for backup in list_backups_in(directory)?.iter().skip(MAX_BACKUP_COUNT) {
+		fs::remove_dir_all(directory.join(&backup.name))?;
+	}
+	Ok(())
+}
+
+fn list_backups_in(directory: &Path) -> io::Result<Vec<BackupInfo>> {
+	let entries = match fs::read_dir(directory) {
+		Ok(entries) => entries,
+		Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+		Err(error) => return Err(error),
+	};
+
+	let mut backups = Vec::new();
+	for entry in entries {
+		let entry = entry?;
+		let name = entry.file_name().to_string_lossy().into_owned();
+		// Anything that isn't named like a backup, such as an interrupted one, is skipped.
+		let Some(time) = parse_backup_time(&name) else { continue };
+
+		let languages = fs::read_dir(entry.path())?
+			.filter_map(|entry| words_file_language(&entry.ok()?.path()))
+			.map(|i| LANGUAGES[i].name)
+			.collect();
+
+		backups.push(BackupInfo { name, time, languages });
+	}
+
+	backups.sort_unstable_by_key(|backup| std::cmp::Reverse(backup.time));
+	Ok(backups)
+}
+
+fn restore_backup_in(directory: &Path, name: &str, save_directory: &Path, saved_languages: &[usize]) -> Result<Options> {
+	// The name comes from the front-end, so make sure it can't point anywhere else than to a backup.
+	if parse_backup_time(name).is_none() {
+		return Err(Error::InvalidRequest(format!("invalid backup name {}", name)));
+	}
+
+	// Everything is read and checked before anything is overwritten, so that a damaged backup leaves the progress as it is.
+	let mut files = Vec::new();
+	let mut options = None;
+	for entry in fs::read_dir(directory.join(name))? {
+		let path = entry?.path();
+		let data = fs::read(&path)?;
+		if is_options_file(&path) {
+			options = Some(save_file::deserialize::<Options>(&data).map_err(|error| Error::from(error).context(path.display()))?);
+		} else if words_file_language(&path).is_some_and(|i| saved_languages.contains(&i)) {
+			save_file::deserialize::<LearningWords>(&data).map_err(|error| Error::from(error).context(path.display()))?;
+			files.push((path, data));
+		}
+	}
+	let mut options = options.ok_or_else(|| Error::MissingResource(format!("options in backup {}", name)))?;
+
+	options.saved_languages.retain(|i| saved_languages.contains(i));
+	if !options.saved_languages.contains(&options.language_index) {
+		options.language_index = *options.saved_languages.first()
+			.ok_or_else(|| Error::InvalidRequest(format!("none of the languages in backup {} are saved anymore", name)))?;
+	}
+
+	for (path, data) in files {
+		let Some(file_name) = path.file_name() else { continue };
+		save_file::write_atomic(save_directory.join(file_name), &data)?;
+	}
+	save_file::save(save_directory.join(Path::new(OPTIONS_SAVE_FILE).file_name().unwrap_or_default()), &options)?;
+	Ok(options)
+}
+
+fn is_options_file(path: &Path) -> bool {
+	path.file_name() == Path::new(OPTIONS_SAVE_FILE).file_name()
+}
+
+fn words_file_language(path: &Path) -> Option<usize> {
+	(0..LANGUAGES.len()).find(|&i| path.file_name() == Path::new(&LearningData::words_file_name(i)).file_name())
+}
+
+fn verify_backups_in(directory: &Path, verification: &mut DataVerification) -> io::Result<()> {
+	for backup in list_backups_in(directory)? {
+		for entry in fs::read_dir(directory.join(&backup.name))? {
+			let path = entry?.path();
+			// Everything but the options is a word file.
+			let result = if is_options_file(&path) {
+				save_file::load::<Options>(&path).map(drop)
+			} else {
+				save_file::load::<LearningWords>(&path).map(drop)
+			};
+			let problem = result.err().map(|error| format!("{}. The backup can't be restored.", error));
+			verification.add(&path, problem, false);
+		}
+	}
+	Ok(())
+}
+
+//----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::save_file::SaveFormat;
+
+	fn test_directory(test_name: &str) -> PathBuf {
+		let directory = std::env::temp_dir().join(format!("gurksaft_backup_test_{}_{}", test_name, std::process::id()));
+		if directory.exists() {
+			fs::remove_dir_all(&directory).unwrap();
+		}
+		fs::create_dir_all(&directory).unwrap();
+		directory
+	}
+
+	fn time(hour: u32) -> DateTime<Utc> {
+		Utc.with_ymd_and_hms(2023, 9, 1, hour, 0, 0).unwrap()
+	}
+
+	#[test]
+	fn old_backups_are_removed() {
+		let directory = test_directory("rotation");
+		let file = directory.join("options");
+		fs::write(&file, "options").unwrap();
+
+		let backup_directory = directory.join(BACKUP_DIRECTORY_NAME);
+		for hour in 0..MAX_BACKUP_COUNT as u32 + 3 {
+			create_backup_in(&backup_directory, std::slice::from_ref(&file), time(hour)).unwrap();
+		}
+
+		let backups = list_backups_in(&backup_directory).unwrap();
+		assert_eq!(backups.len(), MAX_BACKUP_COUNT);
+		assert_eq!(backups[0].time, time(MAX_BACKUP_COUNT as u32 + 2));
+		assert_eq!(backups.last().unwrap().time, time(3));
+		fs::remove_dir_all(&directory).unwrap();
+	}
+
+	// Laid out like LearningWords without any words or history.
+	fn saved_words() -> Vec<u8> {
+		save_file::serialize_version(LearningWords::VERSION, &(Vec::<u8>::new(), Vec::<u8>::new(), Vec::<u8>::new()))
+	}
+
+	#[test]
+	fn restoring_brings_back_the_files() {
+		let directory = test_directory("restore");
+		let options = directory.join(Path::new(OPTIONS_SAVE_FILE).file_name().unwrap());
+		let words = directory.join(Path::new(&LearningData::words_file_name(0)).file_name().unwrap());
+		let missing = directory.join("never_saved");
+		save_file::save(&options, &Options::new(0)).unwrap();
+		fs::write(&words, saved_words()).unwrap();
+
+		let backup_directory = directory.join(BACKUP_DIRECTORY_NAME);
+		let name = create_backup_in(&backup_directory, &[options.clone(), words.clone(), missing.clone()], time(12)).unwrap();
+		save_file::save(&options, &Options::new(1)).unwrap();
+		fs::write(&words, b"new progress").unwrap();
+
+		let backups = list_backups_in(&backup_directory).unwrap();
+		assert_eq!(backups.len(), 1);
+		assert_eq!(backups[0].name, name);
+		assert_eq!(backups[0].languages, [LANGUAGES[0].name]);
+
+		let restored_options = restore_backup_in(&backup_directory, &name, &directory, &[0]).unwrap();
+		assert_eq!(restored_options.language_index, 0);
+		assert_eq!(save_file::load::<Options>(&options).unwrap().language_index, 0);
+		assert_eq!(fs::read(&words).unwrap(), saved_words());
+		assert!(!missing.exists());
+		fs::remove_dir_all(&directory).unwrap();
+	}
+
+	#[test]
+	fn damaged_backups_are_not_restored() {
+		let directory = test_directory("restore_damaged");
+		let options = directory.join(Path::new(OPTIONS_SAVE_FILE).file_name().unwrap());
+		let words = directory.join(Path::new(&LearningData::words_file_name(0)).file_name().unwrap());
+		save_file::save(&options, &Options::new(0)).unwrap();
+		fs::write(&words, b"not words").unwrap();
+
+		let backup_directory = directory.join(BACKUP_DIRECTORY_NAME);
+		let name = create_backup_in(&backup_directory, &[options.clone(), words.clone()], time(12)).unwrap();
+		save_file::save(&options, &Options::new(1)).unwrap();
+		fs::write(&words, b"new progress").unwrap();
+
+		assert!(matches!(restore_backup_in(&backup_directory, &name, &directory, &[0]), Err(Error::Parse(_))));
+		// The options could be read, but aren't restored without the words.
+		assert_eq!(save_file::load::<Options>(&options).unwrap().language_index, 1);
+		assert_eq!(fs::read(&words).unwrap(), b"new progress");
+		fs::remove_dir_all(&directory).unwrap();
+	}
+
+	#[test]
+	fn removed_languages_are_not_restored() {
+		let directory = test_directory("restore_removed");
+		let options = directory.join(Path::new(OPTIONS_SAVE_FILE).file_name().unwrap());
+		let words = [0, 1].map(|i| directory.join(Path::new(&LearningData::words_file_name(i)).file_name().unwrap()));
+		let mut saved_options = Options::new(1);
+		saved_options.saved_languages = vec![0, 1];
+		save_file::save(&options, &saved_options).unwrap();
+		for path in &words {
+			fs::write(path, saved_words()).unwrap();
+		}
+
+		let backup_directory = directory.join(BACKUP_DIRECTORY_NAME);
+		let name = create_backup_in(&backup_directory, &[options.clone(), words[0].clone(), words[1].clone()], time(12)).unwrap();
+		// The current language is removed.
+		fs::remove_file(&words[1]).unwrap();
+
+		let restored_options = restore_backup_in(&backup_directory, &name, &directory, &[0]).unwrap();
+		assert_eq!((restored_options.language_index, restored_options.saved_languages.as_slice()), (0, [0].as_slice()));
+		assert_eq!(save_file::load::<Options>(&options).unwrap().saved_languages, [0]);
+		assert!(!words[1].exists());
+
+		let result = restore_backup_in(&backup_directory, &name, &directory, &[2]);
+		assert!(matches!(result, Err(Error::InvalidRequest(_))));
+		fs::remove_dir_all(&directory).unwrap();
+	}
+
+	#[test]
+	fn damaged_backups_are_found() {
+		let directory = test_directory("verify");
+		let options = directory.join(Path::new(OPTIONS_SAVE_FILE).file_name().unwrap());
+		let words = directory.join(Path::new(&LearningData::words_file_name(0)).file_name().unwrap());
+		save_file::save(&options, &Options::new(0)).unwrap();
+		fs::write(&words, "not words").unwrap();
+
+		let backup_directory = directory.join(BACKUP_DIRECTORY_NAME);
+		create_backup_in(&backup_directory, &[options, words], time(12)).unwrap();
+
+		let mut verification = DataVerification::default();
+		verify_backups_in(&backup_directory, &mut verification).unwrap();
+		assert_eq!(verification.checked_file_count, 2);
+		assert_eq!(verification.problems.len(), 1);
+		assert!(verification.problems[0].path.ends_with(&format!("{}_words", LANGUAGES[0].name)));
+		fs::remove_dir_all(&directory).unwrap();
+	}
+
+	#[test]
+	fn invalid_backup_names_are_rejected() {
+		let directory = test_directory("invalid_name");
+		let result = restore_backup_in(&directory.join(BACKUP_DIRECTORY_NAME), "../..", &directory, &[0]);
+		assert!(matches!(result, Err(Error::InvalidRequest(_))));
+		fs::remove_dir_all(&directory).unwrap();
+	}
+}
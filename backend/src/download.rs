@@ -0,0 +1,413 @@
+use std::{
+	fs,
+	io::{self, Write},
+	path::{Path, PathBuf},
+	time::Duration,
+};
+
+use reqwest::{header, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	error::{Error, Result},
+	integrity::{self, ExpectedFile},
+};
+
+//----------------------------------------------------------------
+
+/*
+	Files are downloaded to "<path>.part" and renamed to their final path once the whole file has been received and its size
+	has been checked, so a file at the final path is always complete. Information about the unfinished transfer is kept in
+	"<path>.part.json" so that an interrupted download, whether from a dropped connection or a closed program, can continue
+	where it stopped with an HTTP Range request instead of starting over.
+*/
+const PART_EXTENSION: &str = "part";
+const PART_INFO_EXTENSION: &str = "part.json";
+
+const MAX_ATTEMPTS: u32 = 5;
+// Doubled after every failed attempt.
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+//----------------------------------------------------------------
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+struct PartInfo {
+	url: String,
+	// The ETag or Last-Modified header of the first response, sent in If-Range so that a file that changed on the server
+	// since is downloaded again from the beginning instead of being resumed.
+	validator: Option<String>,
+}
+
+// An error together with whether trying again might succeed.
+struct Failure {
+	error: Error,
+	transient: bool,
+}
+
+impl Failure {
+	fn transient(error: impl Into<Error>) -> Self {
+		Failure { error: error.into(), transient: true }
+	}
+
+	fn permanent(error: impl Into<Error>) -> Self {
+		Failure { error: error.into(), transient: false }
+	}
+}
+
+impl From<reqwest::Error> for Failure {
+	fn from(error: reqwest::Error) -> Self {
+		// Client errors such as 404 won't go away by asking again, but timeouts, dropped connections and server errors might.
+		let transient = match error.status() {
+			Some(status) => status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS,
+			None => !error.is_builder() && !error.is_redirect() && !error.is_decode(),
+		};
+		let error = if error.status() == Some(StatusCode::NOT_FOUND) {
+			Error::MissingResource(error.to_string())
+		} else {
+			Error::from(error)
+		};
+		Failure { error, transient }
+	}
+}
+
+// Writing to the disk failing is not something retrying fixes.
+impl From<io::Error> for Failure {
+	fn from(error: io::Error) -> Self {
+		Failure::permanent(error)
+	}
+}
+
+fn path_with_extension(path: &Path, extension: &str) -> PathBuf {
+	let mut path = path.as_os_str().to_owned();
+	path.push(".");
+	path.push(extension);
+	path.into()
+}
+
+/*
+	Parses the total length out of a Content-Range header like "bytes 200-999/1000", checking that the range starts where
+	the partial file ends.
+*/
+fn parse_content_range(content_range: &str, start: u64) -> Option<Option<u64>> {
+	let (range, total) = content_range.strip_prefix("bytes ")?.split_once('/')?;
+	let (range_start, _) = range.split_once('-')?;
+	if range_start.parse::<u64>().ok()? != start {
+		return None;
+	}
+	Some(if total == "*" { None } else { Some(total.parse().ok()?) })
+}
+
+//----------------------------------------------------------------
+
+pub struct Downloader {
+	client: reqwest::Client,
+	// Sent with every request.
+	headers: header::HeaderMap,
+	max_attempts: u32,
+	initial_retry_delay: Duration,
+}
+
+impl Downloader {
+	pub fn new(client: reqwest::Client) -> Self {
+		Downloader { client, headers: header::HeaderMap::new(), max_attempts: MAX_ATTEMPTS, initial_retry_delay: INITIAL_RETRY_DELAY }
+	}
+
+	pub fn with_headers(mut self, headers: header::HeaderMap) -> Self {
+		self.headers = headers;
+		self
+	}
+
+	/*
+		Downloads the file at url to path unless the file already exists, resuming a previous partial download of the same
+		url. Transient failures are retried with exponential backoff. The progress callback receives the number of bytes that
+		are on disk and the total size if the server reported it.
+
+		A missing file on the server is reported as Error::MissingResource so that callers can try another location.
+	*/
+	pub async fn download_file<F>(&self, url: &str, path: &Path, progress: F) -> Result<()>
+		where F: Fn(u64, Option<u64>)
+	{
+		self.download_verified_file(url, path, &ExpectedFile::default(), progress).await
+	}
+
+	/*
+		Like download_file, but the file is checked against what is expected of it. An existing file that doesn't match, such 
+		as a truncated one, is downloaded again, and a download that doesn't match is retried from the beginning.
+	*/
+	pub async fn download_verified_file<F>(&self, url: &str, path: &Path, expected: &ExpectedFile, progress: F) -> Result<()>
+		where F: Fn(u64, Option<u64>)
+	{
+		if path.exists() {
+			match integrity::find_mismatch(path, expected)? {
+				None => return Ok(()),
+				Some(mismatch) => {
+					eprintln!("{} is damaged and is downloaded again: {}", path.display(), mismatch);
+					fs::remove_file(path)?;
+				}
+			}
+		}
+		if let Some(directory) = path.parent() {
+			fs::create_dir_all(directory)?;
+		}
+
+		let mut retry_delay = self.initial_retry_delay;
+		let mut attempt = 1;
+		loop {
+			match self.try_download_file(url, path, expected, &progress).await {
+				Ok(()) => return Ok(()),
+				Err(Failure { error, transient: true }) if attempt < self.max_attempts => {
+					eprintln!("Download of {} failed on attempt {}, retrying in {:?}: {}", url, attempt, retry_delay, error);
+					tokio::time::sleep(retry_delay).await;
+					retry_delay *= 2;
+					attempt += 1;
+				}
+				Err(Failure { error, .. }) => return Err(error.context(format!("downloading {}", url))),
+			}
+		}
+	}
+
+	async fn try_download_file(&self, url: &str, path: &Path, expected: &ExpectedFile, progress: &impl Fn(u64, Option<u64>))
+		-> std::result::Result<(), Failure>
+	{
+		let part_path = path_with_extension(path, PART_EXTENSION);
+		let part_info_path = path_with_extension(path, PART_INFO_EXTENSION);
+
+		let part_info = fs::read(&part_info_path).ok()
+			.and_then(|data| serde_json::from_slice::<PartInfo>(&data).ok())
+			.filter(|info| info.url == url);
+		// A partial file without matching information can't be trusted to belong to this url.
+		let mut received = match (&part_info, fs::metadata(&part_path)) {
+			(Some(_), Ok(metadata)) => metadata.len(),
+			_ => 0,
+		};
+
+		let mut request = self.client.get(url).headers(self.headers.clone());
+		if received > 0 {
+			request = request.header(header::RANGE, format!("bytes={}-", received));
+			if let Some(validator) = part_info.as_ref().and_then(|info| info.validator.as_ref()) {
+				request = request.header(header::IF_RANGE, validator);
+			}
+		}
+		let mut response = request.send().await?;
+
+		if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+			// The partial file doesn't fit the file on the server anymore, so start over on the next attempt.
+			fs::remove_file(&part_path)?;
+			return Err(Failure::transient(Error::Network(format!("the server rejected resuming at byte {}", received))));
+		}
+		if let Err(error) = response.error_for_status_ref() {
+			return Err(error.into());
+		}
+
+		let total_length = if response.status() == StatusCode::PARTIAL_CONTENT {
+			let content_range = response.headers().get(header::CONTENT_RANGE).and_then(|value| value.to_str().ok());
+			content_range.and_then(|content_range| parse_content_range(content_range, received))
+				.ok_or_else(|| Failure::permanent(Error::Network(format!("unexpected Content-Range {:?}", content_range))))?
+		}
+		else {
+			// The server sent the whole file, either because nothing was received yet or because it changed.
+			received = 0;
+			let validator = [header::ETAG, header::LAST_MODIFIED].iter()
+				.find_map(|name| response.headers().get(name))
+				.and_then(|value| value.to_str().ok())
+				.map(str::to_owned);
+			let part_info = PartInfo { url: url.to_owned(), validator };
+			fs::write(&part_info_path, serde_json::to_vec(&part_info).map_err(Failure::permanent)?)?;
+			response.content_length()
+		};
+
+		let mut file = fs::File::options().create(true).append(true).open(&part_path)?;
+		file.set_len(received)?;
+		progress(received, total_length);
+
+		while let Some(chunk) = response.chunk().await? {
+			// Written right away so that what was received so far survives the connection dropping.
+			file.write_all(&chunk)?;
+			received += chunk.len() as u64;
+			progress(received, total_length);
+		}
+		file.sync_all()?;
+		drop(file);
+
+		if let Some(total_length) = total_length && received != total_length {
+			if received > total_length {
+				fs::remove_file(&part_path)?;
+			}
+			return Err(Failure::transient(Error::Network(format!("received {} of {} bytes", received, total_length))));
+		}
+		if let Some(mismatch) = integrity::find_mismatch(&part_path, expected)? {
+			// There's no telling which part is wrong, so the next attempt starts over.
+			fs::remove_file(&part_path)?;
+			fs::remove_file(&part_info_path)?;
+			return Err(Failure::transient(Error::Network(format!("the downloaded file is damaged: {}", mismatch))));
+		}
+
+		fs::rename(&part_path, path)?;
+		fs::remove_file(&part_info_path)?;
+		Ok(())
+	}
+}
+
+//----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		io::{BufRead, BufReader},
+		net::TcpListener,
+		sync::{Arc, Mutex},
+	};
+
+	use super::*;
+
+	/*
+		A minimal HTTP server that serves body at any path, honoring Range requests. The first response can be cut off after
+		a number of bytes to simulate a dropped connection. The Range header of every request is recorded.
+	*/
+	struct TestServer {
+		url: String,
+		ranges: Arc<Mutex<Vec<Option<String>>>>,
+	}
+
+	impl TestServer {
+		fn start(body: Option<Vec<u8>>, cut_off_first_response_at: Option<usize>) -> Self {
+			let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+			let url = format!("http://{}/file", listener.local_addr().unwrap());
+			let ranges = Arc::new(Mutex::new(Vec::new()));
+
+			let recorded_ranges = ranges.clone();
+			std::thread::spawn(move || {
+				for (i, stream) in listener.incoming().enumerate() {
+					let mut stream = stream.unwrap();
+					let mut range = None;
+					for line in BufReader::new(&stream).lines() {
+						let line = line.unwrap();
+						if line.is_empty() {
+							break;
+						}
+						if let Some((name, value)) = line.split_once(": ") && name.eq_ignore_ascii_case("range") {
+							range = Some(value.to_owned());
+						}
+					}
+					recorded_ranges.lock().unwrap().push(range.clone());
+
+					let Some(body) = &body else {
+						stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").unwrap();
+						continue;
+					};
+					let start = range.as_deref()
+						.and_then(|range| range.strip_prefix("bytes="))
+						.and_then(|range| range.trim_end_matches('-').parse::<usize>().ok());
+					let header = match start {
+						Some(start) => format!(
+							"HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\n", start, body.len() - 1, body.len()
+						),
+						None => "HTTP/1.1 200 OK\r\n".to_owned(),
+					};
+					let content = &body[start.unwrap_or(0)..];
+					let header = format!("{}Content-Length: {}\r\nETag: \"1\"\r\nConnection: close\r\n\r\n", header, content.len());
+					stream.write_all(header.as_bytes()).unwrap();
+
+					let sent_length = match cut_off_first_response_at {
+						Some(length) if i == 0 => length,
+						_ => content.len(),
+					};
+					stream.write_all(&content[..sent_length]).unwrap();
+				}
+			});
+			TestServer { url, ranges }
+		}
+
+		fn ranges(&self) -> Vec<Option<String>> {
+			self.ranges.lock().unwrap().clone()
+		}
+	}
+
+	fn test_downloader() -> Downloader {
+		Downloader { max_attempts: 3, initial_retry_delay: Duration::from_millis(10), ..Downloader::new(reqwest::Client::new()) }
+	}
+
+	fn test_path(test_name: &str) -> PathBuf {
+		let directory = std::env::temp_dir().join(format!("gurksaft_download_test_{}_{}", test_name, std::process::id()));
+		if directory.exists() {
+			fs::remove_dir_all(&directory).unwrap();
+		}
+		directory.join("file")
+	}
+
+	fn test_body() -> Vec<u8> {
+		(0..10_000u32).map(|i| (i % 251) as u8).collect()
+	}
+
+	#[tokio::test]
+	async fn interrupted_downloads_are_resumed() {
+		let body = test_body();
+		let server = TestServer::start(Some(body.clone()), Some(4000));
+		let path = test_path("resume");
+
+		test_downloader().download_file(&server.url, &path, |_, _| {}).await.unwrap();
+
+		assert_eq!(fs::read(&path).unwrap(), body);
+		assert_eq!(server.ranges(), [None, Some("bytes=4000-".to_owned())]);
+		assert!(!path_with_extension(&path, PART_EXTENSION).exists());
+		assert!(!path_with_extension(&path, PART_INFO_EXTENSION).exists());
+		fs::remove_dir_all(path.parent().unwrap()).unwrap();
+	}
+
+	#[tokio::test]
+	async fn partial_files_of_other_urls_are_discarded() {
+		let body = test_body();
+		let server = TestServer::start(Some(body.clone()), None);
+		let path = test_path("other_url");
+		fs::create_dir_all(path.parent().unwrap()).unwrap();
+		fs::write(path_with_extension(&path, PART_EXTENSION), b"something else").unwrap();
+		let part_info = PartInfo { url: "http://localhost/other".to_owned(), validator: None };
+		fs::write(path_with_extension(&path, PART_INFO_EXTENSION), serde_json::to_vec(&part_info).unwrap()).unwrap();
+
+		test_downloader().download_file(&server.url, &path, |_, _| {}).await.unwrap();
+
+		assert_eq!(fs::read(&path).unwrap(), body);
+		assert_eq!(server.ranges(), [None]);
+		fs::remove_dir_all(path.parent().unwrap()).unwrap();
+	}
+
+	#[tokio::test]
+	async fn missing_files_are_not_retried() {
+		let server = TestServer::start(None, None);
+		let path = test_path("missing");
+
+		let result = test_downloader().download_file(&server.url, &path, |_, _| {}).await;
+
+		assert!(matches!(result, Err(Error::MissingResource(_))));
+		assert_eq!(server.ranges().len(), 1);
+		assert!(!path.exists());
+		fs::remove_dir_all(path.parent().unwrap()).unwrap();
+	}
+
+	#[tokio::test]
+	async fn damaged_files_are_downloaded_again() {
+		use md5::Digest;
+
+		let body = test_body();
+		let server = TestServer::start(Some(body.clone()), None);
+		let path = test_path("damaged");
+		fs::create_dir_all(path.parent().unwrap()).unwrap();
+		fs::write(&path, &body[..5000]).unwrap();
+
+		let expected = ExpectedFile {
+			size_bytes: Some(body.len() as u64),
+			md5_digest: Some(format!("{:x}", md5::Md5::digest(&body))),
+		};
+		test_downloader().download_verified_file(&server.url, &path, &expected, |_, _| {}).await.unwrap();
+		assert_eq!(fs::read(&path).unwrap(), body);
+
+		// A file that never matches is given up on after the last attempt and not kept.
+		fs::remove_file(&path).unwrap();
+		let expected = ExpectedFile { size_bytes: Some(1), md5_digest: None };
+		let result = test_downloader().download_verified_file(&server.url, &path, &expected, |_, _| {}).await;
+		assert!(matches!(result, Err(Error::Network(_))));
+		assert_eq!(server.ranges().len(), 4);
+		assert!(!path.exists());
+		fs::remove_dir_all(path.parent().unwrap()).unwrap();
+	}
+}
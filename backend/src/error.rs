@@ -0,0 +1,137 @@
+use std::io;
+
+use serde::Serialize;
+
+use crate::save_file::LoadError;
+
+//----------------------------------------------------------------
+
+/*
+	The error type of every command. It is serialized as an object with the kind of error and a message, for example
+	{ "kind": "Network", "message": "..." }, so that the front-end can decide whether to offer retrying.
+*/
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "kind", content = "message")]
+pub enum Error {
+	// There is no connection or a server responded with an error. Trying again later might work.
+	Network(String),
+	// Downloaded data or save data that has the wrong format.
+	Parse(String),
+	Io(String),
+	// A file or resource that should exist doesn't, such as a voice model that hasn't been downloaded.
+	MissingResource(String),
+	// Speech synthesis failed for a voice model that was loaded.
+	Synthesis(String),
+	// The front-end asked for something that can't be done, such as removing the only saved language.
+	InvalidRequest(String),
+	// The operation was stopped on request.
+	Cancelled,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+	/*
+		Puts what was being done in front of the message, like "loading save_data/English_words: ...".
+	*/
+	pub fn context(self, context: impl std::fmt::Display) -> Self {
+		let add_context = |message: String| format!("{}: {}", context, message);
+		match self {
+			Error::Network(message) => Error::Network(add_context(message)),
+			Error::Parse(message) => Error::Parse(add_context(message)),
+			Error::Io(message) => Error::Io(add_context(message)),
+			Error::MissingResource(message) => Error::MissingResource(add_context(message)),
+			Error::Synthesis(message) => Error::Synthesis(add_context(message)),
+			Error::InvalidRequest(message) => Error::InvalidRequest(add_context(message)),
+			Error::Cancelled => Error::Cancelled,
+		}
+	}
+}
+
+impl std::fmt::Display for Error {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Error::Network(message) => write!(f, "network error: {}", message),
+			Error::Parse(message) => write!(f, "parse error: {}", message),
+			Error::Io(message) => write!(f, "IO error: {}", message),
+			Error::MissingResource(message) => write!(f, "missing resource: {}", message),
+			Error::Synthesis(message) => write!(f, "speech synthesis error: {}", message),
+			Error::InvalidRequest(message) => write!(f, "invalid request: {}", message),
+			Error::Cancelled => write!(f, "cancelled"),
+		}
+	}
+}
+
+impl std::error::Error for Error {}
+
+//----------------------------------------------------------------
+
+impl From<io::Error> for Error {
+	fn from(error: io::Error) -> Self {
+		Error::Io(error.to_string())
+	}
+}
+
+impl From<reqwest::Error> for Error {
+	fn from(error: reqwest::Error) -> Self {
+		if error.is_decode() {
+			Error::Parse(error.to_string())
+		}
+		else {
+			Error::Network(error.to_string())
+		}
+	}
+}
+
+impl From<bincode::Error> for Error {
+	fn from(error: bincode::Error) -> Self {
+		Error::Parse(error.to_string())
+	}
+}
+
+impl From<serde_json::Error> for Error {
+	fn from(error: serde_json::Error) -> Self {
+		Error::Parse(error.to_string())
+	}
+}
+
+impl From<tauri::Error> for Error {
+	fn from(error: tauri::Error) -> Self {
+		Error::Io(error.to_string())
+	}
+}
+
+impl From<LoadError> for Error {
+	fn from(error: LoadError) -> Self {
+		match error {
+			LoadError::Io(error) if error.kind() == io::ErrorKind::NotFound => Error::MissingResource(error.to_string()),
+			LoadError::Io(error) => Error::Io(error.to_string()),
+			error => Error::Parse(error.to_string()),
+		}
+	}
+}
+
+//----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn errors_serialize_to_kind_and_message() {
+		let error = Error::Network("timed out".to_owned()).context("downloading words");
+		assert_eq!(
+			serde_json::to_value(error).unwrap(),
+			serde_json::json!({ "kind": "Network", "message": "downloading words: timed out" })
+		);
+		assert_eq!(serde_json::to_value(Error::Cancelled).unwrap(), serde_json::json!({ "kind": "Cancelled" }));
+	}
+
+	#[test]
+	fn missing_save_files_are_missing_resources() {
+		let error = Error::from(LoadError::Io(io::Error::from(io::ErrorKind::NotFound)));
+		assert!(matches!(error, Error::MissingResource(_)));
+		let error = Error::from(LoadError::UnsupportedVersion(7));
+		assert!(matches!(error, Error::Parse(_)));
+	}
+}
@@ -0,0 +1,109 @@
+use std::{
+	fs,
+	io::{self, Read},
+	path::Path,
+};
+
+use md5::{Digest, Md5};
+use serde::Serialize;
+
+//----------------------------------------------------------------
+
+/*
+	What a file is known to look like, for example from the voice catalogue. The size is compared first since it's cheap and 
+	catches truncated files.
+*/
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExpectedFile {
+	pub size_bytes: Option<u64>,
+	// Lowercase hexadecimal, like in the voice catalogue.
+	pub md5_digest: Option<String>,
+}
+
+/*
+	Returns how the file differs from what is expected, or None if it matches. Nothing is read if nothing is expected.
+*/
+pub fn find_mismatch(path: &Path, expected: &ExpectedFile) -> io::Result<Option<String>> {
+	if let Some(size_bytes) = expected.size_bytes {
+		let actual_size = fs::metadata(path)?.len();
+		if actual_size != size_bytes {
+			return Ok(Some(format!("the file is {} bytes instead of {}", actual_size, size_bytes)));
+		}
+	}
+	if let Some(md5_digest) = &expected.md5_digest {
+		let actual_digest = md5_digest_of(path)?;
+		if !actual_digest.eq_ignore_ascii_case(md5_digest) {
+			return Ok(Some(format!("the checksum is {} instead of {}", actual_digest, md5_digest)));
+		}
+	}
+	Ok(None)
+}
+
+fn md5_digest_of(path: &Path) -> io::Result<String> {
+	let mut file = fs::File::open(path)?;
+	let mut hasher = Md5::new();
+	// Voice models are tens of megabytes, so they are hashed a piece at a time.
+	let mut buffer = vec![0; 1 << 16];
+	loop {
+		let length = file.read(&mut buffer)?;
+		if length == 0 {
+			break;
+		}
+		hasher.update(&buffer[..length]);
+	}
+	Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+//----------------------------------------------------------------
+
+/*
+	The result of checking the installed data, see the verify_installed_data command.
+*/
+#[derive(Debug, Default, Serialize)]
+pub struct DataVerification {
+	pub checked_file_count: usize,
+	pub problems: Vec<DataProblem>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DataProblem {
+	pub path: String,
+	pub problem: String,
+	// Whether the file was fixed, for example by downloading it again. Otherwise the problem says what can be done.
+	pub repaired: bool,
+}
+
+impl DataVerification {
+	pub fn add(&mut self, path: impl AsRef<Path>, problem: Option<String>, repaired: bool) {
+		self.checked_file_count += 1;
+		if let Some(problem) = problem {
+			self.problems.push(DataProblem { path: path.as_ref().display().to_string(), problem, repaired });
+		}
+	}
+}
+
+//----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn mismatches_are_found() {
+		let path = std::env::temp_dir().join(format!("gurksaft_integrity_test_{}", std::process::id()));
+		fs::write(&path, b"hello").unwrap();
+
+		let expected = |size_bytes, md5_digest: Option<&str>| ExpectedFile { size_bytes, md5_digest: md5_digest.map(str::to_owned) };
+		let md5_digest = Some("5d41402abc4b2a76b9719d911017c592");
+
+		assert_eq!(find_mismatch(&path, &ExpectedFile::default()).unwrap(), None);
+		assert_eq!(find_mismatch(&path, &expected(Some(5), md5_digest)).unwrap(), None);
+		assert_eq!(find_mismatch(&path, &expected(None, Some("5D41402ABC4B2A76B9719D911017C592"))).unwrap(), None);
+		assert!(find_mismatch(&path, &expected(Some(6), md5_digest)).unwrap().is_some());
+
+		fs::write(&path, b"hellO").unwrap();
+		assert!(find_mismatch(&path, &expected(Some(5), md5_digest)).unwrap().is_some());
+
+		fs::remove_file(&path).unwrap();
+	}
+}
@@ -1,19 +1,25 @@
 use std::{
-	collections::HashMap,
-	fs,
+	collections::{HashMap, VecDeque},
+	path::Path,
 };
 
 use chrono::prelude::*;
 
-use rand::distributions::WeightedIndex;
+use md5::{Digest, Md5};
+
 use rand::prelude::*;
 
 use serde::{Serialize, Deserialize};
 
 use crate::{
+	error::{Error, Result},
+	integrity::DataVerification,
 	options::Options,
+	save_file::{self, LoadError, SaveFormat},
+	scheduler::ReviewSchedule,
 	source_data,
-	util
+	tokenizer::Tokenizer,
+	word_selection::{create_word_selector, SentenceCandidate, WordSelection, WordSelector},
 };
 
 //----------------------------------------------------------------
@@ -21,6 +27,8 @@ use crate::{
 pub const SAVE_DIRECTORY: &str = "save_data";
 const MAX_SENTENCE_LEN: usize = 100;
 const MAX_WORD_COUNT: usize = 10_000;
+// How many tasks are chosen ahead of the current one, so that their audio can be loaded in the background.
+pub const UPCOMING_TASK_COUNT: usize = 3;
 
 //----------------------------------------------------------------
 
@@ -69,7 +77,15 @@ impl<T: PartialEq + Default + Copy> HistoryData<T> {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct LearningWord {
+	/*
+		The headword. When a lemma list is used, this is the lemma of the group.
+	*/
 	pub word: String,
+	/*
+		Other forms of the word found in the frequency list, like "went" and "goes" for "go". Reviewing any of them reviews the 
+		whole group.
+	*/
+	pub forms: Vec<String>,
 	/*
 		A number that determines how likely the word is to be reviewed.
 	*/
@@ -79,18 +95,43 @@ pub struct LearningWord {
 	*/
 	pub long_term_memory: f32,
 	pub last_review: DateTime<Utc>,
+	/*
+		When the word should be reviewed next according to the spaced repetition algorithm. None if the word has not been 
+		reviewed since schedules were introduced.
+	*/
+	pub schedule: Option<ReviewSchedule>,
 }
 
 impl LearningWord {
-	fn from_frequency(word: String, frequency: u64, max_frequency: u64, options: &Options) -> Self {
+	fn from_group(group: WordGroup, max_frequency: u64, options: &Options) -> Self {
 		LearningWord {
-			word,
-			weight: f64::powi(frequency as f64 / max_frequency as f64, 2),
+			word: group.lemma,
+			forms: group.forms,
+			weight: f64::powi(group.frequency as f64 / max_frequency as f64, 2),
 			long_term_memory: options.word_memory_parameters.initial_memory,
 			last_review: Utc.timestamp_nanos(0),
+			schedule: None,
 		}
 	}
 
+	/*
+		The headword followed by the other forms.
+	*/
+	pub fn all_forms(&self) -> impl Iterator<Item = &str> {
+		std::iter::once(self.word.as_str()).chain(self.forms.iter().map(String::as_str))
+	}
+
+	/*
+		Whether a lowercase word from a sentence is one of the forms of this word.
+	*/
+	pub fn matches(&self, text: &str) -> bool {
+		self.all_forms().any(|form| form == text)
+	}
+
+	pub fn has_been_reviewed(&self) -> bool {
+		self.last_review > Utc.timestamp_nanos(0)
+	}
+
 	fn update(&mut self, result: WordReviewResult, options: &Options) {
 		self.weight *= match result {
 			WordReviewResult::Succeeded => options.weight_factors.succeeded,
@@ -114,6 +155,12 @@ impl LearningWord {
 		let days_since_last_review = (now - self.last_review).num_seconds() as f32 / SECONDS_PER_DAY;
 		self.last_review = now;
 
+		let algorithm = options.scheduling_algorithm;
+		match &mut self.schedule {
+			Some(schedule) => schedule.review(algorithm, result, now),
+			None => self.schedule = Some(ReviewSchedule::first_review(algorithm, result, now)),
+		}
+
 		let change_rate_range = options.word_memory_parameters.change_rate_range;
 		let half_time = options.word_memory_parameters.change_rate_half_time;
 		let change_rate = change_rate_range.max - change_rate_range.length() * f32::exp2(-days_since_last_review / half_time);
@@ -126,6 +173,63 @@ impl LearningWord {
 
 //----------------------------------------------------------------
 
+/*
+	Maps forms of words to their lemmas. If a form has several possible lemmas, the first one in the list is used.
+*/
+struct LemmaTable(HashMap<String, String>);
+
+impl LemmaTable {
+	fn load_from_source_data(data: &[u8]) -> Self {
+		let data = data.strip_prefix("\u{feff}".as_bytes()).unwrap_or(data);
+		let mut reader = csv::ReaderBuilder::new().delimiter(b'\t').has_headers(false).flexible(true).quoting(false).from_reader(data);
+
+		let mut lemmas = HashMap::new();
+		for (lemma, form) in reader.deserialize::<(String, String)>().filter_map(|result| result.ok()) {
+			lemmas.entry(form.to_lowercase()).or_insert(lemma.to_lowercase());
+		}
+		LemmaTable(lemmas)
+	}
+
+	fn lemma<'a>(&'a self, form: &'a str) -> &'a str {
+		self.0.get(form).map_or(form, String::as_str)
+	}
+}
+
+// The forms in a frequency list that share a lemma, with their frequencies added together.
+struct WordGroup {
+	lemma: String,
+	forms: Vec<String>,
+	frequency: u64,
+}
+
+fn group_by_lemma(word_frequency_pairs: impl Iterator<Item = (String, u64)>, lemmas: &LemmaTable) -> Vec<WordGroup> {
+	let mut groups: Vec<WordGroup> = Vec::new();
+	let mut group_indices: HashMap<String, usize> = HashMap::new();
+
+	for (form, frequency) in word_frequency_pairs {
+		let lemma = lemmas.lemma(&form).to_owned();
+		if let Some(&index) = group_indices.get(&lemma) {
+			let group = &mut groups[index];
+			group.frequency += frequency;
+			if form != group.lemma {
+				group.forms.push(form);
+			}
+		}
+		else {
+			let forms = if form != lemma { vec![form] } else { Vec::new() };
+			group_indices.insert(lemma.clone(), groups.len());
+			groups.push(WordGroup { lemma, forms, frequency });
+		}
+	}
+
+	// Merging forms changes the frequencies, so the groups have to be sorted again. The sort is stable, which keeps the
+	// original order when there is no lemma list.
+	groups.sort_by_key(|group| std::cmp::Reverse(group.frequency));
+	groups
+}
+
+//----------------------------------------------------------------
+
 #[derive(Deserialize, Serialize)]
 pub struct LearningWords {
 	pub words: Vec<LearningWord>,
@@ -134,27 +238,66 @@ pub struct LearningWords {
 }
 
 impl LearningWords {
-	fn load_from_source_data(data: &[u8], options: &Options) -> Self {
+	fn load_from_source_data(data: &[u8], lemma_data: Option<&[u8]>, options: &Options) -> Result<Self> {
 		let mut reader = csv::ReaderBuilder::new().delimiter(b' ').has_headers(false).from_reader(data);
 
-		let mut word_frequency_pairs = reader.deserialize::<(String, u64)>().filter_map(|result| result.ok()).take(MAX_WORD_COUNT);
-		
-		let (first_word, max_frequency) = word_frequency_pairs.next().expect("the parsed word frequency list should not be empty");
+		let word_frequency_pairs = reader.deserialize::<(String, u64)>().filter_map(|result| result.ok());
 
-		let the_rest = word_frequency_pairs.map(|(word, frequency)| LearningWord::from_frequency(word, frequency, max_frequency, options));
+		// Without a lemma list every form is its own group.
+		let lemmas = lemma_data.map_or_else(|| LemmaTable(HashMap::new()), LemmaTable::load_from_source_data);
+		let mut groups = group_by_lemma(word_frequency_pairs, &lemmas);
+		groups.truncate(MAX_WORD_COUNT);
 		
-		let words = std::iter::once(LearningWord::from_frequency(first_word, max_frequency, max_frequency, options))
-			.chain(the_rest).collect();
+		let max_frequency = groups.first()
+			.ok_or_else(|| Error::Parse("the word frequency list contains no words".to_owned()))?
+			.frequency;
+
+		let words = groups.into_iter().map(|group| LearningWord::from_group(group, max_frequency, options)).collect();
 		
-		LearningWords {
+		Ok(LearningWords {
 			words,
 			learned_word_count: HistoryData::new(),
 			easy_word_count: HistoryData::new()
+		})
+	}
+
+	fn create_tokenizer(&self, language_index: usize) -> Tokenizer {
+		Tokenizer::new(source_data::LANGUAGES[language_index].word_segmentation, self.words.iter().flat_map(LearningWord::all_forms))
+	}
+}
+
+impl From<v0::LearningWord> for LearningWord {
+	fn from(word: v0::LearningWord) -> Self {
+		LearningWord {
+			word: word.word,
+			forms: Vec::new(),
+			weight: word.weight,
+			long_term_memory: word.long_term_memory,
+			last_review: word.last_review,
+			schedule: None,
 		}
 	}
+}
 
-	fn create_weighted_index(&self) -> WeightedIndex<f64> {
-		WeightedIndex::new(self.words.iter().map(|word| word.weight)).unwrap()
+impl From<v0::LearningWords> for LearningWords {
+	fn from(words: v0::LearningWords) -> Self {
+		LearningWords {
+			words: words.words.into_iter().map(LearningWord::from).collect(),
+			learned_word_count: words.learned_word_count,
+			easy_word_count: words.easy_word_count,
+		}
+	}
+}
+
+impl SaveFormat for LearningWords {
+	// Version 1 added word forms and review schedules.
+	const VERSION: u32 = 1;
+
+	fn upgrade(version: u32, data: &[u8]) -> std::result::Result<Vec<u8>, LoadError> {
+		match version {
+			0 => save_file::upgrade_step::<v0::LearningWords, LearningWords>(data),
+			_ => Err(LoadError::UnsupportedVersion(version)),
+		}
 	}
 }
 
@@ -166,6 +309,9 @@ pub type SentenceId = u32;
 struct Translation {
 	id: SentenceId,
 	text: String,
+	// The index of the language in LANGUAGES. None for translations saved before the language was recorded, which is filled in 
+	// if the same translation is added again.
+	language_index: Option<usize>,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -175,13 +321,38 @@ struct LearningSentence {
 	translations: Vec<Translation>,
 }
 
+/*
+	What changed when the sentences were refreshed from a newer export.
+*/
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct SentenceChanges {
+	pub added_sentences: usize,
+	pub removed_sentences: usize,
+	// Sentences whose text was edited.
+	pub updated_sentences: usize,
+	pub added_translations: usize,
+	pub removed_translations: usize,
+}
+
 //----------------------------------------------------------------
 
 #[derive(Deserialize, Serialize)]
 struct LearningSentences(HashMap<SentenceId, LearningSentence>);
 
 impl LearningSentences {
-	fn load_from_source_data(data: &[u8]) -> Self {
+	fn load_from_source_data(lists: &[source_data::SentenceList]) -> Self {
+		let mut result = LearningSentences(HashMap::with_capacity(100_000));
+		for list in lists {
+			result.add_translations(list);
+		}
+		result
+	}
+
+	/*
+		Adds the sentences and translations in the list. Sentences and translations that already exist are kept, since 
+		Tatoeba's sentence IDs are the same in every list.
+	*/
+	fn add_translations(&mut self, list: &source_data::SentenceList) {
 		#[derive(Debug, Deserialize)]
 		struct SentencePair {
 			id_0: SentenceId,
@@ -190,46 +361,312 @@ impl LearningSentences {
 			translation: String,
 		}
 		
-		let mut reader = csv::ReaderBuilder::new().delimiter(b'\t').has_headers(false).from_reader(data);
+		let mut reader = csv::ReaderBuilder::new().delimiter(b'\t').has_headers(false).from_reader(list.data.as_slice());
 		
 		let pairs = reader.deserialize::<SentencePair>()
 			.filter_map(|result| result.ok())
 			.filter(|sentence| sentence.original.len() < MAX_SENTENCE_LEN);
 
-		let mut result = LearningSentences(HashMap::with_capacity(100_000));
-		
 		for pair in pairs {
-			let translation = Translation { id: pair.id_1, text: pair.translation };
-			if let Some(sentence) = result.0.get_mut(&pair.id_0) {
-				sentence.translations.push(translation);
+			let language_index = Some(list.translation_language_index);
+			if let Some(sentence) = self.0.get_mut(&pair.id_0) {
+				if let Some(translation) = sentence.translations.iter_mut().find(|translation| translation.id == pair.id_1) {
+					translation.language_index = language_index;
+				}
+				else {
+					sentence.translations.push(Translation { id: pair.id_1, text: pair.translation, language_index });
+				}
 			}
 			else {
 				let lowercase = pair.original.to_lowercase();
-				result.0.insert(pair.id_0, LearningSentence {
+				self.0.insert(pair.id_0, LearningSentence {
 					original: pair.original,
 					lowercase,
-					translations: vec![translation],
+					translations: vec![Translation { id: pair.id_1, text: pair.translation, language_index }],
 				});
 			}
 		}
+	}
 
-		result
+	/*
+		Removes the translations in other languages, and the ones whose language is unknown, together with the sentences that 
+		have no other translations.
+	*/
+	fn retain_translations(&mut self, languages: &[usize]) {
+		self.0.retain(|_, sentence| {
+			sentence.translations.retain(|translation| translation.language_index.is_some_and(|i| languages.contains(&i)));
+			!sentence.translations.is_empty()
+		});
+	}
+
+	fn has_translations_in(&self, languages: &[usize]) -> bool {
+		self.0.values()
+			.flat_map(|sentence| &sentence.translations)
+			.any(|translation| translation.language_index.is_some_and(|i| languages.contains(&i)))
+	}
+
+	/*
+		Makes the sentences match newer exports of the same lists. Translations in the languages of the lists that are no 
+		longer in them are removed, as are sentences left without translations. Translations whose language is unknown are 
+		only removed together with their sentence.
+	*/
+	fn refresh(&mut self, lists: &[source_data::SentenceList]) -> SentenceChanges {
+		let fresh = LearningSentences::load_from_source_data(lists);
+		let refreshed_languages: Vec<usize> = lists.iter().map(|list| list.translation_language_index).collect();
+		let mut changes = SentenceChanges::default();
+
+		self.0.retain(|id, sentence| {
+			let fresh_sentence = fresh.0.get(id);
+			let translation_count = sentence.translations.len();
+			sentence.translations.retain(|translation| {
+				!translation.language_index.is_some_and(|i| refreshed_languages.contains(&i))
+					|| fresh_sentence.is_some_and(|fresh| fresh.translations.iter().any(|fresh| fresh.id == translation.id))
+			});
+			changes.removed_translations += translation_count - sentence.translations.len();
+
+			let keep = !sentence.translations.is_empty();
+			if !keep {
+				changes.removed_sentences += 1;
+			}
+			keep
+		});
+
+		for (id, fresh_sentence) in fresh.0 {
+			let Some(sentence) = self.0.get_mut(&id) else {
+				changes.added_sentences += 1;
+				changes.added_translations += fresh_sentence.translations.len();
+				self.0.insert(id, fresh_sentence);
+				continue;
+			};
+
+			if sentence.original != fresh_sentence.original {
+				changes.updated_sentences += 1;
+				sentence.original = fresh_sentence.original;
+				sentence.lowercase = fresh_sentence.lowercase;
+			}
+			for fresh_translation in fresh_sentence.translations {
+				if let Some(translation) = sentence.translations.iter_mut().find(|translation| translation.id == fresh_translation.id) {
+					*translation = fresh_translation;
+				}
+				else {
+					changes.added_translations += 1;
+					sentence.translations.push(fresh_translation);
+				}
+			}
+		}
+
+		changes
+	}
+
+	fn translation_languages(&self) -> Vec<usize> {
+		let mut languages: Vec<usize> = self.0.values()
+			.flat_map(|sentence| &sentence.translations)
+			.filter_map(|translation| translation.language_index)
+			.collect();
+		languages.sort_unstable();
+		languages.dedup();
+		languages
+	}
+}
+
+impl SaveFormat for LearningSentences {
+	// Version 1 only added the header. Version 2 added the language of translations.
+	const VERSION: u32 = 2;
+
+	fn upgrade(version: u32, data: &[u8]) -> std::result::Result<Vec<u8>, LoadError> {
+		match version {
+			// Only the header was added.
+			0 => Ok(data.to_vec()),
+			1 => save_file::upgrade_step::<v1::LearningSentences, LearningSentences>(data),
+			_ => Err(LoadError::UnsupportedVersion(version)),
+		}
+	}
+}
+
+impl From<v1::LearningSentences> for LearningSentences {
+	fn from(sentences: v1::LearningSentences) -> Self {
+		let sentences = sentences.0.into_iter()
+			.map(|(id, sentence)| (id, LearningSentence {
+				original: sentence.original,
+				lowercase: sentence.lowercase,
+				translations: sentence.translations.into_iter()
+					.map(|translation| Translation { id: translation.id, text: translation.text, language_index: None })
+					.collect(),
+			}))
+			.collect();
+		LearningSentences(sentences)
 	}
 }
 
 //----------------------------------------------------------------
 
+#[derive(Deserialize, Serialize)]
+struct SentenceWords {
+	// Ids of the words from the word list that the sentence consists of, once for each occurrence.
+	word_ids: Vec<usize>,
+	// Number of words in the sentence that are not in the word list. These are rare enough to most likely be unknown.
+	unlisted_word_count: u32,
+}
+
+// The difficulty of a word that is not in the word list, which is the highest possible difficulty of a word that is.
+const UNLISTED_WORD_DIFFICULTY: f32 = 2.;
+
+/*
+	An inverted index from words to the sentences that contain them, together with the words that each sentence consists of.
+	sentences_with_word is parallel to LearningWords::words, so the sentence ids for a word are found at the same index as the
+	word itself. The sentence ids for each word are sorted.
+*/
+#[derive(Deserialize, Serialize)]
+struct SentenceIndex {
+	sentences_with_word: Vec<Vec<SentenceId>>,
+	sentence_words: HashMap<SentenceId, SentenceWords>,
+	// Of the words and sentences the index was built from, see index_fingerprint. Updated when the index is saved.
+	fingerprint: [u8; 16],
+}
+
+impl SentenceIndex {
+	fn new(words: &LearningWords, sentences: &LearningSentences, tokenizer: &Tokenizer) -> Self {
+		let word_ids: HashMap<&str, usize> = words.words.iter()
+			.enumerate()
+			.flat_map(|(i, word)| word.all_forms().map(move |form| (form, i)))
+			.collect();
+
+		let mut sentences_with_word = vec![Vec::new(); words.words.len()];
+		let mut sentence_words = HashMap::with_capacity(sentences.0.len());
+
+		for (&sentence_id, sentence) in &sentences.0 {
+			let mut words_in_sentence = SentenceWords { word_ids: Vec::new(), unlisted_word_count: 0 };
+
+			for word in tokenizer.words(&sentence.lowercase) {
+				if let Some(&word_id) = word_ids.get(word.text) {
+					sentences_with_word[word_id].push(sentence_id);
+					words_in_sentence.word_ids.push(word_id);
+				}
+				else {
+					words_in_sentence.unlisted_word_count += 1;
+				}
+			}
+			sentence_words.insert(sentence_id, words_in_sentence);
+		}
+
+		// A word can occur several times in the same sentence.
+		for sentence_ids in &mut sentences_with_word {
+			sentence_ids.sort_unstable();
+			sentence_ids.dedup();
+		}
+
+		SentenceIndex { sentences_with_word, sentence_words, fingerprint: index_fingerprint(words, sentences) }
+	}
+
+	/*
+		Whether a saved index belongs to the words and sentences. The word list can change without changing its length, for 
+		example when a backup is restored, and an index of other words would review the wrong words in sentences that might
+		not even exist anymore.
+	*/
+	fn matches(&self, words: &LearningWords, sentences: &LearningSentences) -> bool {
+		self.sentences_with_word.len() == words.words.len()
+			&& self.sentences_with_word.iter().flatten().all(|id| sentences.0.contains_key(id))
+			&& self.sentence_words.iter().all(|(id, sentence_words)| {
+				sentences.0.contains_key(id) && sentence_words.word_ids.iter().all(|&word_id| word_id < words.words.len())
+			})
+			&& self.fingerprint == index_fingerprint(words, sentences)
+	}
+
+	fn sentences_with_word(&self, word_id: usize) -> &[SentenceId] {
+		&self.sentences_with_word[word_id]
+	}
+
+	/*
+		How hard the sentence is to understand for the learner apart from the word being reviewed. Words that are easy for the
+		learner add nothing while other words add more the worse they are remembered and the less frequent they are. Every word 
+		also adds a bit no matter how well it is known, so that shorter sentences are easier. A sentence where the reviewed word 
+		is the only unknown word ("i+1") is thus among the easiest.
+	*/
+	fn sentence_difficulty(&self, sentence_id: SentenceId, reviewed_word_id: usize, words: &[LearningWord], options: &Options) -> f32 {
+		let Some(sentence_words) = self.sentence_words.get(&sentence_id)
+		else {
+			return 0.;
+		};
+
+		let easy_threshold = options.word_memory_parameters.easy_threshold;
+
+		let listed_word_difficulty: f32 = sentence_words.word_ids.iter()
+			.filter(|&&word_id| word_id != reviewed_word_id)
+			.map(|&word_id| {
+				let word = &words[word_id];
+				if word.long_term_memory >= easy_threshold {
+					0.
+				}
+				else {
+					// The word list is sorted by frequency.
+					let rarity = word_id as f32 / words.len() as f32;
+					(1. - word.long_term_memory) * (1. + rarity)
+				}
+			})
+			.sum();
+
+		let word_count = sentence_words.word_ids.len() + sentence_words.unlisted_word_count as usize;
+
+		listed_word_difficulty 
+			+ sentence_words.unlisted_word_count as f32 * UNLISTED_WORD_DIFFICULTY 
+			+ word_count as f32 * options.sentence_difficulty.length_weight
+	}
+
+	// Must be called together with removing the word from LearningWords::words to keep the indices in sync.
+	fn remove_word(&mut self, word_id: usize) {
+		self.sentences_with_word.remove(word_id);
+
+		for sentence_words in self.sentence_words.values_mut() {
+			// The removed word is not in any sentence, but all following word ids are shifted down.
+			for id in sentence_words.word_ids.iter_mut().filter(|id| **id > word_id) {
+				*id -= 1;
+			}
+		}
+	}
+}
+
+// The index can always be rebuilt from the words and sentences, so old versions are not migrated.
+impl SaveFormat for SentenceIndex {
+	// Version 2 added the fingerprint.
+	const VERSION: u32 = 2;
+}
+
+/*
+	A digest of everything the index is built from: the forms of every word in order, and the text of every sentence.
+*/
+fn index_fingerprint(words: &LearningWords, sentences: &LearningSentences) -> [u8; 16] {
+	let mut hasher = Md5::new();
+	for word in &words.words {
+		for form in word.all_forms() {
+			hasher.update(form.as_bytes());
+			hasher.update([0]);
+		}
+		hasher.update([1]);
+	}
+
+	let mut sentence_ids: Vec<SentenceId> = sentences.0.keys().copied().collect();
+	sentence_ids.sort_unstable();
+	for id in sentence_ids {
+		hasher.update(id.to_le_bytes());
+		hasher.update(sentences.0[&id].lowercase.as_bytes());
+		hasher.update([0]);
+	}
+	hasher.finalize().into()
+}
+
+//----------------------------------------------------------------
+
 /*
 	A word to be reviewed in a sentence.
 */
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct TaskWord {
 	pub id: usize,
 	pub word: String,
 	pub position: usize,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct LearningTask {
 	pub sentence_id: SentenceId,
 	pub sentence: String,
@@ -258,8 +695,22 @@ pub struct FinishedTask {
 
 pub struct LearningData {
 	words: LearningWords,
-	word_weighted_index: WeightedIndex<f64>,
+	word_selector: Box<dyn WordSelector>,
+	// All randomness in word and sentence selection comes from here, so that sessions can be reproduced with a fixed seed.
+	rng: StdRng,
 	sentences: LearningSentences,
+	sentence_index: SentenceIndex,
+	tokenizer: Tokenizer,
+	// Number of tasks finished since the words were last saved.
+	unsaved_task_count: u32,
+	// Whether the index has changed since it was last saved, which only happens when words are removed.
+	sentence_index_modified: bool,
+	/*
+		Tasks that have been chosen but not given out yet. They are chosen before the results of the tasks in front of them 
+		are known, which delays the effect of a result by a few tasks. The queue is emptied whenever something changes how 
+		tasks are chosen.
+	*/
+	upcoming_tasks: VecDeque<LearningTask>,
 }
 
 impl LearningData {
@@ -267,51 +718,92 @@ impl LearningData {
 		&self.words
 	}
 
-	fn choose_sentence_with_word(&self, word_id: usize) -> Option<(&SentenceId, &LearningSentence)> {
-		// Find sentences that contain the word.
-		let matching_sentences: Vec<_> = self.sentences.0.iter()
-			.filter(|(_id, sentence)| util::contains_word(&sentence.lowercase, &self.words.words[word_id].word))
-			.collect();
+	pub fn set_word_selection(&mut self, selection: WordSelection) {
+		self.word_selector = create_word_selector(selection, &self.words.words);
+		self.upcoming_tasks.clear();
+	}
 
-		if matching_sentences.is_empty() {
-			None
+	fn create_rng(seed: Option<u64>) -> StdRng {
+		match seed {
+			Some(seed) => StdRng::seed_from_u64(seed),
+			None => StdRng::from_entropy(),
 		}
-		else {
-			// Choose a sentence randomly among the ones that contained the word.
-			Some(*matching_sentences.choose(&mut thread_rng()).unwrap())
+	}
+
+	pub fn set_random_seed(&mut self, seed: Option<u64>) {
+		self.rng = Self::create_rng(seed);
+		self.upcoming_tasks.clear();
+	}
+
+	/*
+		Gives out the next task and chooses the ones after it, see upcoming_tasks. Fails if none of the words are in any of the
+		sentences.
+	*/
+	pub fn next_task(&mut self, options: &Options) -> Result<LearningTask> {
+		while self.upcoming_tasks.len() <= UPCOMING_TASK_COUNT {
+			let task = self.choose_task(options)?;
+			self.upcoming_tasks.push_back(task);
 		}
+		Ok(self.upcoming_tasks.pop_front().unwrap())
 	}
 
-	pub fn next_task(&mut self) -> LearningTask {
+	/*
+		The tasks that come after the one last given out, in order.
+	*/
+	pub fn upcoming_tasks(&self) -> impl Iterator<Item = &LearningTask> {
+		self.upcoming_tasks.iter()
+	}
+
+	fn choose_task(&mut self, options: &Options) -> Result<LearningTask> {
 		loop {
-			// First we stochastically select the main word to be reviewed based on the weight distribution.
-			let word_id = self.word_weighted_index.sample(&mut thread_rng());
+			// Words that aren't in any sentence are removed below, which can leave nothing to review.
+			if self.words.words.is_empty() {
+				return Err(Error::MissingResource("sentences that contain any of the words".to_owned()));
+			}
+
+			// First we select the main word to be reviewed.
+			let word_id = self.word_selector.choose_word(&self.words.words, options, &mut self.rng);
 
-			// Choose a sentence randomly among the ones that contained the word.
-			let Some((&sentence_id, sentence)) = self.choose_sentence_with_word(word_id)
+			// Then we choose a sentence among the ones that contain the word.
+			let candidates: Vec<_> = self.sentence_index.sentences_with_word(word_id).iter()
+				.map(|&id| SentenceCandidate {
+					id,
+					difficulty: self.sentence_index.sentence_difficulty(id, word_id, &self.words.words, options),
+				})
+				.collect();
+			let difficulty_bias = options.sentence_difficulty.current_bias(self.words.learned_word_count.get_latest());
+
+			let Some((&sentence_id, sentence)) = self.word_selector.choose_sentence(word_id, &candidates, difficulty_bias, &mut self.rng)
+				.and_then(|sentence_id| self.sentences.0.get_key_value(&sentence_id))
 			else {
 				// The word does not exist in any of the sentences, we can remove it.
 				self.words.words.remove(word_id);
-				self.word_weighted_index = self.words.create_weighted_index();
+				self.sentence_index.remove_word(word_id);
+				self.sentence_index_modified = true;
+				if !self.words.words.is_empty() {
+					self.word_selector.word_removed(&self.words.words, word_id);
+				}
+				// The word IDs of the upcoming tasks have shifted.
+				self.upcoming_tasks.clear();
 				continue;
 			};
 
-			// Lowercase versions might not have the same number of characters as the original ones, so we need to use the original case
-			// when calculating word position.
-			let original_words: Vec<&str> = sentence.original.split_ascii_whitespace().collect();
+			// Lowercase versions might not have the same number of characters as the original ones, so we tokenize the original
+			// sentence to get the word positions and lowercase each word separately when comparing.
+			let original_words = self.tokenizer.words(&sentence.original);
+			let lowercase_words: Vec<String> = original_words.iter().map(|word| word.text.to_lowercase()).collect();
 
 			let mut review_words = Vec::new();
 
 			let mut add_review_words = |id: usize, review_word: &LearningWord| {
-				let matching_words = sentence.lowercase
-					.split_ascii_whitespace()
-					.enumerate()
-					.filter(|(_i, word)| *word == review_word.word)
-					.map(|(i, _word)| {
+				let matching_words = original_words.iter()
+					.zip(&lowercase_words)
+					.filter(|(_original, lowercase)| review_word.matches(lowercase))
+					.map(|(original, _lowercase)| {
 						TaskWord {
 							id,
-							word: original_words[i].to_string(),
-							position: util::get_word_position(&sentence.original, original_words[i]),
+							word: original.text.to_string(),
+							position: original.position,
 						}
 					});
 				review_words.extend(matching_words);
@@ -334,12 +826,12 @@ impl LearningData {
 			// The front-end wants the review words to be in order of position in the sentence.
 			review_words.sort_unstable_by_key(|word| word.position);
 			
-			return LearningTask {
+			return Ok(LearningTask {
 				sentence_id,
 				sentence: sentence.original.clone(),
 				translations: sentence.translations.iter().map(|sentence| sentence.text.clone()).collect(),
 				review_words,
-			};
+			});
 		}
 	}
 
@@ -353,65 +845,608 @@ impl LearningData {
 			
 			word.update(word_review.result, options);
 			
-			let learned_threshold = options.word_memory_parameters.learned_threshold;
-			learned_word_count += (word.long_term_memory >= learned_threshold) as u32 - (previous_memory >= learned_threshold) as u32;
-			let easy_threshold = options.word_memory_parameters.easy_threshold;
-			easy_word_count += (word.long_term_memory >= easy_threshold) as u32 - (previous_memory >= easy_threshold) as u32;
+			// The count goes down by one if the word fell below the threshold and up by one if it rose above it.
+			let update_count = |count: u32, threshold: f32| {
+				(count as i64 + (word.long_term_memory >= threshold) as i64 - (previous_memory >= threshold) as i64) as u32
+			};
+			learned_word_count = update_count(learned_word_count, options.word_memory_parameters.learned_threshold);
+			easy_word_count = update_count(easy_word_count, options.word_memory_parameters.easy_threshold);
 		}
 
 		self.words.learned_word_count.add_point(learned_word_count);
 		self.words.easy_word_count.add_point(easy_word_count);
 		
-		let mut updated_weights: Vec<_> = task.word_reviews.iter()
-			.map(|review| (review.word_id, &self.words.words[review.word_id].weight))
-			.collect();
-	
-		// The elements passed to updated_weights must be sorted by index (first element in the tuple), and I assume not contain duplicates.
-		updated_weights.sort_by_key(|(i, _weight)| *i);
-		updated_weights.dedup_by_key(|(i, _weight)| *i);
+		let word_ids: Vec<usize> = task.word_reviews.iter().map(|review| review.word_id).collect();
+		self.word_selector.words_updated(&self.words.words, &word_ids);
+
+		self.unsaved_task_count += 1;
+	}
 
-		self.word_weighted_index.update_weights(&updated_weights).expect("should be able to update word weights");
+	pub fn unsaved_task_count(&self) -> u32 {
+		self.unsaved_task_count
 	}
 	
-	pub fn load_from_source_data(source_data: &source_data::SourceData, options: &Options) -> Self {
-		let words = LearningWords::load_from_source_data(&source_data.word_list, options);
-		let word_weighted_index = words.create_weighted_index();
-		Self { 
+	pub fn load_from_source_data(source_data: &source_data::SourceData, options: &Options) -> Result<Self> {
+		let words = LearningWords::load_from_source_data(&source_data.word_list, source_data.lemma_list.as_deref(), options)?;
+		let word_selector = create_word_selector(options.word_selection, &words.words);
+		let tokenizer = words.create_tokenizer(source_data.language_index);
+		let sentences = LearningSentences::load_from_source_data(&source_data.sentence_lists);
+		let sentence_index = SentenceIndex::new(&words, &sentences, &tokenizer);
+		Ok(Self { 
 			words, 
-			word_weighted_index,
-			sentences: LearningSentences::load_from_source_data(&source_data.sentence_list),
+			word_selector,
+			rng: Self::create_rng(options.random_seed),
+			sentences,
+			sentence_index,
+			tokenizer,
+			unsaved_task_count: 0,
+			sentence_index_modified: true,
+			upcoming_tasks: VecDeque::new(),
+		})
+	}
+
+	/*
+		The languages that sentences are translated into, apart from translations saved before their language was recorded.
+	*/
+	pub fn translation_languages(&self) -> Vec<usize> {
+		self.sentences.translation_languages()
+	}
+
+	/*
+		Adds the translations in the lists and removes the ones in other languages, which changes which sentences there are 
+		but leaves the words and their progress as they are. The lists have to be in the given languages. Translations saved 
+		before their language was recorded are only kept if they are in one of the lists, since their language can't be told 
+		otherwise. Fails without changing anything if no sentences would be left. The sentences have to be saved afterwards.
+	*/
+	pub fn set_translation_languages(&mut self, languages: &[usize], added_lists: &[source_data::SentenceList]) -> Result<()> {
+		if !self.sentences.has_translations_in(languages) && LearningSentences::load_from_source_data(added_lists).0.is_empty() {
+			return Err(Error::InvalidRequest("no sentences would be left with these translation languages".to_owned()));
 		}
+
+		for list in added_lists {
+			self.sentences.add_translations(list);
+		}
+		self.sentences.retain_translations(languages);
+		self.rebuild_sentence_index();
+		Ok(())
 	}
 
-	fn words_file_name(language_index: usize) -> String {
+	/*
+		Replaces the sentences with the ones in newer exports of the translation lists, keeping the words and their progress.
+		The sentences have to be saved afterwards.
+	*/
+	pub fn refresh_sentences(&mut self, lists: &[source_data::SentenceList]) -> SentenceChanges {
+		let changes = self.sentences.refresh(lists);
+		self.rebuild_sentence_index();
+		changes
+	}
+
+	fn rebuild_sentence_index(&mut self) {
+		self.sentence_index = SentenceIndex::new(&self.words, &self.sentences, &self.tokenizer);
+		self.sentence_index_modified = true;
+		self.upcoming_tasks.clear();
+	}
+
+	pub fn words_file_name(language_index: usize) -> String {
 		format!("{}/{}_words", SAVE_DIRECTORY, source_data::LANGUAGES[language_index].name)
 	}
 	fn sentences_file_name(language_index: usize) -> String {
 		format!("{}/{}_sentences", SAVE_DIRECTORY, source_data::LANGUAGES[language_index].name)
 	}
+	fn sentence_index_file_name(language_index: usize) -> String {
+		format!("{}/{}_sentence_index", SAVE_DIRECTORY, source_data::LANGUAGES[language_index].name)
+	}
+
+	/*
+		Whether the language still has its sentences, which are removed together with the language.
+	*/
+	pub fn has_saved_sentences(language_index: usize) -> bool {
+		Path::new(&Self::sentences_file_name(language_index)).exists()
+	}
+
+	/*
+		Removes the saved words, sentences and sentence index of the language and returns the number of bytes freed.
+	*/
+	pub fn remove_files(language_index: usize) -> Result<u64> {
+		let mut freed_bytes = 0;
+		for file_name in [
+			Self::words_file_name(language_index),
+			Self::sentences_file_name(language_index),
+			Self::sentence_index_file_name(language_index),
+		] {
+			freed_bytes += save_file::remove(&file_name).map_err(|error| Error::from(error).context(format!("removing {}", file_name)))?;
+		}
+		Ok(freed_bytes)
+	}
+
+	/*
+		Checks that the save files of the language can be read. A damaged sentence index is removed since it is built again 
+		when the language is loaded, which is also why a missing one is fine.
+	*/
+	pub fn verify_files(language_index: usize, verification: &mut DataVerification) {
+		let file_name = Self::words_file_name(language_index);
+		let problem = save_file::load::<LearningWords>(&file_name).err()
+			.map(|error| format!("{}. Restoring a backup brings back the progress that was saved in it.", error));
+		verification.add(&file_name, problem, false);
+
+		let file_name = Self::sentences_file_name(language_index);
+		let problem = save_file::load::<LearningSentences>(&file_name).err().map(|error| error.to_string());
+		verification.add(&file_name, problem, false);
+
+		let file_name = Self::sentence_index_file_name(language_index);
+		match save_file::load::<SentenceIndex>(&file_name) {
+			Err(error) if !error.is_missing_file() => {
+				let removed = save_file::remove(&file_name).is_ok();
+				verification.add(&file_name, Some(error.to_string()), removed);
+			}
+			_ => verification.add(&file_name, None, false),
+		}
+	}
+
+	fn read_save_file<T: SaveFormat>(file_name: String) -> Result<T> {
+		save_file::load(&file_name).map_err(|error| Error::from(error).context(format!("loading {}", file_name)))
+	}
+	fn write_save_file<T: SaveFormat>(file_name: String, value: &T) -> Result<()> {
+		save_file::save(&file_name, value).map_err(|error| Error::from(error).context(format!("saving {}", file_name)))
+	}
 	
-	pub fn load_from_file(language_index: usize) -> Self {
-		let words: LearningWords = bincode::deserialize(&fs::read(Self::words_file_name(language_index)).unwrap()).unwrap();
-		let word_weighted_index = words.create_weighted_index();
-		Self { 
+	pub fn load_from_file(language_index: usize, options: &Options) -> Result<Self> {
+		let words: LearningWords = Self::read_save_file(Self::words_file_name(language_index))?;
+		let word_selector = create_word_selector(options.word_selection, &words.words);
+		let tokenizer = words.create_tokenizer(language_index);
+		let sentences = Self::read_save_file(Self::sentences_file_name(language_index))?;
+
+		// Save data from before the index existed, or where the index is out of sync with the words or sentences, gets a new 
+		// index.
+		let saved_sentence_index = save_file::load::<SentenceIndex>(Self::sentence_index_file_name(language_index)).ok()
+			.filter(|index| index.matches(&words, &sentences));
+		let sentence_index_modified = saved_sentence_index.is_none();
+		let sentence_index = saved_sentence_index.unwrap_or_else(|| SentenceIndex::new(&words, &sentences, &tokenizer));
+
+		Ok(Self { 
 			words, 
-			word_weighted_index,
-			sentences: bincode::deserialize(&fs::read(Self::sentences_file_name(language_index)).unwrap()).unwrap(),
+			word_selector,
+			rng: Self::create_rng(options.random_seed),
+			sentences,
+			sentence_index,
+			tokenizer,
+			unsaved_task_count: 0,
+			sentence_index_modified,
+			upcoming_tasks: VecDeque::new(),
+		})
+	}
+
+	fn save_sentence_index_to_file(&mut self, language_index: usize) -> Result<()> {
+		// Removing words changes the fingerprint, which is only worked out here since it goes through every sentence.
+		self.sentence_index.fingerprint = index_fingerprint(&self.words, &self.sentences);
+		Self::write_save_file(Self::sentence_index_file_name(language_index), &self.sentence_index)?;
+		self.sentence_index_modified = false;
+		Ok(())
+	}
+
+	pub fn save_sentences_to_file(&mut self, language_index: usize) -> Result<()> {
+		Self::write_save_file(Self::sentences_file_name(language_index), &self.sentences)?;
+		self.save_sentence_index_to_file(language_index)
+	}
+	pub fn save_words_to_file(&mut self, language_index: usize) -> Result<()> {
+		Self::write_save_file(Self::words_file_name(language_index), &self.words)?;
+		self.unsaved_task_count = 0;
+		// The index follows the word list, which changes when words that aren't in any sentence are removed.
+		if self.sentence_index_modified {
+			self.save_sentence_index_to_file(language_index)?;
+		}
+		Ok(())
+	}
+}
+
+//----------------------------------------------------------------
+
+/*
+	Layouts of the save data from before versioned save files, which didn't have word forms or review schedules.
+*/
+mod v0 {
+	use chrono::prelude::*;
+	use serde::{Deserialize, Serialize};
+
+	use super::HistoryData;
+
+	#[derive(Deserialize, Serialize)]
+	pub struct LearningWord {
+		pub word: String,
+		pub weight: f64,
+		pub long_term_memory: f32,
+		pub last_review: DateTime<Utc>,
+	}
+
+	#[derive(Deserialize, Serialize)]
+	pub struct LearningWords {
+		pub words: Vec<LearningWord>,
+		pub learned_word_count: HistoryData<u32>,
+		pub easy_word_count: HistoryData<u32>,
+	}
+}
+
+/*
+	The layout of sentences from before translations had a language.
+*/
+mod v1 {
+	use std::collections::HashMap;
+
+	use serde::{Deserialize, Serialize};
+
+	use super::SentenceId;
+
+	#[derive(Deserialize, Serialize)]
+	pub struct Translation {
+		pub id: SentenceId,
+		pub text: String,
+	}
+
+	#[derive(Deserialize, Serialize)]
+	pub struct LearningSentence {
+		pub original: String,
+		pub lowercase: String,
+		pub translations: Vec<Translation>,
+	}
+
+	#[derive(Deserialize, Serialize)]
+	pub struct LearningSentences(pub HashMap<SentenceId, LearningSentence>);
+}
+
+//----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const WORD_LIST: &str = "\
+the 500
+a 400
+cat 300
+dog 250
+sees 200
+bird 150
+likes 100
+fish 80
+unused 50
+";
+	const SENTENCE_LIST: &str = "\
+1\tThe cat sees a dog.\t101\tKatten ser en hund.
+1\tThe cat sees a dog.\t102\tKatten ser hunden.
+2\tThe dog sees a bird.\t103\tHunden ser en fågel.
+3\tA bird likes fish.\t104\tEn fågel gillar fisk.
+4\tThe cat likes the dog.\t105\tKatten gillar hunden.
+5\tDog!\t106\tHund!
+";
+
+	fn language_index(name: &str) -> usize {
+		source_data::LANGUAGES.iter().position(|language| language.name == name).unwrap()
+	}
+
+	fn sentence_list(translation_language: &str, data: &str) -> source_data::SentenceList {
+		source_data::SentenceList { translation_language_index: language_index(translation_language), data: data.as_bytes().to_vec() }
+	}
+
+	fn source_data() -> source_data::SourceData {
+		source_data::SourceData {
+			language_index: language_index("English"),
+			word_list: WORD_LIST.as_bytes().to_vec(),
+			sentence_lists: vec![sentence_list("Swedish", SENTENCE_LIST)],
+			lemma_list: None,
+		}
+	}
+
+	fn options(selection: WordSelection, seed: u64) -> Options {
+		let mut options = Options::new(source_data().language_index);
+		options.word_selection = selection;
+		options.random_seed = Some(seed);
+		options
+	}
+
+	// Runs a session where every third task is failed and returns all tasks that were given.
+	fn run_session(options: &Options) -> Vec<LearningTask> {
+		let mut learning_data = LearningData::load_from_source_data(&source_data(), options).unwrap();
+
+		(0..60).map(|i| {
+			let task = learning_data.next_task(options).unwrap();
+			let result = if i % 3 == 0 { WordReviewResult::Failed } else { WordReviewResult::Succeeded };
+			let word_reviews = task.review_words.iter()
+				.map(|word| FinishedWordReview { word_id: word.id, result })
+				.collect();
+			learning_data.finish_task(FinishedTask { word_reviews }, options);
+			task
+		}).collect()
+	}
+
+	#[test]
+	fn seeded_sessions_are_reproducible() {
+		for selection in [
+			WordSelection::Weighted,
+			WordSelection::FrequencyOrder,
+			WordSelection::WeakestFirst,
+			WordSelection::Mixed { new_word_ratio: 0.3 },
+			WordSelection::SpacedRepetition,
+		] {
+			let options = options(selection, 42);
+			assert_eq!(run_session(&options), run_session(&options), "{:?}", selection);
 		}
 	}
 
-	pub fn save_sentences_to_file(&self, language_index: usize) {
-		fs::create_dir_all(SAVE_DIRECTORY).unwrap();
-		fs::write(
-			Self::sentences_file_name(language_index), 
-			bincode::serialize(&self.sentences).unwrap()
-		).unwrap();
+	#[test]
+	fn different_seeds_give_different_sessions() {
+		assert_ne!(
+			run_session(&options(WordSelection::Weighted, 1)), 
+			run_session(&options(WordSelection::Weighted, 2))
+		);
 	}
-	pub fn save_words_to_file(&self, language_index: usize) {
-		fs::create_dir_all(SAVE_DIRECTORY).unwrap();
-		fs::write(
-			Self::words_file_name(language_index), 
-			bincode::serialize(&self.words).unwrap()
-		).unwrap();
+
+	#[test]
+	fn no_task_is_given_when_no_word_is_in_any_sentence() {
+		let options = options(WordSelection::Weighted, 3);
+		let source_data = source_data::SourceData {
+			word_list: "unused 50\nelephant 20\n".as_bytes().to_vec(),
+			..source_data()
+		};
+		let mut learning_data = LearningData::load_from_source_data(&source_data, &options).unwrap();
+
+		assert!(matches!(learning_data.next_task(&options), Err(Error::MissingResource(_))));
+		assert!(learning_data.words().words.is_empty());
+		assert!(matches!(learning_data.next_task(&options), Err(Error::MissingResource(_))));
+	}
+
+	#[test]
+	fn forms_are_grouped_by_lemma() {
+		let options = options(WordSelection::Weighted, 4);
+		let source_data = source_data::SourceData {
+			lemma_list: Some("see\tsees\nsee\tsaw\nlike\tlikes\ndog\tdogs\n".as_bytes().to_vec()),
+			..source_data()
+		};
+		let mut learning_data = LearningData::load_from_source_data(&source_data, &options).unwrap();
+
+		let words = &learning_data.words().words;
+		let see_id = words.iter().position(|word| word.word == "see").unwrap();
+		assert_eq!(words[see_id].forms, ["sees"]);
+		assert!(words.iter().all(|word| word.word != "sees"));
+		// The lemma "dog" is in the frequency list itself, so it is not listed as a form.
+		assert!(words.iter().find(|word| word.word == "dog").unwrap().forms.is_empty());
+
+		for _ in 0..100 {
+			let task = learning_data.next_task(&options).unwrap();
+			if task.review_words.iter().any(|word| word.id == see_id) {
+				assert!(task.review_words.iter().any(|word| word.id == see_id && word.word == "sees"));
+				return;
+			}
+			let word_reviews = task.review_words.iter()
+				.map(|word| FinishedWordReview { word_id: word.id, result: WordReviewResult::Succeeded })
+				.collect();
+			learning_data.finish_task(FinishedTask { word_reviews }, &options);
+		}
+		panic!("the word \"see\" was never reviewed");
+	}
+
+	#[test]
+	fn words_are_migrated_from_version_0() {
+		let last_review = Utc.with_ymd_and_hms(2023, 8, 1, 12, 0, 0).unwrap();
+		let mut learned_word_count = HistoryData::new();
+		learned_word_count.add_point(1);
+		let fixture = bincode::serialize(&v0::LearningWords {
+			words: vec![
+				v0::LearningWord { word: "cat".to_owned(), weight: 0.5, long_term_memory: 0.95, last_review },
+				v0::LearningWord { word: "dog".to_owned(), weight: 0.25, long_term_memory: 0.3, last_review: Utc.timestamp_nanos(0) },
+			],
+			learned_word_count,
+			easy_word_count: HistoryData::new(),
+		}).unwrap();
+
+		let words: LearningWords = save_file::deserialize(&fixture).unwrap();
+		assert_eq!(words.words.len(), 2);
+		assert_eq!(words.words[0].word, "cat");
+		assert_eq!(words.words[0].weight, 0.5);
+		assert_eq!(words.words[0].long_term_memory, 0.95);
+		assert_eq!(words.words[0].last_review, last_review);
+		assert!(words.words[0].forms.is_empty());
+		assert!(words.words[0].schedule.is_none());
+		assert!(!words.words[1].has_been_reviewed());
+		assert_eq!(words.learned_word_count.get_latest(), 1);
+	}
+
+	#[test]
+	fn sentences_are_migrated_from_version_0() {
+		// Version 0 had the layout of version 1 without the header.
+		let translation = |id, text: &str| v1::Translation { id, text: text.to_owned() };
+		let sentence = v1::LearningSentence {
+			original: "The cat sees a dog.".to_owned(),
+			lowercase: "the cat sees a dog.".to_owned(),
+			translations: vec![translation(101, "Katten ser en hund."), translation(102, "Katten ser hunden.")],
+		};
+		let fixture = bincode::serialize(&v1::LearningSentences(HashMap::from([(1, sentence)]))).unwrap();
+
+		let mut sentences: LearningSentences = save_file::deserialize(&fixture).unwrap();
+		assert_eq!(sentences.0.len(), 1);
+		assert_eq!(sentences.0[&1].original, "The cat sees a dog.");
+		assert_eq!(sentences.0[&1].translations.len(), 2);
+		assert!(sentences.translation_languages().is_empty());
+
+		// Downloading the same translations again fills in their language without duplicating them.
+		sentences.add_translations(&sentence_list("Swedish", SENTENCE_LIST));
+		assert_eq!(sentences.0[&1].translations.len(), 2);
+		assert_eq!(sentences.translation_languages(), [language_index("Swedish")]);
+	}
+
+	#[test]
+	fn sentences_are_migrated_from_version_1() {
+		let sentence = v1::LearningSentence {
+			original: "Dog!".to_owned(),
+			lowercase: "dog!".to_owned(),
+			translations: vec![v1::Translation { id: 106, text: "Hund!".to_owned() }],
+		};
+		let fixture = save_file::serialize_version(1, &v1::LearningSentences(HashMap::from([(5, sentence)])));
+
+		let sentences: LearningSentences = save_file::deserialize(&fixture).unwrap();
+		assert_eq!(sentences.0[&5].lowercase, "dog!");
+		assert_eq!(sentences.0[&5].translations[0].text, "Hund!");
+		assert_eq!(sentences.0[&5].translations[0].language_index, None);
+	}
+
+	#[test]
+	fn translations_of_unknown_language_are_only_kept_if_downloaded_again() {
+		let options = options(WordSelection::Weighted, 7);
+		let mut learning_data = LearningData::load_from_source_data(&source_data(), &options).unwrap();
+		for sentence in learning_data.sentences.0.values_mut() {
+			for translation in &mut sentence.translations {
+				translation.language_index = None;
+			}
+		}
+		assert!(learning_data.translation_languages().is_empty());
+
+		// The translations can't be told apart from the ones of the new language, so they are removed.
+		let german = language_index("German");
+		let german_list = sentence_list("German", "2\tThe dog sees a bird.\t203\tDer Hund sieht einen Vogel.\n");
+		learning_data.set_translation_languages(&[german], &[german_list]).unwrap();
+		assert_eq!(learning_data.sentences.0.keys().collect::<Vec<_>>(), [&2]);
+		assert_eq!(learning_data.sentences.0[&2].translations.len(), 1);
+
+		// Downloading the same translations again keeps them.
+		for sentence in learning_data.sentences.0.values_mut() {
+			sentence.translations[0].language_index = None;
+		}
+		let german_list = sentence_list("German", "2\tThe dog sees a bird.\t203\tDer Hund sieht einen Vogel.\n");
+		learning_data.set_translation_languages(&[german], &[german_list]).unwrap();
+		assert_eq!(learning_data.translation_languages(), [german]);
+	}
+
+	#[test]
+	fn translation_languages_are_added_and_removed_without_changing_words() {
+		let options = options(WordSelection::Weighted, 6);
+		let mut learning_data = LearningData::load_from_source_data(&source_data(), &options).unwrap();
+		for _ in 0..10 {
+			let task = learning_data.next_task(&options).unwrap();
+			let word_reviews = task.review_words.iter()
+				.map(|word| FinishedWordReview { word_id: word.id, result: WordReviewResult::Succeeded })
+				.collect();
+			learning_data.finish_task(FinishedTask { word_reviews }, &options);
+		}
+		let words = bincode::serialize(&learning_data.words).unwrap();
+
+		let german = language_index("German");
+		let swedish = language_index("Swedish");
+		let german_list = sentence_list("German", "\
+1\tThe cat sees a dog.\t201\tDie Katze sieht einen Hund.
+6\tThe fish likes the cat.\t202\tDer Fisch mag die Katze.
+");
+		let mut expected_languages = vec![german, swedish];
+		expected_languages.sort_unstable();
+		learning_data.set_translation_languages(&expected_languages, &[german_list]).unwrap();
+		assert_eq!(learning_data.translation_languages(), expected_languages);
+		assert_eq!(learning_data.sentences.0[&1].translations.len(), 3);
+
+		// Neither language would be left, so nothing changes.
+		let danish = language_index("Danish");
+		assert!(matches!(learning_data.set_translation_languages(&[danish], &[]), Err(Error::InvalidRequest(_))));
+		assert_eq!(learning_data.translation_languages(), expected_languages);
+
+		learning_data.set_translation_languages(&[german], &[]).unwrap();
+		assert_eq!(learning_data.translation_languages(), [german]);
+		let mut sentence_ids: Vec<_> = learning_data.sentences.0.keys().copied().collect();
+		sentence_ids.sort_unstable();
+		assert_eq!(sentence_ids, [1, 6]);
+
+		let fish_id = learning_data.words.words.iter().position(|word| word.word == "fish").unwrap();
+		assert_eq!(learning_data.sentence_index.sentences_with_word(fish_id), [6]);
+		assert_eq!(bincode::serialize(&learning_data.words).unwrap(), words);
+	}
+
+	#[test]
+	fn sentence_index_round_trip() {
+		let options = options(WordSelection::Weighted, 5);
+		let learning_data = LearningData::load_from_source_data(&source_data(), &options).unwrap();
+
+		let index: SentenceIndex = save_file::deserialize(&save_file::serialize(&learning_data.sentence_index)).unwrap();
+		assert_eq!(index.sentences_with_word, learning_data.sentence_index.sentences_with_word);
+		assert!(index.matches(&learning_data.words, &learning_data.sentences));
+	}
+
+	#[test]
+	fn stale_sentence_index_is_not_used() {
+		let options = options(WordSelection::Weighted, 9);
+		let learning_data = LearningData::load_from_source_data(&source_data(), &options).unwrap();
+		let index = &learning_data.sentence_index;
+
+		// A different word list of the same length, like the one of a restored backup.
+		let mut words: LearningWords = save_file::deserialize(&save_file::serialize(&learning_data.words)).unwrap();
+		words.words.swap(2, 3);
+		assert!(!index.matches(&words, &learning_data.sentences));
+
+		let mut sentences: LearningSentences = save_file::deserialize(&save_file::serialize(&learning_data.sentences)).unwrap();
+		sentences.0.remove(&2);
+		assert!(!index.matches(&learning_data.words, &sentences));
+
+		// Checked on its own, since the fingerprint would already tell the sentences apart.
+		let mut index: SentenceIndex = save_file::deserialize(&save_file::serialize(index)).unwrap();
+		index.fingerprint = index_fingerprint(&learning_data.words, &sentences);
+		assert!(!index.matches(&learning_data.words, &sentences));
+	}
+
+	#[test]
+	fn sentences_are_refreshed_from_a_newer_export() {
+		let options = options(WordSelection::Weighted, 7);
+		let mut learning_data = LearningData::load_from_source_data(&source_data(), &options).unwrap();
+		let words = bincode::serialize(&learning_data.words).unwrap();
+
+		// Sentence 2 was deleted, 3 was edited, 1 lost a translation and 4 got one, and sentence 7 is new.
+		let changes = learning_data.refresh_sentences(&[sentence_list("Swedish", "\
+1\tThe cat sees a dog.\t101\tKatten ser en hund.
+3\tA bird likes the fish.\t104\tEn fågel gillar fisken.
+4\tThe cat likes the dog.\t105\tKatten gillar hunden.
+4\tThe cat likes the dog.\t107\tKatten tycker om hunden.
+5\tDog!\t106\tHund!
+7\tA cat likes fish.\t108\tEn katt gillar fisk.
+")]);
+
+		assert_eq!(changes, SentenceChanges {
+			added_sentences: 1,
+			removed_sentences: 1,
+			updated_sentences: 1,
+			added_translations: 2,
+			removed_translations: 2,
+		});
+		let sentences = &learning_data.sentences.0;
+		assert!(!sentences.contains_key(&2));
+		assert_eq!(sentences[&1].translations.len(), 1);
+		assert_eq!(sentences[&3].lowercase, "a bird likes the fish.");
+		assert_eq!(sentences[&3].translations[0].text, "En fågel gillar fisken.");
+		assert_eq!(sentences[&4].translations.len(), 2);
+
+		let bird_id = learning_data.words.words.iter().position(|word| word.word == "bird").unwrap();
+		assert_eq!(learning_data.sentence_index.sentences_with_word(bird_id), [3]);
+		assert_eq!(bincode::serialize(&learning_data.words).unwrap(), words);
+	}
+
+	#[test]
+	fn upcoming_tasks_are_given_out_in_order() {
+		let options = options(WordSelection::Weighted, 8);
+		let mut learning_data = LearningData::load_from_source_data(&source_data(), &options).unwrap();
+
+		learning_data.next_task(&options).unwrap();
+		let upcoming: Vec<SentenceId> = learning_data.upcoming_tasks().map(|task| task.sentence_id).collect();
+		assert_eq!(upcoming.len(), UPCOMING_TASK_COUNT);
+		for sentence_id in upcoming {
+			assert_eq!(learning_data.next_task(&options).unwrap().sentence_id, sentence_id);
+		}
+
+		learning_data.set_random_seed(Some(8));
+		assert_eq!(learning_data.upcoming_tasks().count(), 0);
+	}
+
+	#[test]
+	fn tasks_review_the_words_they_contain() {
+		let options = options(WordSelection::Weighted, 3);
+		for task in run_session(&options) {
+			assert!(!task.review_words.is_empty());
+			for word in &task.review_words {
+				let position_in_sentence: String = task.sentence.chars().skip(word.position).take(word.word.chars().count()).collect();
+				assert_eq!(position_in_sentence, word.word);
+			}
+		}
 	}
 }
@@ -2,11 +2,25 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod app;
+mod audio_cache;
+mod audio_requests;
+mod backup;
+mod download;
+mod error;
+mod integrity;
 mod learning_data;
 mod options;
+mod save_file;
+mod scheduler;
 mod sentence_audio;
+mod source_config;
 mod source_data;
-mod util;
+mod tokenizer;
+mod voice_catalogue;
+mod voice_models;
+mod voices;
+mod word_selection;
+mod worker_pool;
 
 fn main() {
 	app::run();
@@ -1,10 +1,22 @@
-use std::fs;
-use std::path::Path;
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    audio_cache,
+    error::{Error, Result},
+    save_file::{self, LoadError, SaveFormat},
+    scheduler::SchedulingAlgorithm,
+    source_data,
+    voice_models,
+    voices,
+    word_selection::WordSelection,
+    worker_pool,
+};
+
 //----------------------------------------------------------------
 
-const OPTIONS_SAVE_FILE: &str = "save_data/options";
+pub const OPTIONS_SAVE_FILE: &str = "save_data/options";
 
 //----------------------------------------------------------------
 
@@ -57,28 +69,91 @@ impl Default for WordMemoryParameters {
     }
 }
 
+#[derive(Copy, Clone, Deserialize, Serialize)]
+pub struct SentenceDifficultyParameters {
+    // How strongly sentences that are easy to understand are preferred. Zero makes all sentences with the word equally likely.
+    pub bias: f32,
+    // The number of learned words at which the bias has weakened to half of its initial strength.
+    pub bias_half_learned_count: f32,
+    // How much each word in a sentence adds to its difficulty no matter how well it is known.
+    pub length_weight: f32,
+}
+
+impl SentenceDifficultyParameters {
+    pub fn current_bias(&self, learned_word_count: u32) -> f32 {
+        self.bias * f32::exp2(-(learned_word_count as f32) / self.bias_half_learned_count)
+    }
+
+    /*
+        A negative bias or length weight would prefer the hardest sentences, and the bias can only weaken over a positive
+        number of learned words.
+    */
+    pub fn check(&self) -> Result<()> {
+        if !self.bias.is_finite() || self.bias < 0. {
+            return Err(Error::InvalidRequest(format!("the sentence difficulty bias can't be {}", self.bias)));
+        }
+        if !self.bias_half_learned_count.is_finite() || self.bias_half_learned_count <= 0. {
+            return Err(Error::InvalidRequest(format!(
+                "the learned word count that halves the bias can't be {}", self.bias_half_learned_count
+            )));
+        }
+        if !self.length_weight.is_finite() || self.length_weight < 0. {
+            return Err(Error::InvalidRequest(format!("the sentence length weight can't be {}", self.length_weight)));
+        }
+        Ok(())
+    }
+}
+
+impl Default for SentenceDifficultyParameters {
+    fn default() -> Self {
+        Self {
+            bias: 1.5,
+            bias_half_learned_count: 1000.,
+            length_weight: 0.1,
+        }
+    }
+}
+
 #[derive(Clone, Deserialize, Serialize)]
 pub struct Options {
     pub language_index: usize,
     pub saved_languages: Vec<usize>,
     pub weight_factors: WeightFactors,
     pub word_memory_parameters: WordMemoryParameters,
+    pub sentence_difficulty: SentenceDifficultyParameters,
+    pub word_selection: WordSelection,
+    pub scheduling_algorithm: SchedulingAlgorithm,
+    // Seed for choosing words and sentences. None gives a different sequence of tasks every time, which is what you want 
+    // unless you are trying to reproduce a particular session.
+    pub random_seed: Option<u64>,
     pub hide_translations_by_default: bool,
     pub skip_correct_feedback: bool,
     pub play_audio_automatically: bool,
+    // The Piper voices that are loaded for speech synthesis, by language index.
+    pub enabled_voices: BTreeMap<usize, Vec<String>>,
+    // How much space cached sentence audio may take up, see audio_cache.rs.
+    pub audio_cache_size_limit_bytes: u64,
+    // How many voices generate speech at the same time, see sentence_audio.rs. A single voice generates one clip at a time
+    // however many threads there are.
+    pub synthesis_thread_count: usize,
+    // How much memory the loaded voice models are estimated to take up at most, see voice_models.rs.
+    pub voice_model_estimated_memory_budget_bytes: u64,
 }
 
 impl Options {
-    pub fn save(&self) {
-        if let Some(directory) = Path::new(OPTIONS_SAVE_FILE).parent() {
-            fs::create_dir_all(directory).unwrap();
-        }
-		let file = std::fs::File::create(OPTIONS_SAVE_FILE).unwrap();
-		bincode::serialize_into(file, &self).unwrap();
+    pub fn save(&self) -> Result<()> {
+        save_file::save(OPTIONS_SAVE_FILE, self).map_err(|error| Error::from(error).context("saving options"))
     }
     
-    pub fn load() -> Option<Self> {
-        bincode::deserialize_from(std::fs::File::open(OPTIONS_SAVE_FILE).ok()?).ok()
+    /*
+        Returns None if no options have been saved yet, which means that no language has been added.
+    */
+    pub fn load() -> Result<Option<Self>> {
+        match save_file::load(OPTIONS_SAVE_FILE) {
+            Ok(options) => Ok(Some(options)),
+            Err(error) if error.is_missing_file() => Ok(None),
+            Err(error) => Err(Error::from(error).context("loading options")),
+        }
     }
     pub fn new(language_index: usize) -> Self {
         Self {
@@ -86,9 +161,464 @@ impl Options {
             saved_languages: vec![language_index],
             weight_factors: WeightFactors::default(),
             word_memory_parameters: WordMemoryParameters::default(),
+            sentence_difficulty: SentenceDifficultyParameters::default(),
+            word_selection: WordSelection::Weighted,
+            scheduling_algorithm: SchedulingAlgorithm::Fsrs,
+            random_seed: None,
             hide_translations_by_default: false,
             skip_correct_feedback: false,
             play_audio_automatically: true,
+            enabled_voices: BTreeMap::from([(language_index, voices::initial_voices(language_index))]),
+            audio_cache_size_limit_bytes: audio_cache::DEFAULT_SIZE_LIMIT_BYTES,
+            synthesis_thread_count: worker_pool::default_thread_count(),
+            voice_model_estimated_memory_budget_bytes: voice_models::DEFAULT_ESTIMATED_MEMORY_BUDGET_BYTES,
+        }
+    }
+
+    pub fn current_voices(&self) -> &[String] {
+        self.enabled_voices.get(&self.language_index).map_or(&[], Vec::as_slice)
+    }
+
+    /*
+        The voices enabled for saved languages other than the given one, which must not be deleted along with it.
+    */
+    pub fn voices_in_use_except(&self, language_index: usize) -> Vec<String> {
+        self.enabled_voices.iter()
+            .filter(|&(&i, _)| i != language_index && self.saved_languages.contains(&i))
+            .flat_map(|(_, voices)| voices.iter().cloned())
+            .collect()
+    }
+}
+
+impl From<v0::Options> for v1::Options {
+    fn from(options: v0::Options) -> Self {
+        let defaults = Options::new(options.language_index);
+        Self {
+            language_index: options.language_index,
+            saved_languages: options.saved_languages,
+            weight_factors: options.weight_factors,
+            word_memory_parameters: options.word_memory_parameters,
+            sentence_difficulty: defaults.sentence_difficulty,
+            word_selection: defaults.word_selection,
+            scheduling_algorithm: defaults.scheduling_algorithm,
+            random_seed: defaults.random_seed,
+            hide_translations_by_default: options.hide_translations_by_default,
+            skip_correct_feedback: options.skip_correct_feedback,
+            play_audio_automatically: options.play_audio_automatically,
+        }
+    }
+}
+
+impl From<v1::Options> for v2::Options {
+    fn from(options: v1::Options) -> Self {
+        // All installed voices of a language used to be loaded, so they all stay enabled.
+        let enabled_voices = options.saved_languages.iter()
+            .map(|&i| {
+                let installed_voices = voices::voices_in_directory(&source_data::voice_directory(), i);
+                (i, if installed_voices.is_empty() { voices::initial_voices(i) } else { installed_voices })
+            })
+            .collect();
+        Self {
+            language_index: options.language_index,
+            saved_languages: options.saved_languages,
+            weight_factors: options.weight_factors,
+            word_memory_parameters: options.word_memory_parameters,
+            sentence_difficulty: options.sentence_difficulty,
+            word_selection: options.word_selection,
+            scheduling_algorithm: options.scheduling_algorithm,
+            random_seed: options.random_seed,
+            hide_translations_by_default: options.hide_translations_by_default,
+            skip_correct_feedback: options.skip_correct_feedback,
+            play_audio_automatically: options.play_audio_automatically,
+            enabled_voices,
+        }
+    }
+}
+
+impl From<v2::Options> for v3::Options {
+    fn from(options: v2::Options) -> Self {
+        Self {
+            language_index: options.language_index,
+            saved_languages: options.saved_languages,
+            weight_factors: options.weight_factors,
+            word_memory_parameters: options.word_memory_parameters,
+            sentence_difficulty: options.sentence_difficulty,
+            word_selection: options.word_selection,
+            scheduling_algorithm: options.scheduling_algorithm,
+            random_seed: options.random_seed,
+            hide_translations_by_default: options.hide_translations_by_default,
+            skip_correct_feedback: options.skip_correct_feedback,
+            play_audio_automatically: options.play_audio_automatically,
+            enabled_voices: options.enabled_voices,
+            audio_cache_size_limit_bytes: audio_cache::DEFAULT_SIZE_LIMIT_BYTES,
+        }
+    }
+}
+
+impl From<v3::Options> for v4::Options {
+    fn from(options: v3::Options) -> Self {
+        Self {
+            language_index: options.language_index,
+            saved_languages: options.saved_languages,
+            weight_factors: options.weight_factors,
+            word_memory_parameters: options.word_memory_parameters,
+            sentence_difficulty: options.sentence_difficulty,
+            word_selection: options.word_selection,
+            scheduling_algorithm: options.scheduling_algorithm,
+            random_seed: options.random_seed,
+            hide_translations_by_default: options.hide_translations_by_default,
+            skip_correct_feedback: options.skip_correct_feedback,
+            play_audio_automatically: options.play_audio_automatically,
+            enabled_voices: options.enabled_voices,
+            audio_cache_size_limit_bytes: options.audio_cache_size_limit_bytes,
+            synthesis_thread_count: worker_pool::default_thread_count(),
+        }
+    }
+}
+
+impl From<v4::Options> for Options {
+    fn from(options: v4::Options) -> Self {
+        Self {
+            language_index: options.language_index,
+            saved_languages: options.saved_languages,
+            weight_factors: options.weight_factors,
+            word_memory_parameters: options.word_memory_parameters,
+            sentence_difficulty: options.sentence_difficulty,
+            word_selection: options.word_selection,
+            scheduling_algorithm: options.scheduling_algorithm,
+            random_seed: options.random_seed,
+            hide_translations_by_default: options.hide_translations_by_default,
+            skip_correct_feedback: options.skip_correct_feedback,
+            play_audio_automatically: options.play_audio_automatically,
+            enabled_voices: options.enabled_voices,
+            audio_cache_size_limit_bytes: options.audio_cache_size_limit_bytes,
+            synthesis_thread_count: options.synthesis_thread_count,
+            voice_model_estimated_memory_budget_bytes: voice_models::DEFAULT_ESTIMATED_MEMORY_BUDGET_BYTES,
+        }
+    }
+}
+
+impl SaveFormat for Options {
+    // Version 1 added the sentence difficulty, word selection, scheduling algorithm and random seed options.
+    // Version 2 added the enabled voices.
+    // Version 3 added the audio cache size limit.
+    // Version 4 added the synthesis thread count.
+    // Version 5 added the voice model memory budget.
+    const VERSION: u32 = 5;
+
+    fn upgrade(version: u32, data: &[u8]) -> std::result::Result<Vec<u8>, LoadError> {
+        match version {
+            0 => save_file::upgrade_step::<v0::Options, v1::Options>(data),
+            1 => save_file::upgrade_step::<v1::Options, v2::Options>(data),
+            2 => save_file::upgrade_step::<v2::Options, v3::Options>(data),
+            3 => save_file::upgrade_step::<v3::Options, v4::Options>(data),
+            4 => save_file::upgrade_step::<v4::Options, Options>(data),
+            _ => Err(LoadError::UnsupportedVersion(version)),
+        }
+    }
+}
+
+//----------------------------------------------------------------
+
+/*
+    Layout of the options from before versioned save files.
+*/
+mod v0 {
+    use serde::{Deserialize, Serialize};
+
+    use super::{WeightFactors, WordMemoryParameters};
+
+    #[derive(Deserialize, Serialize)]
+    pub struct Options {
+        pub language_index: usize,
+        pub saved_languages: Vec<usize>,
+        pub weight_factors: WeightFactors,
+        pub word_memory_parameters: WordMemoryParameters,
+        pub hide_translations_by_default: bool,
+        pub skip_correct_feedback: bool,
+        pub play_audio_automatically: bool,
+    }
+}
+
+/*
+    Layout of the options before voices could be enabled individually.
+*/
+mod v1 {
+    use serde::{Deserialize, Serialize};
+
+    use crate::{scheduler::SchedulingAlgorithm, word_selection::WordSelection};
+
+    use super::{SentenceDifficultyParameters, WeightFactors, WordMemoryParameters};
+
+    #[derive(Deserialize, Serialize)]
+    pub struct Options {
+        pub language_index: usize,
+        pub saved_languages: Vec<usize>,
+        pub weight_factors: WeightFactors,
+        pub word_memory_parameters: WordMemoryParameters,
+        pub sentence_difficulty: SentenceDifficultyParameters,
+        pub word_selection: WordSelection,
+        pub scheduling_algorithm: SchedulingAlgorithm,
+        pub random_seed: Option<u64>,
+        pub hide_translations_by_default: bool,
+        pub skip_correct_feedback: bool,
+        pub play_audio_automatically: bool,
+    }
+}
+
+/*
+    Layout of the options before sentence audio was cached.
+*/
+mod v2 {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::{scheduler::SchedulingAlgorithm, word_selection::WordSelection};
+
+    use super::{SentenceDifficultyParameters, WeightFactors, WordMemoryParameters};
+
+    #[derive(Deserialize, Serialize)]
+    pub struct Options {
+        pub language_index: usize,
+        pub saved_languages: Vec<usize>,
+        pub weight_factors: WeightFactors,
+        pub word_memory_parameters: WordMemoryParameters,
+        pub sentence_difficulty: SentenceDifficultyParameters,
+        pub word_selection: WordSelection,
+        pub scheduling_algorithm: SchedulingAlgorithm,
+        pub random_seed: Option<u64>,
+        pub hide_translations_by_default: bool,
+        pub skip_correct_feedback: bool,
+        pub play_audio_automatically: bool,
+        pub enabled_voices: BTreeMap<usize, Vec<String>>,
+    }
+}
+
+/*
+    Layout of the options before the number of synthesis threads could be set.
+*/
+mod v3 {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::{scheduler::SchedulingAlgorithm, word_selection::WordSelection};
+
+    use super::{SentenceDifficultyParameters, WeightFactors, WordMemoryParameters};
+
+    #[derive(Deserialize, Serialize)]
+    pub struct Options {
+        pub language_index: usize,
+        pub saved_languages: Vec<usize>,
+        pub weight_factors: WeightFactors,
+        pub word_memory_parameters: WordMemoryParameters,
+        pub sentence_difficulty: SentenceDifficultyParameters,
+        pub word_selection: WordSelection,
+        pub scheduling_algorithm: SchedulingAlgorithm,
+        pub random_seed: Option<u64>,
+        pub hide_translations_by_default: bool,
+        pub skip_correct_feedback: bool,
+        pub play_audio_automatically: bool,
+        pub enabled_voices: BTreeMap<usize, Vec<String>>,
+        pub audio_cache_size_limit_bytes: u64,
+    }
+}
+
+/*
+    Layout of the options before voice models were loaded on demand.
+*/
+mod v4 {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::{scheduler::SchedulingAlgorithm, word_selection::WordSelection};
+
+    use super::{SentenceDifficultyParameters, WeightFactors, WordMemoryParameters};
+
+    #[derive(Deserialize, Serialize)]
+    pub struct Options {
+        pub language_index: usize,
+        pub saved_languages: Vec<usize>,
+        pub weight_factors: WeightFactors,
+        pub word_memory_parameters: WordMemoryParameters,
+        pub sentence_difficulty: SentenceDifficultyParameters,
+        pub word_selection: WordSelection,
+        pub scheduling_algorithm: SchedulingAlgorithm,
+        pub random_seed: Option<u64>,
+        pub hide_translations_by_default: bool,
+        pub skip_correct_feedback: bool,
+        pub play_audio_automatically: bool,
+        pub enabled_voices: BTreeMap<usize, Vec<String>>,
+        pub audio_cache_size_limit_bytes: u64,
+        pub synthesis_thread_count: usize,
+    }
+}
+
+//----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sentence_difficulty_parameters_are_checked() {
+        let parameters = SentenceDifficultyParameters::default();
+        assert!(parameters.check().is_ok());
+        assert!(SentenceDifficultyParameters { bias: 0., ..parameters }.check().is_ok());
+
+        for invalid in [
+            SentenceDifficultyParameters { bias: -1., ..parameters },
+            SentenceDifficultyParameters { bias: f32::NAN, ..parameters },
+            SentenceDifficultyParameters { bias_half_learned_count: 0., ..parameters },
+            SentenceDifficultyParameters { bias_half_learned_count: -10., ..parameters },
+            SentenceDifficultyParameters { bias_half_learned_count: f32::INFINITY, ..parameters },
+            SentenceDifficultyParameters { length_weight: -0.1, ..parameters },
+        ] {
+            assert!(matches!(invalid.check(), Err(Error::InvalidRequest(_))));
         }
     }
+
+    #[test]
+    fn options_are_migrated_from_version_0() {
+        let fixture = bincode::serialize(&v0::Options {
+            language_index: 3,
+            saved_languages: vec![3, 5],
+            weight_factors: WeightFactors { succeeded: 0.5, failed: 3. },
+            word_memory_parameters: WordMemoryParameters::default(),
+            hide_translations_by_default: true,
+            skip_correct_feedback: true,
+            play_audio_automatically: false,
+        }).unwrap();
+
+        let options: Options = save_file::deserialize(&fixture).unwrap();
+        assert_eq!(options.language_index, 3);
+        assert_eq!(options.saved_languages, [3, 5]);
+        assert_eq!(options.weight_factors.failed, 3.);
+        assert!(options.hide_translations_by_default);
+        assert!(options.skip_correct_feedback);
+        assert!(!options.play_audio_automatically);
+        assert_eq!(options.word_selection, WordSelection::Weighted);
+        assert_eq!(options.scheduling_algorithm, SchedulingAlgorithm::Fsrs);
+        assert_eq!(options.random_seed, None);
+        assert_eq!(options.enabled_voices.len(), 2);
+    }
+
+    #[test]
+    fn all_voices_stay_enabled_when_migrating_from_version_1() {
+        let finnish = source_data::language_index("Finnish").unwrap();
+        let fixture = save_file::serialize_version(1, &v1::Options {
+            language_index: finnish,
+            saved_languages: vec![finnish],
+            weight_factors: WeightFactors::default(),
+            word_memory_parameters: WordMemoryParameters::default(),
+            sentence_difficulty: SentenceDifficultyParameters::default(),
+            word_selection: WordSelection::Weighted,
+            scheduling_algorithm: SchedulingAlgorithm::Fsrs,
+            random_seed: Some(4),
+            hide_translations_by_default: false,
+            skip_correct_feedback: false,
+            play_audio_automatically: true,
+        });
+
+        let options: Options = save_file::deserialize(&fixture).unwrap();
+        assert_eq!(options.random_seed, Some(4));
+        // No voices are installed where the tests run.
+        assert_eq!(options.current_voices(), voices::initial_voices(finnish));
+        assert_eq!(options.audio_cache_size_limit_bytes, audio_cache::DEFAULT_SIZE_LIMIT_BYTES);
+        assert!(options.synthesis_thread_count >= 1);
+        assert_eq!(options.voice_model_estimated_memory_budget_bytes, voice_models::DEFAULT_ESTIMATED_MEMORY_BUDGET_BYTES);
+    }
+
+    fn enabled_voices() -> BTreeMap<usize, Vec<String>> {
+        BTreeMap::from([(2, vec!["fi_FI/harri/medium".to_owned()])])
+    }
+
+    #[test]
+    fn options_are_migrated_from_version_2() {
+        let fixture = save_file::serialize_version(2, &v2::Options {
+            language_index: 2,
+            saved_languages: vec![2],
+            weight_factors: WeightFactors::default(),
+            word_memory_parameters: WordMemoryParameters::default(),
+            sentence_difficulty: SentenceDifficultyParameters::default(),
+            word_selection: WordSelection::WeakestFirst,
+            scheduling_algorithm: SchedulingAlgorithm::Fsrs,
+            random_seed: None,
+            hide_translations_by_default: false,
+            skip_correct_feedback: true,
+            play_audio_automatically: true,
+            enabled_voices: enabled_voices(),
+        });
+
+        let options: Options = save_file::deserialize(&fixture).unwrap();
+        assert_eq!(options.word_selection, WordSelection::WeakestFirst);
+        assert!(options.skip_correct_feedback);
+        assert_eq!(options.enabled_voices, enabled_voices());
+        assert_eq!(options.audio_cache_size_limit_bytes, audio_cache::DEFAULT_SIZE_LIMIT_BYTES);
+        assert_eq!(options.synthesis_thread_count, worker_pool::default_thread_count());
+        assert_eq!(options.voice_model_estimated_memory_budget_bytes, voice_models::DEFAULT_ESTIMATED_MEMORY_BUDGET_BYTES);
+    }
+
+    #[test]
+    fn options_are_migrated_from_version_3() {
+        let fixture = save_file::serialize_version(3, &v3::Options {
+            language_index: 2,
+            saved_languages: vec![2],
+            weight_factors: WeightFactors::default(),
+            word_memory_parameters: WordMemoryParameters::default(),
+            sentence_difficulty: SentenceDifficultyParameters::default(),
+            word_selection: WordSelection::Weighted,
+            scheduling_algorithm: SchedulingAlgorithm::Fsrs,
+            random_seed: Some(1),
+            hide_translations_by_default: true,
+            skip_correct_feedback: false,
+            play_audio_automatically: false,
+            enabled_voices: enabled_voices(),
+            audio_cache_size_limit_bytes: 1234,
+        });
+
+        let options: Options = save_file::deserialize(&fixture).unwrap();
+        assert_eq!(options.random_seed, Some(1));
+        assert_eq!(options.enabled_voices, enabled_voices());
+        assert_eq!(options.audio_cache_size_limit_bytes, 1234);
+        assert_eq!(options.synthesis_thread_count, worker_pool::default_thread_count());
+        assert_eq!(options.voice_model_estimated_memory_budget_bytes, voice_models::DEFAULT_ESTIMATED_MEMORY_BUDGET_BYTES);
+    }
+
+    #[test]
+    fn options_are_migrated_from_version_4() {
+        let fixture = save_file::serialize_version(4, &v4::Options {
+            language_index: 2,
+            saved_languages: vec![2],
+            weight_factors: WeightFactors::default(),
+            word_memory_parameters: WordMemoryParameters::default(),
+            sentence_difficulty: SentenceDifficultyParameters::default(),
+            word_selection: WordSelection::Weighted,
+            scheduling_algorithm: SchedulingAlgorithm::Fsrs,
+            random_seed: None,
+            hide_translations_by_default: false,
+            skip_correct_feedback: false,
+            play_audio_automatically: true,
+            enabled_voices: enabled_voices(),
+            audio_cache_size_limit_bytes: 1234,
+            synthesis_thread_count: 7,
+        });
+
+        let options: Options = save_file::deserialize(&fixture).unwrap();
+        assert_eq!(options.enabled_voices, enabled_voices());
+        assert_eq!(options.audio_cache_size_limit_bytes, 1234);
+        assert_eq!(options.synthesis_thread_count, 7);
+        assert_eq!(options.voice_model_estimated_memory_budget_bytes, voice_models::DEFAULT_ESTIMATED_MEMORY_BUDGET_BYTES);
+    }
+
+    #[test]
+    fn options_round_trip() {
+        let mut options = Options::new(2);
+        options.random_seed = Some(9);
+        let loaded: Options = save_file::deserialize(&save_file::serialize(&options)).unwrap();
+        assert_eq!(loaded.language_index, 2);
+        assert_eq!(loaded.random_seed, Some(9));
+    }
 }
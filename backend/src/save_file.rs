@@ -0,0 +1,259 @@
+use std::{
+	fs,
+	io::{self, Write},
+	path::Path,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+//----------------------------------------------------------------
+
+/*
+	Save files start with a header made up of a magic number and the schema version of the saved type, followed by the
+	bincode data. Files saved before the header was introduced don't have one and are read as version 0.
+*/
+const MAGIC: [u8; 4] = *b"GRKS";
+const VERSION_LEN: usize = std::mem::size_of::<u32>();
+
+//----------------------------------------------------------------
+
+#[derive(Debug)]
+pub enum LoadError {
+	Io(io::Error),
+	Parse(bincode::Error),
+	// The file was saved by a newer version of the program, or there is no migration from its version.
+	UnsupportedVersion(u32),
+}
+
+impl LoadError {
+	pub fn is_missing_file(&self) -> bool {
+		matches!(self, LoadError::Io(error) if error.kind() == io::ErrorKind::NotFound)
+	}
+}
+
+impl std::fmt::Display for LoadError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			LoadError::Io(error) => write!(f, "could not read save file: {}", error),
+			LoadError::Parse(error) => write!(f, "could not parse save file: {}", error),
+			LoadError::UnsupportedVersion(version) => write!(f, "unsupported save file version {}", version),
+		}
+	}
+}
+
+//----------------------------------------------------------------
+
+/*
+	A type that is saved to a file. When the serialized layout of the type changes, VERSION is increased, the previous layout
+	is kept in a module named after its version, and upgrade gets a step from it to the next layout. Older versions are 
+	upgraded one version at a time, so adding a version only adds a step and every step only has to know about the version 
+	right after it.
+*/
+pub trait SaveFormat: Serialize + DeserializeOwned {
+	const VERSION: u32;
+
+	/*
+		Upgrades the bincode data saved with the version to the layout of the next version, usually with upgrade_step.
+	*/
+	fn upgrade(version: u32, _data: &[u8]) -> Result<Vec<u8>, LoadError> {
+		Err(LoadError::UnsupportedVersion(version))
+	}
+}
+
+/*
+	Parses the bincode data of a save file without its header.
+*/
+pub fn parse<T: DeserializeOwned>(data: &[u8]) -> Result<T, LoadError> {
+	bincode::deserialize(data).map_err(LoadError::Parse)
+}
+
+/*
+	A single step of an upgrade, which parses the old layout and converts it to the next one through its From implementation.
+*/
+pub fn upgrade_step<Old, New>(data: &[u8]) -> Result<Vec<u8>, LoadError>
+	where Old: DeserializeOwned, New: From<Old> + Serialize
+{
+	let upgraded = New::from(parse::<Old>(data)?);
+	Ok(bincode::serialize(&upgraded).expect("save data should be serializable"))
+}
+
+pub fn serialize<T: SaveFormat>(value: &T) -> Vec<u8> {
+	serialize_version(T::VERSION, value)
+}
+
+/*
+	Serializes a value with the header of the version, which is also how tests write fixtures of earlier layouts.
+*/
+pub fn serialize_version<T: Serialize>(version: u32, value: &T) -> Vec<u8> {
+	let mut data = MAGIC.to_vec();
+	data.extend_from_slice(&version.to_le_bytes());
+	bincode::serialize_into(&mut data, value).expect("save data should be serializable");
+	data
+}
+
+pub fn deserialize<T: SaveFormat>(data: &[u8]) -> Result<T, LoadError> {
+	let (version, body) = match data.strip_prefix(&MAGIC) {
+		Some(rest) if rest.len() >= VERSION_LEN => {
+			let (version, body) = rest.split_at(VERSION_LEN);
+			(u32::from_le_bytes(version.try_into().unwrap()), body)
+		}
+		_ => (0, data),
+	};
+
+	match version.cmp(&T::VERSION) {
+		std::cmp::Ordering::Equal => parse(body),
+		std::cmp::Ordering::Less => {
+			let mut data = T::upgrade(version, body)?;
+			for version in version + 1..T::VERSION {
+				data = T::upgrade(version, &data)?;
+			}
+			parse(&data)
+		}
+		std::cmp::Ordering::Greater => Err(LoadError::UnsupportedVersion(version)),
+	}
+}
+
+/*
+	Writes to a temporary file next to the destination and then renames it over the destination, so that a crash while 
+	saving leaves either the old or the new file behind and never a partially written one.
+*/
+pub fn write_atomic(path: impl AsRef<Path>, data: &[u8]) -> io::Result<()> {
+	let path = path.as_ref();
+	if let Some(directory) = path.parent() {
+		fs::create_dir_all(directory)?;
+	}
+
+	let mut temporary_path = path.as_os_str().to_owned();
+	temporary_path.push(".tmp");
+
+	let mut file = fs::File::create(&temporary_path)?;
+	file.write_all(data)?;
+	file.sync_all()?;
+	drop(file);
+
+	fs::rename(&temporary_path, path)
+}
+
+/*
+	Removes the file if it exists and returns how many bytes were freed.
+*/
+pub fn remove(path: impl AsRef<Path>) -> io::Result<u64> {
+	let path = path.as_ref();
+	let size = match fs::metadata(path) {
+		Ok(metadata) => metadata.len(),
+		Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(0),
+		Err(error) => return Err(error),
+	};
+	fs::remove_file(path)?;
+	Ok(size)
+}
+
+pub fn save<T: SaveFormat>(path: impl AsRef<Path>, value: &T) -> io::Result<()> {
+	write_atomic(path, &serialize(value))
+}
+
+pub fn load<T: SaveFormat>(path: impl AsRef<Path>) -> Result<T, LoadError> {
+	deserialize(&fs::read(path).map_err(LoadError::Io)?)
+}
+
+//----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use serde::Deserialize;
+
+	use super::*;
+
+	mod v0 {
+		use serde::{Deserialize, Serialize};
+
+		#[derive(Deserialize, Serialize)]
+		pub struct Counter {
+			pub count: u32,
+		}
+	}
+
+	mod v1 {
+		use serde::{Deserialize, Serialize};
+
+		#[derive(Deserialize, Serialize)]
+		pub struct Counter {
+			pub count: u64,
+		}
+	}
+
+	#[derive(Debug, PartialEq, Deserialize, Serialize)]
+	struct Counter {
+		count: u64,
+		name: String,
+	}
+
+	impl From<v0::Counter> for v1::Counter {
+		fn from(counter: v0::Counter) -> Self {
+			v1::Counter { count: counter.count as u64 }
+		}
+	}
+
+	impl From<v1::Counter> for Counter {
+		fn from(counter: v1::Counter) -> Self {
+			Counter { count: counter.count, name: String::new() }
+		}
+	}
+
+	impl SaveFormat for Counter {
+		const VERSION: u32 = 2;
+
+		fn upgrade(version: u32, data: &[u8]) -> Result<Vec<u8>, LoadError> {
+			match version {
+				0 => upgrade_step::<v0::Counter, v1::Counter>(data),
+				1 => upgrade_step::<v1::Counter, Counter>(data),
+				_ => Err(LoadError::UnsupportedVersion(version)),
+			}
+		}
+	}
+
+	#[test]
+	fn round_trip() {
+		let counter = Counter { count: 5, name: "apples".to_owned() };
+		assert_eq!(deserialize::<Counter>(&serialize(&counter)).unwrap(), counter);
+	}
+
+	#[test]
+	fn headerless_data_is_migrated_from_version_0() {
+		let data = bincode::serialize(&v0::Counter { count: 7 }).unwrap();
+		assert_eq!(deserialize::<Counter>(&data).unwrap(), Counter { count: 7, name: String::new() });
+	}
+
+	#[test]
+	fn versions_are_upgraded_one_at_a_time() {
+		let data = serialize_version(1, &v1::Counter { count: 8 });
+		assert_eq!(deserialize::<Counter>(&data).unwrap(), Counter { count: 8, name: String::new() });
+	}
+
+	#[test]
+	fn newer_versions_are_rejected() {
+		let mut data = serialize(&Counter { count: 1, name: String::new() });
+		data[MAGIC.len()..MAGIC.len() + VERSION_LEN].copy_from_slice(&3u32.to_le_bytes());
+		assert!(matches!(deserialize::<Counter>(&data), Err(LoadError::UnsupportedVersion(3))));
+	}
+
+	#[test]
+	fn save_replaces_the_previous_file() {
+		let directory = std::env::temp_dir().join(format!("gurksaft_save_file_test_{}", std::process::id()));
+		let path = directory.join("counter");
+
+		save(&path, &Counter { count: 1, name: String::new() }).unwrap();
+		save(&path, &Counter { count: 2, name: String::new() }).unwrap();
+
+		assert_eq!(load::<Counter>(&path).unwrap().count, 2);
+		assert_eq!(fs::read_dir(&directory).unwrap().count(), 1, "the temporary file should have been renamed");
+		fs::remove_dir_all(&directory).unwrap();
+	}
+
+	#[test]
+	fn corrupt_data_is_an_error() {
+		let mut data = serialize(&Counter { count: 1, name: "pears".to_owned() });
+		data.truncate(data.len() - 2);
+		assert!(matches!(deserialize::<Counter>(&data), Err(LoadError::Parse(_))));
+	}
+}
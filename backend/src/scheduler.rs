@@ -0,0 +1,327 @@
+use chrono::prelude::*;
+
+use serde::{Deserialize, Serialize};
+
+use crate::learning_data::WordReviewResult;
+
+//----------------------------------------------------------------
+
+/*
+	Decides when words are due for review. The schedules are updated after every review no matter how words are selected, 
+	so that switching to spaced repetition selection at any point has up to date schedules to work with.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum SchedulingAlgorithm {
+	// SuperMemo 2, https://super-memory.com/english/ol/sm2.htm
+	Sm2,
+	// Free Spaced Repetition Scheduler (version 4) with its default parameters, https://github.com/open-spaced-repetition/fsrs4anki/wiki/The-Algorithm
+	Fsrs,
+}
+
+//----------------------------------------------------------------
+
+const SECONDS_PER_DAY: f32 = (60 * 60 * 24) as f32;
+
+const SM2_INITIAL_EASE_FACTOR: f32 = 2.5;
+const SM2_MIN_EASE_FACTOR: f32 = 1.3;
+
+// Default FSRS v4 model weights.
+const FSRS_WEIGHTS: [f32; 17] = [
+	0.4, 0.6, 2.4, 5.8, 4.93, 0.94, 0.86, 0.01, 1.49, 0.14, 0.94, 2.18, 0.05, 0.34, 1.26, 0.29, 2.61
+];
+// The probability of recalling a word when it is due. With FSRS, the interval then equals the stability.
+const FSRS_DESIRED_RETENTION: f32 = 0.9;
+const FSRS_MAX_INTERVAL_DAYS: f32 = 36500.;
+
+/*
+	The front-end only tells us whether a word was typed correctly or not, which we map to the grades of SM-2 (0-5) and
+	FSRS (1-4). A correct answer is a perfect response for SM-2, since the ease factor only grows with a quality of 5 and 
+	could otherwise never recover from failures.
+*/
+fn sm2_quality(result: WordReviewResult) -> f32 {
+	match result {
+		WordReviewResult::Succeeded => 5.,
+		WordReviewResult::Failed => 1.,
+	}
+}
+fn fsrs_grade(result: WordReviewResult) -> f32 {
+	match result {
+		WordReviewResult::Succeeded => 3.,
+		WordReviewResult::Failed => 1.,
+	}
+}
+
+fn fsrs_initial_difficulty(grade: f32) -> f32 {
+	(FSRS_WEIGHTS[4] - (grade - 3.) * FSRS_WEIGHTS[5]).clamp(1., 10.)
+}
+
+fn fsrs_retrievability(days_elapsed: f32, stability: f32) -> f32 {
+	1. / (1. + days_elapsed / (9. * stability))
+}
+
+fn fsrs_interval(stability: f32) -> f32 {
+	(9. * stability * (1. / FSRS_DESIRED_RETENTION - 1.)).clamp(1., FSRS_MAX_INTERVAL_DAYS)
+}
+
+//----------------------------------------------------------------
+
+/*
+	Spaced repetition state of a word. Both the SM-2 and FSRS variables are kept so that switching between the algorithms
+	doesn't lose all information about the word.
+*/
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ReviewSchedule {
+	pub due: DateTime<Utc>,
+	pub last_review: DateTime<Utc>,
+	// Time in days between the last review and the due date.
+	pub interval: f32,
+	// Number of successful reviews in a row.
+	pub repetitions: u32,
+	// Number of times the word has been forgotten after having been remembered.
+	pub lapses: u32,
+	// SM-2 ease factor, at least 1.3.
+	pub ease_factor: f32,
+	// FSRS stability, the number of days it takes for the probability of recall to fall to 90%.
+	pub stability: f32,
+	// FSRS difficulty between 1 and 10.
+	pub difficulty: f32,
+}
+
+impl ReviewSchedule {
+	/*
+		Creates the schedule of a word after its first review.
+	*/
+	pub fn first_review(algorithm: SchedulingAlgorithm, result: WordReviewResult, now: DateTime<Utc>) -> Self {
+		let grade = fsrs_grade(result);
+		let mut schedule = ReviewSchedule {
+			due: now,
+			last_review: now,
+			interval: 0.,
+			repetitions: 0,
+			lapses: 0,
+			ease_factor: SM2_INITIAL_EASE_FACTOR,
+			stability: FSRS_WEIGHTS[grade as usize - 1],
+			difficulty: fsrs_initial_difficulty(grade),
+		};
+
+		match algorithm {
+			SchedulingAlgorithm::Sm2 => schedule.review_sm2(result, now),
+			SchedulingAlgorithm::Fsrs => {
+				schedule.repetitions = (result == WordReviewResult::Succeeded) as u32;
+				schedule.set_interval(fsrs_interval(schedule.stability), now);
+			}
+		}
+		schedule
+	}
+
+	pub fn review(&mut self, algorithm: SchedulingAlgorithm, result: WordReviewResult, now: DateTime<Utc>) {
+		match algorithm {
+			SchedulingAlgorithm::Sm2 => self.review_sm2(result, now),
+			SchedulingAlgorithm::Fsrs => self.review_fsrs(result, now),
+		}
+	}
+
+	pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+		self.due <= now
+	}
+
+	fn set_interval(&mut self, interval: f32, now: DateTime<Utc>) {
+		self.interval = interval;
+		self.last_review = now;
+		self.due = now + chrono::Duration::seconds((interval * SECONDS_PER_DAY) as i64);
+	}
+
+	fn review_sm2(&mut self, result: WordReviewResult, now: DateTime<Utc>) {
+		let quality = sm2_quality(result);
+
+		let interval = if result == WordReviewResult::Succeeded {
+			self.repetitions += 1;
+			match self.repetitions {
+				1 => 1.,
+				2 => 6.,
+				_ => (self.interval * self.ease_factor).round(),
+			}
+		}
+		else {
+			if self.repetitions > 0 {
+				self.lapses += 1;
+			}
+			self.repetitions = 0;
+			1.
+		};
+
+		self.ease_factor = f32::max(
+			self.ease_factor + 0.1 - (5. - quality) * (0.08 + (5. - quality) * 0.02),
+			SM2_MIN_EASE_FACTOR
+		);
+		self.set_interval(interval, now);
+	}
+
+	fn review_fsrs(&mut self, result: WordReviewResult, now: DateTime<Utc>) {
+		let w = &FSRS_WEIGHTS;
+		let grade = fsrs_grade(result);
+
+		let days_elapsed = f32::max((now - self.last_review).num_seconds() as f32 / SECONDS_PER_DAY, 0.);
+		let retrievability = fsrs_retrievability(days_elapsed, self.stability);
+
+		self.stability = if result == WordReviewResult::Succeeded {
+			self.repetitions += 1;
+			self.stability * (1. + f32::exp(w[8]) * (11. - self.difficulty) * self.stability.powf(-w[9])
+				* (f32::exp(w[10] * (1. - retrievability)) - 1.))
+		}
+		else {
+			if self.repetitions > 0 {
+				self.lapses += 1;
+			}
+			self.repetitions = 0;
+			f32::min(
+				w[11] * self.difficulty.powf(-w[12]) * ((self.stability + 1.).powf(w[13]) - 1.) * f32::exp(w[14] * (1. - retrievability)),
+				self.stability
+			)
+		};
+
+		// The difficulty moves with the grade and reverts slightly towards the initial difficulty of a "good" grade.
+		let difficulty = self.difficulty - w[6] * (grade - 3.);
+		self.difficulty = (w[7] * fsrs_initial_difficulty(3.) + (1. - w[7]) * difficulty).clamp(1., 10.);
+
+		self.set_interval(fsrs_interval(self.stability), now);
+	}
+}
+
+//----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use WordReviewResult::{Failed, Succeeded};
+
+	fn start_time() -> DateTime<Utc> {
+		Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap()
+	}
+
+	fn days_later(time: DateTime<Utc>, days: f32) -> DateTime<Utc> {
+		time + chrono::Duration::seconds((days * SECONDS_PER_DAY) as i64)
+	}
+
+	fn assert_close(actual: f32, expected: f32) {
+		assert!((actual - expected).abs() < 1e-3, "expected {}, got {}", expected, actual);
+	}
+
+	#[test]
+	fn sm2_intervals_grow_with_the_ease_factor() {
+		let now = start_time();
+		let mut schedule = ReviewSchedule::first_review(SchedulingAlgorithm::Sm2, Succeeded, now);
+		assert_eq!(schedule.interval, 1.);
+		assert_eq!(schedule.repetitions, 1);
+		assert_close(schedule.ease_factor, 2.6);
+		assert_eq!(schedule.due, days_later(now, 1.));
+		assert!(!schedule.is_due(now));
+		assert!(schedule.is_due(schedule.due));
+
+		let now = schedule.due;
+		schedule.review(SchedulingAlgorithm::Sm2, Succeeded, now);
+		assert_eq!(schedule.interval, 6.);
+		assert_close(schedule.ease_factor, 2.7);
+
+		let now = schedule.due;
+		schedule.review(SchedulingAlgorithm::Sm2, Succeeded, now);
+		// 6 * 2.7 rounded.
+		assert_eq!(schedule.interval, 16.);
+		assert_eq!(schedule.last_review, now);
+		assert_eq!(schedule.due, days_later(now, 16.));
+	}
+
+	#[test]
+	fn sm2_failures_reset_the_interval_and_lower_the_ease_factor() {
+		let now = start_time();
+		let mut schedule = ReviewSchedule::first_review(SchedulingAlgorithm::Sm2, Failed, now);
+		assert_eq!((schedule.interval, schedule.repetitions, schedule.lapses), (1., 0, 0));
+		assert_close(schedule.ease_factor, 1.96);
+
+		schedule.review(SchedulingAlgorithm::Sm2, Succeeded, days_later(now, 1.));
+		schedule.review(SchedulingAlgorithm::Sm2, Succeeded, days_later(now, 2.));
+		schedule.review(SchedulingAlgorithm::Sm2, Failed, days_later(now, 8.));
+		assert_eq!((schedule.interval, schedule.repetitions, schedule.lapses), (1., 0, 1));
+
+		for i in 0..10 {
+			schedule.review(SchedulingAlgorithm::Sm2, Failed, days_later(now, 9. + i as f32));
+		}
+		assert_eq!(schedule.ease_factor, SM2_MIN_EASE_FACTOR);
+		// Failing again doesn't count as a lapse until the word has been remembered.
+		assert_eq!(schedule.lapses, 1);
+	}
+
+	#[test]
+	fn sm2_ease_factor_recovers_after_failures() {
+		let now = start_time();
+		let mut schedule = ReviewSchedule::first_review(SchedulingAlgorithm::Sm2, Failed, now);
+		for i in 0..5 {
+			schedule.review(SchedulingAlgorithm::Sm2, Failed, days_later(now, i as f32));
+		}
+		assert_eq!(schedule.ease_factor, SM2_MIN_EASE_FACTOR);
+
+		let mut ease_factor = schedule.ease_factor;
+		for i in 0..5 {
+			schedule.review(SchedulingAlgorithm::Sm2, Succeeded, days_later(now, 10. + i as f32));
+			assert!(schedule.ease_factor > ease_factor);
+			ease_factor = schedule.ease_factor;
+		}
+		assert_close(ease_factor, 1.8);
+	}
+
+	#[test]
+	fn fsrs_interval_equals_stability_at_desired_retention() {
+		for stability in [1., 2.4, 30., 400.] {
+			assert_close(fsrs_retrievability(stability, stability), FSRS_DESIRED_RETENTION);
+			assert_close(fsrs_interval(stability), stability);
+		}
+		assert_eq!(fsrs_interval(0.1), 1.);
+		assert_eq!(fsrs_interval(1e6), FSRS_MAX_INTERVAL_DAYS);
+	}
+
+	#[test]
+	fn fsrs_first_review_uses_the_initial_weights() {
+		let now = start_time();
+		let schedule = ReviewSchedule::first_review(SchedulingAlgorithm::Fsrs, Succeeded, now);
+		assert_eq!(schedule.stability, FSRS_WEIGHTS[2]);
+		assert_close(schedule.difficulty, 4.93);
+		assert_close(schedule.interval, 2.4);
+		assert_eq!(schedule.repetitions, 1);
+		assert_eq!(schedule.due, days_later(now, 2.4));
+
+		let schedule = ReviewSchedule::first_review(SchedulingAlgorithm::Fsrs, Failed, now);
+		assert_eq!(schedule.stability, FSRS_WEIGHTS[0]);
+		assert_close(schedule.difficulty, 6.81);
+		// The stability is less than a day, but words aren't reviewed more than once a day.
+		assert_eq!(schedule.interval, 1.);
+		assert_eq!(schedule.repetitions, 0);
+	}
+
+	#[test]
+	fn fsrs_reviews_update_stability_and_difficulty() {
+		let now = start_time();
+		let first_review = ReviewSchedule::first_review(SchedulingAlgorithm::Fsrs, Succeeded, now);
+
+		// Reviewed when due, so the retrievability is the desired retention.
+		let mut schedule = first_review.clone();
+		schedule.review(SchedulingAlgorithm::Fsrs, Succeeded, first_review.due);
+		assert_close(schedule.stability, 8.036);
+		assert_close(schedule.difficulty, 4.93);
+		assert_close(schedule.interval, 8.036);
+		assert_eq!(schedule.repetitions, 2);
+
+		let mut schedule = first_review.clone();
+		schedule.review(SchedulingAlgorithm::Fsrs, Failed, first_review.due);
+		assert_close(schedule.stability, 1.178);
+		assert_close(schedule.difficulty, 6.633);
+		assert_eq!((schedule.repetitions, schedule.lapses), (0, 1));
+
+		// Recalling a word that was less likely to be remembered makes it more stable.
+		let mut late_schedule = first_review.clone();
+		late_schedule.review(SchedulingAlgorithm::Fsrs, Succeeded, days_later(now, 10.));
+		let mut early_schedule = first_review;
+		early_schedule.review(SchedulingAlgorithm::Fsrs, Succeeded, days_later(now, 1.));
+		assert!(late_schedule.stability > early_schedule.stability);
+	}
+}
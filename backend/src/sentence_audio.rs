@@ -1,16 +1,23 @@
 use std::{
 	collections::HashMap,
-	sync::{Arc, atomic, atomic::AtomicBool}
+	sync::{Arc, Mutex}
 };
 use once_cell::sync::Lazy;
 
 use piper::{vits::VitsModel, synth::PiperSpeechSynthesizer};
 
-use tauri::Manager;
+use serde::Serialize;
 
 use crate::{
-	learning_data::{SentenceId, SAVE_DIRECTORY}, 
-	source_data::LANGUAGES
+	audio_cache::{AudioCache, AudioKey},
+	audio_requests::{AudioRequestId, CancellationToken},
+	error::{Error, Result},
+	learning_data::SentenceId, 
+	source_config::SourceConfig,
+	source_data,
+	voice_models::{VoiceModelInfo, VoiceModels},
+	voices,
+	worker_pool::WorkerPool,
 };
 
 //----------------------------------------------------------------
@@ -20,15 +27,17 @@ pub type AudioId = SentenceId;
 struct AudioIdMap(HashMap<SentenceId, Vec<AudioId>>);
 
 impl AudioIdMap {
-	fn load(app: &tauri::AppHandle) -> Self {
+	fn load(app: &tauri::AppHandle) -> Result<Self> {
 		let path = app.path_resolver()
 			.resolve_resource("audio_ids")
-			.expect("audio IDs resource should exist");
+			.ok_or_else(|| Error::MissingResource("audio IDs resource".to_owned()))?;
 
 		let file = std::fs::File::open(&path)
-			.expect("audio IDs resource should exist");
+			.map_err(|error| Error::MissingResource(format!("audio IDs resource {}: {}", path.display(), error)))?;
 
-		AudioIdMap(bincode::deserialize_from(&file).expect("audio ID data should be serialized properly by data_packing_utility"))
+		// The data is serialized by data_packing_utility.
+		let map = bincode::deserialize_from(&file).map_err(|error| Error::from(error).context("parsing audio IDs"))?;
+		Ok(AudioIdMap(map))
 	}
 }
 
@@ -37,14 +46,16 @@ impl AudioIdMap {
 pub struct SentenceAudioRecordings {
 	audio_id_map: AudioIdMap,
 	client: reqwest::Client,
+	config: SourceConfig,
 }
 
 impl SentenceAudioRecordings {
-	pub fn new(app: &tauri::AppHandle) -> Self {
-		SentenceAudioRecordings { 
-			audio_id_map: AudioIdMap::load(app), 
+	pub fn new(app: &tauri::AppHandle) -> Result<Self> {
+		Ok(SentenceAudioRecordings { 
+			audio_id_map: AudioIdMap::load(app)?, 
 			client: reqwest::Client::new(),
-		}
+			config: SourceConfig::load()?,
+		})
 	}
 
 	pub fn sentence_audio_ids(&self, sentence_id: SentenceId) -> Vec<AudioId> {
@@ -56,17 +67,11 @@ impl SentenceAudioRecordings {
 		}
 	}
 	
-	pub async fn download_audio(&self, audio_id: AudioId) -> Vec<u8> {
-		let url = format!("https://tatoeba.org/audio/download/{}", audio_id);
+	pub async fn download_audio(&self, audio_id: AudioId) -> Result<Vec<u8>> {
+		let url = self.config.tatoeba_audio_url(audio_id);
 
-		let response = self.client.get(url).send().await;
-		
-		if let Ok(response) = response && response.status().is_success() {
-			response.bytes().await.unwrap().to_vec()
-		}
-		else {
-			vec![]
-		}
+		let response = self.client.get(url).send().await?.error_for_status()?;
+		Ok(response.bytes().await?.to_vec())
 	}
 }
 
@@ -74,144 +79,290 @@ impl SentenceAudioRecordings {
 
 static ENVIRONMENT: Lazy<Arc<ort::Environment>> = Lazy::new(|| Arc::new(ort::Environment::default()));
 
+pub struct SpeechModel {
+	model: Arc<VitsModel>,
+	/*
+		Held while synthesizing. The speaker is part of the model's state, so a model can't be used for two clips at once 
+		and the clips of a voice, including those of its different speakers, are generated one after another. Only 
+		different voices are generated in parallel.
+	*/
+	synthesis_lock: Mutex<()>,
+}
+
+impl SpeechModel {
+	/*
+		Blocks until the clip is done, so it runs on the synthesis pool. Returns None if the speaker doesn't exist.
+	*/
+	fn synthesize(&self, sentence: &str, speaker: Option<String>) -> Result<Option<Vec<u8>>> {
+		// A panic during an earlier clip poisons the lock, but the lock guards no data and the model can still be used.
+		let _synthesis_guard = self.synthesis_lock.lock().unwrap_or_else(|error| error.into_inner());
+		if let Some(speaker) = speaker && self.model.set_speaker(speaker).is_err() {
+			return Ok(None);
+		}
+		let file_data = PiperSpeechSynthesizer::new(self.model.clone())
+			.map_err(|error| Error::Synthesis(error.to_string()))?
+			.synthesize_to_wav_buffer(sentence.to_owned())
+			.map_err(|error| Error::Synthesis(error.to_string()))?;
+		Ok(Some(file_data))
+	}
+}
+
+type SharedVoiceModels = Arc<Mutex<VoiceModels<SpeechModel>>>;
+
+/*
+	How much more memory a loaded model takes up than its file. Besides the weights, onnxruntime keeps the optimized graph
+	and memory arenas for running it, which together take up a few times the size of the file.
+*/
+const MODEL_MEMORY_FACTOR: u64 = 3;
+
 pub struct NeuralSpeechModels {
-	pub models: Vec<Arc<VitsModel>>,
-	language_index: Option<usize>,
+	// The enabled voices of the current language, see voices.rs.
+	voices: Vec<String>,
+	// Loaded when they are first used, so that enabling many voices doesn't take up memory for the ones that aren't used.
+	models: SharedVoiceModels,
 }
 
 impl NeuralSpeechModels {
-	pub fn new(language_index: usize) -> NeuralSpeechModels {
-		NeuralSpeechModels { models: Self::load_models(language_index), language_index: None }
+	pub fn new(voices: &[String], estimated_memory_budget_bytes: u64) -> NeuralSpeechModels {
+		let models = VoiceModels::new(estimated_memory_budget_bytes);
+		NeuralSpeechModels { voices: voices.to_vec(), models: Arc::new(Mutex::new(models)) }
 	}
 
-	pub fn set_language(&mut self, language_index: usize) {
-		if self.language_index == Some(language_index) {
-			return;
-		}
-		self.language_index = Some(language_index);
-		// The models are pretty memory intensive so we clear them first.
-		self.models.clear();
-		self.models = Self::load_models(language_index);
+	/*
+		Unloads the models of voices that aren't used anymore, for example because the language was switched.
+	*/
+	pub fn set_voices(&mut self, voices: &[String]) {
+		self.voices = voices.to_vec();
+		self.models.lock().unwrap().retain_voices(voices);
+	}
+
+	pub fn set_memory_budget(&mut self, estimated_memory_budget_bytes: u64) {
+		self.models.lock().unwrap().set_memory_budget(estimated_memory_budget_bytes);
 	}
 
-	fn load_models(language_index: usize) -> Vec<Arc<VitsModel>> {
-		let mut models = Vec::new();
+	pub fn info(&self) -> Vec<VoiceModelInfo> {
+		self.models.lock().unwrap().info(&self.voices)
+	}
 
-		for model in &LANGUAGES[language_index].piper_voices {
-			let onnx_path = format!("{}/voices/{}.onnx", SAVE_DIRECTORY, model.replace('/', "-"));
-			models.push(Arc::new(VitsModel::new(format!("{}.json", onnx_path).into(), onnx_path.into(), &ENVIRONMENT).unwrap()));
+	/*
+		Returns the model together with an estimate of how much memory it takes up, see MODEL_MEMORY_FACTOR.
+	*/
+	fn load_model(voice: &str) -> Result<(SpeechModel, u64)> {
+		let [model_file_name, configuration_file_name] = voices::file_names(voice);
+		let onnx_path = format!("{}/{}", source_data::voice_directory().display(), model_file_name);
+		let config_path = format!("{}/{}", source_data::voice_directory().display(), configuration_file_name);
+
+		for path in [&onnx_path, &config_path] {
+			if !std::path::Path::new(path).exists() {
+				return Err(Error::MissingResource(format!("voice file {}", path)));
+			}
 		}
+		let estimated_memory_bytes = std::fs::metadata(&onnx_path)?.len() * MODEL_MEMORY_FACTOR;
 
-		models
+		let model = VitsModel::new(config_path.into(), onnx_path.clone().into(), &ENVIRONMENT)
+			.map_err(|error| Error::Parse(format!("loading voice model {}: {}", onnx_path, error)))?;
+		Ok((SpeechModel { model: Arc::new(model), synthesis_lock: Mutex::new(()) }, estimated_memory_bytes))
 	}
 }
 
 //----------------------------------------------------------------
 
-struct AppListenGuard {
-	app: tauri::AppHandle,
-	handler_id: tauri::EventHandler
+/*
+	The payload of the "sentence_audio_data" event.
+*/
+#[derive(Clone, Serialize)]
+struct SentenceAudioData {
+	request_id: AudioRequestId,
+	file_data: Vec<u8>,
 }
 
-impl AppListenGuard {
-	fn new<F>(app: tauri::AppHandle, event_name: &str, handler: F) -> Self 
-		where F: Fn(tauri::Event) + Send + 'static
-	{
-		println!("Listen");
-		let handler_id = app.listen_global(event_name, handler);
-		Self { 
-			app, 
-			handler_id,
+const SYNTHESIS_THREAD_NAME: &str = "speech synthesis";
+
+pub struct AudioLoader {
+	sentence_recordings: Arc<SentenceAudioRecordings>,
+	speech_models: NeuralSpeechModels,
+	cache: Arc<Mutex<AudioCache>>,
+	synthesis_pool: Arc<WorkerPool>,
+}
+
+impl AudioLoader {
+	pub fn new(
+		app: &tauri::AppHandle, 
+		voices: &[String], 
+		cache: Arc<Mutex<AudioCache>>, 
+		synthesis_thread_count: usize, 
+		estimated_model_memory_budget_bytes: u64
+	) -> Result<Self> {
+		Ok(Self {
+			sentence_recordings: Arc::new(SentenceAudioRecordings::new(app)?),
+			speech_models: NeuralSpeechModels::new(voices, estimated_model_memory_budget_bytes),
+			cache,
+			synthesis_pool: Arc::new(WorkerPool::new(SYNTHESIS_THREAD_NAME, synthesis_thread_count)),
+		})
+	}
+
+	pub fn set_voices(&mut self, voices: &[String]) {
+		self.speech_models.set_voices(voices);
+	}
+
+	pub fn set_model_memory_budget(&mut self, estimated_memory_budget_bytes: u64) {
+		self.speech_models.set_memory_budget(estimated_memory_budget_bytes);
+	}
+
+	pub fn voice_model_info(&self) -> Vec<VoiceModelInfo> {
+		self.speech_models.info()
+	}
+
+	/*
+		The threads generate the clips of different voices in parallel, so more threads than enabled voices don't make speech
+		any faster, see SpeechModel::synthesis_lock. Loads that are running keep using the previous threads until they are 
+		done.
+	*/
+	pub fn set_synthesis_thread_count(&mut self, thread_count: usize) {
+		if thread_count != self.synthesis_pool.thread_count() {
+			self.synthesis_pool = Arc::new(WorkerPool::new(SYNTHESIS_THREAD_NAME, thread_count));
+		}
+	}
+
+	/*
+		Takes what loading audio needs so that the loader doesn't stay locked while loading. That way changing the voices 
+		or the language never has to wait for speech that is being generated.
+	*/
+	pub fn sources(&self) -> SentenceAudioSources {
+		SentenceAudioSources {
+			sentence_recordings: self.sentence_recordings.clone(),
+			voices: self.speech_models.voices.clone(),
+			speech_models: self.speech_models.models.clone(),
+			cache: self.cache.clone(),
+			synthesis_pool: self.synthesis_pool.clone(),
 		}
 	}
 }
 
-impl Drop for AppListenGuard {
-	fn drop(&mut self) {
-		println!("Unlisten");
-		self.app.unlisten(self.handler_id);
+/*
+	The audio has been loaded already, so a clip that can't be cached only costs loading it again next time.
+*/
+fn cache_audio(cache: &Mutex<AudioCache>, key: &AudioKey, file_data: &[u8]) {
+	if let Err(error) = cache.lock().unwrap().insert(key, file_data) {
+		eprintln!("Could not cache audio: {}", error);
 	}
 }
 
 //----------------------------------------------------------------
 
-pub struct AudioLoader {
-	sentence_recordings: SentenceAudioRecordings,
-	speech_models: NeuralSpeechModels,
-	_listen_guard: AppListenGuard
+pub struct SentenceAudioSources {
+	sentence_recordings: Arc<SentenceAudioRecordings>,
+	voices: Vec<String>,
+	speech_models: SharedVoiceModels,
+	cache: Arc<Mutex<AudioCache>>,
+	synthesis_pool: Arc<WorkerPool>,
 }
 
-static SHOULD_CANCEL_AUDIO_LOADING: AtomicBool = AtomicBool::new(false);
+impl SentenceAudioSources {
+	/*
+		Sends the audio clips to the window as they become available, tagged with the ID of the request. Returns 
+		Error::Cancelled if the request was cancelled before all clips were sent.
+	*/
+	pub async fn load_audio_for_sentence(
+		&self, 
+		window: &tauri::Window, 
+		request_id: AudioRequestId, 
+		token: &CancellationToken, 
+		sentence_id: SentenceId, 
+		sentence: String
+	) -> Result<()> {
+		println!("Started loading audio for '{}'.", &sentence);
 
-impl AudioLoader {
-	pub fn new(app: tauri::AppHandle, language_index: usize) -> Self {
-		Self {
-			sentence_recordings: SentenceAudioRecordings::new(&app),
-			speech_models: NeuralSpeechModels::new(language_index),
-			_listen_guard: AppListenGuard::new(app, "cancel_sentence_audio", |_| {
-				println!("Setting SHOULD_CANCEL_AUDIO_LOADING to true in listener");
-				SHOULD_CANCEL_AUDIO_LOADING.store(true, atomic::Ordering::SeqCst);
-			}),
-		}
+		let result = self.load_audio(sentence_id, &sentence, token, |file_data| {
+			window.emit("sentence_audio_data", SentenceAudioData { request_id, file_data })?;
+			Ok(())
+		}).await;
+
+		println!("Stopped loading audio for '{}'.", &sentence);
+		result
 	}
 
-	pub fn set_language(&mut self, language_index: usize) {
-		self.speech_models.set_language(language_index);
+	/*
+		Loads the audio of a sentence that comes up later into the cache without sending it anywhere. Returns 
+		Error::Cancelled if the prefetch was cancelled before all clips were loaded.
+	*/
+	pub async fn prefetch_audio_for_sentence(&self, sentence_id: SentenceId, sentence: &str, token: &CancellationToken) -> Result<()> {
+		self.load_audio(sentence_id, sentence, token, |_| Ok(())).await
 	}
-	
-	pub async fn load_audio_for_sentence(&self, window: &tauri::Window, sentence_id: SentenceId, sentence: String) {
-		println!("Started loading audio for '{}'.", &sentence);
-		
-		// Download recorded audio clips if available.
+
+	/*
+		Loads the recordings and generated speech of the sentence from the cache, or downloads and generates the ones that
+		aren't cached yet, and passes each clip to on_clip. The token is checked before and after every clip.
+	*/
+	async fn load_audio<F>(&self, sentence_id: SentenceId, sentence: &str, token: &CancellationToken, mut on_clip: F) -> Result<()>
+		where F: FnMut(Vec<u8>) -> Result<()>
+	{
+		let check_cancelled = || if token.is_cancelled() { Err(Error::Cancelled) } else { Ok(()) };
+
+		// Download recorded audio clips if available, unless they are cached.
 
 		for audio_id in self.sentence_recordings.sentence_audio_ids(sentence_id) {
-			let file_data = self.sentence_recordings.download_audio(audio_id).await;
-			if SHOULD_CANCEL_AUDIO_LOADING.load(atomic::Ordering::SeqCst) {
-				SHOULD_CANCEL_AUDIO_LOADING.store(false, atomic::Ordering::SeqCst);
-				println!("Stopped loading audio for '{}'.", &sentence);
-				return;
+			check_cancelled()?;
+			let key = AudioKey::Recording { sentence_id, audio_id };
+			let cached_file_data = self.cache.lock().unwrap().get(&key);
+			let file_data = match cached_file_data {
+				Some(file_data) => Ok(file_data),
+				None => self.sentence_recordings.download_audio(audio_id).await
+					.inspect(|file_data| cache_audio(&self.cache, &key, file_data)),
+			};
+			check_cancelled()?;
+			// Generated speech works offline, so a recording that can't be downloaded is skipped rather than failing.
+			match file_data {
+				Ok(file_data) => on_clip(file_data)?,
+				Err(error) => eprintln!("Could not download audio {}: {}", audio_id, error),
 			}
-			window.emit("sentence_audio_data", file_data).unwrap();
 		}
 
-		// Generate neural speech with Piper.
+		// Generate neural speech with Piper, unless it is cached. Cached clips are passed on right away and the others are 
+		// generated on the synthesis pool and passed on in the order they are done.
 
-		let load_sentence_audio_for_model = |model: Arc<VitsModel>| {
-			let file_data = PiperSpeechSynthesizer::new(model).unwrap()
-				.synthesize_to_wav_buffer(sentence.clone()).unwrap();
+		let (result_sender, mut result_receiver) = tokio::sync::mpsc::unbounded_channel();
 
-			if SHOULD_CANCEL_AUDIO_LOADING.load(atomic::Ordering::SeqCst) {
-				SHOULD_CANCEL_AUDIO_LOADING.store(false, atomic::Ordering::SeqCst);
-				return true;
-			}
+		for voice in &self.voices {
+			let speakers = voices::speakers(voice)?;
+			let speakers: Vec<Option<String>> = if speakers.is_empty() { vec![None] } else { speakers.into_iter().map(Some).collect() };
 
-			window.emit("sentence_audio_data", file_data).unwrap();
-			false
-		};
-
-		for model in &self.speech_models.models {
-			if let Ok(speakers) = model.speakers() && !speakers.is_empty() {
-				for (_, name) in speakers {
-					if model.set_speaker(name).is_ok() {
-						if load_sentence_audio_for_model(model.clone()) {
-							println!("Stopped loading audio for '{}'.", &sentence);
-							return;
-						}
-					}
+			for speaker in speakers {
+				check_cancelled()?;
+				let key = AudioKey::Speech { sentence_id, voice, speaker: speaker.as_deref(), sentence };
+				let cached_file_data = self.cache.lock().unwrap().get(&key);
+				if let Some(file_data) = cached_file_data {
+					on_clip(file_data)?;
+					continue;
 				}
-			}
-			else if load_sentence_audio_for_model(model.clone()) {
-				println!("Stopped loading audio for '{}'.", &sentence);
-				return;
+
+				let (voice, models, cache, token, sentence, result_sender) = (
+					voice.clone(), self.speech_models.clone(), self.cache.clone(), token.clone(), sentence.to_owned(), result_sender.clone()
+				);
+				self.synthesis_pool.spawn(move || {
+					// Jobs of requests that were cancelled while they waited for a thread are skipped.
+					if token.is_cancelled() {
+						return;
+					}
+					let model = VoiceModels::get_or_load(&models, &voice, || NeuralSpeechModels::load_model(&voice));
+					let result = model.and_then(|model| model.synthesize(&sentence, speaker.clone()));
+					if let Ok(Some(file_data)) = &result {
+						let key = AudioKey::Speech { sentence_id, voice: &voice, speaker: speaker.as_deref(), sentence: &sentence };
+						cache_audio(&cache, &key, file_data);
+					}
+					// The load has stopped if nobody is receiving, but the clip has been cached for next time.
+					let _ = result_sender.send(result);
+				});
 			}
 		}
-		println!("Stopped loading audio for '{}'.", &sentence);
-	}
-}
+		drop(result_sender);
 
-impl Drop for AudioLoader {
-	fn drop(&mut self) {
-		println!("Setting SHOULD_CANCEL_AUDIO_LOADING to true in AudioLoader::drop()");
-		SHOULD_CANCEL_AUDIO_LOADING.store(true, atomic::Ordering::SeqCst);
+		while let Some(result) = result_receiver.recv().await {
+			check_cancelled()?;
+			if let Some(file_data) = result? {
+				on_clip(file_data)?;
+			}
+		}
+		Ok(())
 	}
 }
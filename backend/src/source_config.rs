@@ -0,0 +1,123 @@
+use std::{fs, io};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	error::{Error, Result},
+	learning_data::SAVE_DIRECTORY,
+};
+
+//----------------------------------------------------------------
+
+/*
+	Where word lists, sentences, recordings and voices are fetched from. The defaults are the public sources, but they can be
+	pointed at mirrors or a local server by writing the fields to change into save_data/sources.json, for example
+	{ "tatoeba_url": "http://localhost:8080", "frequency_words_year": "2016" }. Fields that are left out keep their defaults.
+*/
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SourceConfig {
+	// The content directory of the FrequencyWords repository by Hermit Dave, https://github.com/hermitdave/FrequencyWords.
+	pub frequency_words_url: String,
+	// The subdirectory of the content directory, which is the year of the OpenSubtitles data the lists were made from.
+	pub frequency_words_year: String,
+	// The Tatoeba site that prepares sentence exports at /en/exports/add, /en/exports/get and /en/exports/download.
+	pub tatoeba_url: String,
+	// Sentence recordings are downloaded from {tatoeba_audio_url}/{audio ID}.
+	pub tatoeba_audio_url: String,
+	// The piper-voices repository and the tag or branch of it that voices are downloaded from.
+	pub piper_voices_url: String,
+	pub piper_voices_version: String,
+}
+
+pub const SOURCE_CONFIG_FILE: &str = "sources.json";
+
+impl Default for SourceConfig {
+	fn default() -> Self {
+		SourceConfig {
+			frequency_words_url: "https://raw.githubusercontent.com/hermitdave/FrequencyWords/master/content".to_owned(),
+			frequency_words_year: "2018".to_owned(),
+			tatoeba_url: "https://tatoeba.org".to_owned(),
+			tatoeba_audio_url: "https://tatoeba.org/audio/download".to_owned(),
+			piper_voices_url: "https://huggingface.co/rhasspy/piper-voices".to_owned(),
+			piper_voices_version: "v1.0.0".to_owned(),
+		}
+	}
+}
+
+impl SourceConfig {
+	/*
+		Returns the default configuration if there is no configuration file.
+	*/
+	pub fn load() -> Result<Self> {
+		let path = format!("{}/{}", SAVE_DIRECTORY, SOURCE_CONFIG_FILE);
+		match fs::read(&path) {
+			Ok(data) => Self::parse(&data).map_err(|error| error.context(&path)),
+			Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+			Err(error) => Err(Error::from(error).context(&path)),
+		}
+	}
+
+	fn parse(data: &[u8]) -> Result<Self> {
+		Ok(serde_json::from_slice(data)?)
+	}
+
+	/*
+		The list file name is for example "en_50k.txt" or "en_full.txt".
+	*/
+	pub fn word_list_url(&self, language_id_2: &str, list_file_name: &str) -> String {
+		format!("{}/{}/{}/{}", trim(&self.frequency_words_url), self.frequency_words_year, language_id_2, list_file_name)
+	}
+
+	/*
+		The path is relative to the exports endpoint, such as "add" or "get/{id}".
+	*/
+	pub fn tatoeba_export_url(&self, path: &str) -> String {
+		format!("{}/en/exports/{}", trim(&self.tatoeba_url), path)
+	}
+
+	pub fn tatoeba_audio_url(&self, audio_id: impl std::fmt::Display) -> String {
+		format!("{}/{}", trim(&self.tatoeba_audio_url), audio_id)
+	}
+
+	/*
+		The path is relative to the version of the repository, such as "en/en_US/ryan/high/en_US-ryan-high.onnx".
+	*/
+	pub fn piper_voices_file_url(&self, path: &str) -> String {
+		format!("{}/resolve/{}/{}", trim(&self.piper_voices_url), self.piper_voices_version, path)
+	}
+}
+
+fn trim(url: &str) -> &str {
+	url.trim_end_matches('/')
+}
+
+//----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn defaults_point_at_the_public_sources() {
+		let config = SourceConfig::default();
+		assert_eq!(
+			config.word_list_url("en", "en_50k.txt"),
+			"https://raw.githubusercontent.com/hermitdave/FrequencyWords/master/content/2018/en/en_50k.txt"
+		);
+		assert_eq!(config.tatoeba_export_url("add"), "https://tatoeba.org/en/exports/add");
+		assert_eq!(config.tatoeba_audio_url(42), "https://tatoeba.org/audio/download/42");
+		assert_eq!(
+			config.piper_voices_file_url("fi/fi_FI/harri/medium/fi_FI-harri-medium.onnx"),
+			"https://huggingface.co/rhasspy/piper-voices/resolve/v1.0.0/fi/fi_FI/harri/medium/fi_FI-harri-medium.onnx"
+		);
+	}
+
+	#[test]
+	fn missing_fields_keep_their_defaults() {
+		let config = SourceConfig::parse(br#"{ "tatoeba_url": "http://localhost:8080/", "piper_voices_version": "main" }"#).unwrap();
+		assert_eq!(config.tatoeba_export_url("get/3"), "http://localhost:8080/en/exports/get/3");
+		assert_eq!(config.piper_voices_version, "main");
+		assert_eq!(config.frequency_words_year, SourceConfig::default().frequency_words_year);
+	}
+}
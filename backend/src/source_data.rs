@@ -1,9 +1,19 @@
-use std::{fs, io::Write};
+use std::{fs, path::{Path, PathBuf}};
 
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 
-use crate::learning_data::SAVE_DIRECTORY;
+use crate::{
+	download::Downloader,
+	error::{Error, Result},
+	integrity::ExpectedFile,
+	learning_data::{SentenceId, SAVE_DIRECTORY},
+	save_file,
+	source_config::SourceConfig,
+	tokenizer::WordSegmentation,
+	voice_catalogue::VoiceCatalogue,
+	voices,
+};
 
 //----------------------------------------------------------------
 
@@ -14,74 +24,126 @@ pub struct Language {
 	pub id_2: &'static str,
 	// Three-letter ISO 639-3 language code.
 	pub id_3: &'static str,
+	// How words are separated from each other in the language.
+	pub word_segmentation: WordSegmentation,
 	/*
-		PIPER voice model paths for the language, relative to https://huggingface.co/rhasspy/piper-voices/tree/v1.0.0/{id_2}/ where id_2 is 
-		the two-letter ISO 639-1 language code and is not included in the strings. The file name is not included either and is retrieved by 
-		replacing "/" by "-" and adding the appropriate extension. These are approximately ordered in order of quality from highest to 
-		lowest.
+		The preferred PIPER voices for the language, named like in voices.rs, approximately ordered by quality from highest to 
+		lowest. They only rank the voices of the voice catalogue, which is where the voices and the default one are chosen 
+		from, by quality when a language has no preferred ones. Without a downloaded catalogue the first one is the default.
 	*/
 	pub piper_voices: Vec<&'static str>
 }
 
+pub fn voice_directory() -> PathBuf {
+	Path::new(SAVE_DIRECTORY).join("voices")
+}
+
+/*
+	Removes the installed voices of a language that no remaining saved language refers to. Unfinished downloads of them are 
+	removed too. Returns the number of bytes freed.
+*/
+pub fn remove_unused_voices(removed_language_index: usize, remaining_languages: &[usize], enabled_voices: &[String]) -> Result<u64> {
+	let directory = voice_directory();
+	let installed_voices = voices::voices_with_files_in_directory(&directory, removed_language_index);
+	let voices_in_use = voices_in_use(&directory, remaining_languages, enabled_voices);
+
+	let mut freed_bytes = 0;
+	for file_name in unused_voice_file_names(&installed_voices, &voices_in_use) {
+		for suffix in std::iter::once("").chain(voices::PARTIAL_FILE_SUFFIXES) {
+			freed_bytes += save_file::remove(voice_directory().join(file_name.clone() + suffix))?;
+		}
+	}
+	Ok(freed_bytes)
+}
+
+/*
+	The voices that the remaining languages refer to: the ones enabled for them, and the installed ones they can use, since 
+	voices belong to a language family and are shared, for example by Norwegian Bokmål and Nynorsk.
+*/
+fn voices_in_use(directory: &Path, remaining_languages: &[usize], enabled_voices: &[String]) -> Vec<String> {
+	let mut voices = enabled_voices.to_vec();
+	for &language_index in remaining_languages {
+		voices.extend(voices::voices_in_directory(directory, language_index));
+	}
+	voices
+}
+
+fn unused_voice_file_names(installed_voices: &[String], voices_in_use: &[String]) -> Vec<String> {
+	installed_voices.iter()
+		.filter(|voice| !voices_in_use.contains(voice))
+		.flat_map(|voice| voices::file_names(voice))
+		.collect()
+}
+
 pub static LANGUAGES: Lazy<Vec<Language>> = Lazy::new(|| vec![
 	Language {
 		name: "Afrikaans",
 		id_2: "af",
 		id_3: "afr",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Albanian",
 		id_2: "sq",
 		id_3: "sqi",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Arabic",
 		id_2: "ar",
 		id_3: "ara",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Armenian",
 		id_2: "hy",
 		id_3: "hye",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Basque",
 		id_2: "eu",
 		id_3: "eus",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Bengali",
 		id_2: "bn",
 		id_3: "ben",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Bosnian",
 		id_2: "bs",
 		id_3: "bos",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Breton",
 		id_2: "br",
 		id_3: "bre",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Bulgarian",
 		id_2: "bg",
 		id_3: "bul",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Catalan",
 		id_2: "ca",
 		id_3: "cat",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![
 			"ca_ES/upc_ona/medium",
 			"ca_ES/upc_pau/x_low",
@@ -91,18 +153,21 @@ pub static LANGUAGES: Lazy<Vec<Language>> = Lazy::new(|| vec![
 		name: "Croatian",
 		id_2: "hr",
 		id_3: "hrv",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Czech",
 		id_2: "cs",
 		id_3: "ces",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Danish",
 		id_2: "da",
 		id_3: "dan",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![
 			"da_DK/talesyntese/medium"
 		],
@@ -111,6 +176,7 @@ pub static LANGUAGES: Lazy<Vec<Language>> = Lazy::new(|| vec![
 		name: "Dutch",
 		id_2: "nl",
 		id_3: "nld",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![
 			"nl_BE/nathalie/medium",
 			"nl_BE/rdh/medium",
@@ -122,6 +188,7 @@ pub static LANGUAGES: Lazy<Vec<Language>> = Lazy::new(|| vec![
 		name: "English",
 		id_2: "en",
 		id_3: "eng",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![
 			// The models are currently all loaded into memory at once which would be 1 GB with all of these.
 			"en_US/ryan/high",
@@ -147,18 +214,21 @@ pub static LANGUAGES: Lazy<Vec<Language>> = Lazy::new(|| vec![
 		name: "Esperanto",
 		id_2: "eo",
 		id_3: "epo",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Estonian",
 		id_2: "et",
 		id_3: "est",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Finnish",
 		id_2: "fi",
 		id_3: "fin",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![
 			"fi_FI/harri/medium"
 		],
@@ -167,6 +237,7 @@ pub static LANGUAGES: Lazy<Vec<Language>> = Lazy::new(|| vec![
 		name: "French",
 		id_2: "fr",
 		id_3: "fra",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![
 			"fr_FR/siwis/medium",
 			"fr_FR/upmc/medium",
@@ -178,12 +249,14 @@ pub static LANGUAGES: Lazy<Vec<Language>> = Lazy::new(|| vec![
 		name: "Galician",
 		id_2: "gl",
 		id_3: "glg",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Georgian",
 		id_2: "ka",
 		id_3: "kat",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![
 			"ka_GE/natia/medium"
 		],
@@ -192,6 +265,7 @@ pub static LANGUAGES: Lazy<Vec<Language>> = Lazy::new(|| vec![
 		name: "German",
 		id_2: "de",
 		id_3: "deu",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![
 			"de_DE/thorsten_emotional/medium",
 			"de_DE/pavoque/low",
@@ -205,6 +279,7 @@ pub static LANGUAGES: Lazy<Vec<Language>> = Lazy::new(|| vec![
 		name: "Greek",
 		id_2: "el",
 		id_3: "ell",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![
 			"el_GR/rapunzelina/low"
 		],
@@ -213,24 +288,28 @@ pub static LANGUAGES: Lazy<Vec<Language>> = Lazy::new(|| vec![
 		name: "Hebrew",
 		id_2: "he",
 		id_3: "heb",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Hindi",
 		id_2: "hi",
 		id_3: "hin",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Hungarian",
 		id_2: "hu",
 		id_3: "hun",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Icelandic",
 		id_2: "is",
 		id_3: "isl",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![
 			"is_IS/steinn/medium",
 			"is_IS/bui/medium",
@@ -242,12 +321,14 @@ pub static LANGUAGES: Lazy<Vec<Language>> = Lazy::new(|| vec![
 		name: "Indonesian",
 		id_2: "id",
 		id_3: "ind",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Italian",
 		id_2: "it",
 		id_3: "ita",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![
 			"it_IT/riccardo/x_low",
 		],
@@ -256,12 +337,14 @@ pub static LANGUAGES: Lazy<Vec<Language>> = Lazy::new(|| vec![
 		name: "Japanese",
 		id_2: "ja",
 		id_3: "jpn",
+		word_segmentation: WordSegmentation::LongestMatch,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Kazakh",
 		id_2: "kk",
 		id_3: "kaz",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![
 			"kk_KZ/issai/high",
 			"kk_KZ/iseke/x_low",
@@ -272,42 +355,49 @@ pub static LANGUAGES: Lazy<Vec<Language>> = Lazy::new(|| vec![
 		name: "Korean",
 		id_2: "ko",
 		id_3: "kor",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Latvian",
 		id_2: "lv",
 		id_3: "lat",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Lithuanian",
 		id_2: "lt",
 		id_3: "lit",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Macedonian",
 		id_2: "mk",
 		id_3: "mkd",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Malay",
 		id_2: "ms",
 		id_3: "zsm",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Malayalam",
 		id_2: "ml",
 		id_3: "mal",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Norwegian Bokmål",
 		id_2: "no",
 		id_3: "nob",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![
 			"no_NO/talesyntese/medium",
 		],
@@ -316,6 +406,7 @@ pub static LANGUAGES: Lazy<Vec<Language>> = Lazy::new(|| vec![
 		name: "Norwegian Nynorsk",
 		id_2: "no",
 		id_3: "nno",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![
 			"no_NO/talesyntese/medium",
 		],
@@ -324,12 +415,14 @@ pub static LANGUAGES: Lazy<Vec<Language>> = Lazy::new(|| vec![
 		name: "Persian",
 		id_2: "fa",
 		id_3: "pes",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Polish",
 		id_2: "pl",
 		id_3: "pol",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![
 			"pl_PL/gosia/medium",
 			"pl_PL/darkman/medium",
@@ -340,6 +433,7 @@ pub static LANGUAGES: Lazy<Vec<Language>> = Lazy::new(|| vec![
 		name: "Portuguese",
 		id_2: "pt",
 		id_3: "por",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![
 			"pt_BR/faber/medium",
 			"pt_BR/edresson/low",
@@ -349,6 +443,7 @@ pub static LANGUAGES: Lazy<Vec<Language>> = Lazy::new(|| vec![
 		name: "Romanian",
 		id_2: "ro",
 		id_3: "ron",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![
 			"ro_RO/mihai/medium"
 		],
@@ -357,6 +452,7 @@ pub static LANGUAGES: Lazy<Vec<Language>> = Lazy::new(|| vec![
 		name: "Russian",
 		id_2: "ru",
 		id_3: "rus",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![
 			"ru_RU/irina/medium",
 			"ru_RU/ruslan/medium",
@@ -368,6 +464,7 @@ pub static LANGUAGES: Lazy<Vec<Language>> = Lazy::new(|| vec![
 		name: "Serbian",
 		id_2: "sr",
 		id_3: "srp",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![
 			"sr_RS/serbski_institut/medium"
 		],
@@ -376,24 +473,28 @@ pub static LANGUAGES: Lazy<Vec<Language>> = Lazy::new(|| vec![
 		name: "Sinhala",
 		id_2: "si",
 		id_3: "sin",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Slovak",
 		id_2: "sk",
 		id_3: "slk",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Slovenian",
 		id_2: "sl",
 		id_3: "slv",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Spanish",
 		id_2: "es",
 		id_3: "spa",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![
 			"es_ES/sharvard/medium",
 			"es_MX/ald/medium",
@@ -407,6 +508,7 @@ pub static LANGUAGES: Lazy<Vec<Language>> = Lazy::new(|| vec![
 		name: "Swedish",
 		id_2: "sv",
 		id_3: "swe",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![
 			"sv_SE/nst/medium",
 		],
@@ -415,30 +517,35 @@ pub static LANGUAGES: Lazy<Vec<Language>> = Lazy::new(|| vec![
 		name: "Tagalog",
 		id_2: "tl",
 		id_3: "tgl",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Tamil",
 		id_2: "ta",
 		id_3: "tam",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Telugu",
 		id_2: "te",
 		id_3: "tel",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Thai",
 		id_2: "th",
 		id_3: "tha",
+		word_segmentation: WordSegmentation::LongestMatch,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Turkish",
 		id_2: "tr",
 		id_3: "tur",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![
 			"tr_TR/dfki/medium",
 		],
@@ -447,6 +554,7 @@ pub static LANGUAGES: Lazy<Vec<Language>> = Lazy::new(|| vec![
 		name: "Ukranian",
 		id_2: "uk",
 		id_3: "ukr",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![
 			"uk_UA/ukrainian_tts/medium",
 		],
@@ -455,12 +563,14 @@ pub static LANGUAGES: Lazy<Vec<Language>> = Lazy::new(|| vec![
 		name: "Urdu",
 		id_2: "ur",
 		id_3: "urd",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![],
 	},
 	Language {
 		name: "Vietnamese",
 		id_2: "vi",
 		id_3: "vie",
+		word_segmentation: WordSegmentation::Unicode,
 		piper_voices: vec![
 			"vi_VN/vais1000/medium",
 			"vi_VN/25hours_single/low",
@@ -482,6 +592,49 @@ pub struct SourceDataInfo {
 	pub translation_languages: Vec<String>,
 }
 
+/*
+	Used as input to the procedure that builds the data for a target language from local files instead of downloading it, 
+	for machines without internet access or curated word and sentence lists.
+*/
+#[derive(Deserialize, Serialize)]
+pub struct SourceDataImportInfo {
+	pub target_language: String,
+	// A word frequency list with a word and its number of occurrences separated by a space on each line, like the lists in 
+	// the FrequencyWords repository.
+	pub word_list_path: String,
+	pub sentence_lists: Vec<SentenceListImportInfo>,
+	// A directory containing the Piper voices of the target language, named like in the piper-voices repository, for 
+	// example "en_US-ryan-high.onnx" and "en_US-ryan-high.onnx.json". Without it no voices are installed.
+	pub voice_directory: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct SentenceListImportInfo {
+	pub translation_language: String,
+	// A sentence pair list in the format of Tatoeba's exports, with a sentence ID, the sentence, a translation ID and the 
+	// translation separated by tabs on each line.
+	pub path: String,
+}
+
+/*
+	The file data of a list of sentences in the target language together with their translations in one translation language.
+*/
+pub struct SentenceList {
+	pub translation_language_index: usize,
+	pub data: Vec<u8>,
+}
+
+impl SentenceList {
+	fn new(translation_language_index: usize, data: Vec<u8>) -> Self {
+		// Strip BOM.
+		let data = match data.strip_prefix("\u{feff}".as_bytes()) {
+			Some(rest) => rest.to_vec(),
+			None => data,
+		};
+		SentenceList { translation_language_index, data }
+	}
+}
+
 /*
 	Source file data with words and sentences for a particular target language.
 	This data is parsed in learning_data.rs.
@@ -491,20 +644,187 @@ pub struct SourceData {
 	pub language_index: usize,
 	// The word frequency list file data fetched from the internet.
 	pub word_list: Vec<u8>,
-	// The concatenated file data for the lists of sentences in the target language together with translations in the translation languages.
-	pub sentence_list: Vec<u8>,
+	// One list for each translation language.
+	pub sentence_lists: Vec<SentenceList>,
+	// The optional lemma list supplied by the user, see lemma_list_file_name.
+	pub lemma_list: Option<Vec<u8>>,
 }
 
 impl SourceData {
-	pub async fn download<F>(info: SourceDataInfo, status_callback: F) -> Self 
+	/*
+		Lemma lists group inflected forms of words under their dictionary form, so that for example "went" and "goes" are
+		learned together with "go". They are not downloaded but can be placed in this location by the user. Each line contains a 
+		lemma and one of its forms separated by a tab, which is the format of https://github.com/michmech/lemmatization-lists.
+	*/
+	pub fn lemma_list_file_name(language_index: usize) -> String {
+		format!("{}/lemmas/{}.txt", SAVE_DIRECTORY, LANGUAGES[language_index].name)
+	}
+
+	pub async fn download<F>(info: SourceDataInfo, status_callback: F) -> Result<Self> 
+		where F: Fn(SourceDataDownloadStatus)
+	{
+		SourceDataDownloader::new(info, status_callback)?.download().await
+	}
+
+	/*
+		Downloads only the sentence lists, for adding translation languages to a target language that has already been added.
+	*/
+	pub async fn download_sentence_lists<F>(info: SourceDataInfo, status_callback: F) -> Result<Vec<SentenceList>> 
 		where F: Fn(SourceDataDownloadStatus)
 	{
-		SourceDataDownloader::new(info, status_callback).download().await
+		let downloader = SourceDataDownloader::new(info, status_callback)?;
+		let paths = downloader.download_sentence_lists().await?;
+		read_downloaded_sentence_lists(paths)
+	}
+
+	/*
+		Reads and checks the files instead of downloading them. Unlike the downloaded lists, which are parsed leniently, 
+		imported files are rejected with the first line that doesn't have the expected format since they were probably 
+		prepared by hand.
+	*/
+	pub fn import(info: SourceDataImportInfo) -> Result<Self> {
+		let target_language_index = language_index(&info.target_language)?;
+
+		let word_list = read_import_file(&info.word_list_path)?;
+		check_word_list(&word_list).map_err(|error| error.context(&info.word_list_path))?;
+
+		if info.sentence_lists.is_empty() {
+			return Err(Error::Parse("at least one sentence list is needed".to_owned()));
+		}
+		let mut sentence_lists = Vec::new();
+		for list_info in &info.sentence_lists {
+			let list = SentenceList::new(language_index(&list_info.translation_language)?, read_import_file(&list_info.path)?);
+			check_sentence_list(&list.data).map_err(|error| error.context(&list_info.path))?;
+			sentence_lists.push(list);
+		}
+
+		if let Some(voice_directory) = &info.voice_directory {
+			import_piper_voices(target_language_index, Path::new(voice_directory))?;
+		}
+
+		Ok(SourceData {
+			language_index: target_language_index,
+			word_list,
+			sentence_lists,
+			lemma_list: fs::read(SourceData::lemma_list_file_name(target_language_index)).ok(),
+		})
 	}
 }
 
 //----------------------------------------------------------------
 
+/*
+	Reads the lists and removes the files, so that the next download of the language fetches them again in case they were
+	updated.
+*/
+fn read_downloaded_sentence_lists(paths: Vec<(usize, PathBuf)>) -> Result<Vec<SentenceList>> {
+	let lists = paths.iter()
+		.map(|(translation_language_index, path)| Ok(SentenceList::new(*translation_language_index, fs::read(path)?)))
+		.collect::<Result<_>>()?;
+	for (_, path) in &paths {
+		remove_downloaded_file(path);
+	}
+	Ok(lists)
+}
+
+fn remove_downloaded_file(path: &Path) {
+	if let Err(error) = fs::remove_file(path) {
+		eprintln!("Could not remove {}: {}", path.display(), error);
+	}
+}
+
+/*
+	Checks the end of a downloaded list and removes the list if it is damaged, so that it is downloaded again instead of being 
+	trusted because it exists. There are no checksums for the lists, but a truncated list ends in a broken line. The rest 
+	isn't checked since the odd broken line in the middle is skipped when the list is loaded.
+*/
+fn check_downloaded_list(path: &Path, check: fn(&[u8]) -> Result<()>) -> Result<()> {
+	let result = fs::read(path).map_err(Error::from).and_then(|data| {
+		let last_line = data.trim_ascii_end().rsplit(|&byte| byte == b'\n').next().unwrap_or_default();
+		if last_line.is_empty() {
+			return Err(Error::Parse("the file is empty".to_owned()));
+		}
+		check(last_line)
+	});
+	if let Err(error) = result {
+		remove_downloaded_file(path);
+		return Err(error.context(format!("checking {}", path.display())));
+	}
+	Ok(())
+}
+
+fn read_import_file(path: &str) -> Result<Vec<u8>> {
+	fs::read(path).map_err(|error| match error.kind() {
+		std::io::ErrorKind::NotFound => Error::MissingResource(path.to_owned()),
+		_ => Error::from(error).context(path),
+	})
+}
+
+fn check_word_list(data: &[u8]) -> Result<()> {
+	let mut reader = csv::ReaderBuilder::new().delimiter(b' ').has_headers(false).from_reader(data);
+	let mut word_count = 0;
+	for record in reader.deserialize::<(String, u64)>() {
+		record.map_err(|error| Error::Parse(format!("expected a word and its frequency separated by a space: {}", error)))?;
+		word_count += 1;
+	}
+	if word_count == 0 {
+		return Err(Error::Parse("the word frequency list contains no words".to_owned()));
+	}
+	Ok(())
+}
+
+fn check_sentence_list(data: &[u8]) -> Result<()> {
+	let data = data.strip_prefix("\u{feff}".as_bytes()).unwrap_or(data);
+	let mut reader = csv::ReaderBuilder::new().delimiter(b'\t').has_headers(false).from_reader(data);
+	let mut sentence_count = 0;
+	for record in reader.deserialize::<(SentenceId, String, SentenceId, String)>() {
+		record.map_err(|error| Error::Parse(format!(
+			"expected a sentence ID, sentence, translation ID and translation separated by tabs: {}", error
+		)))?;
+		sentence_count += 1;
+	}
+	if sentence_count == 0 {
+		return Err(Error::Parse("the sentence list contains no sentences".to_owned()));
+	}
+	Ok(())
+}
+
+/*
+	Copies the voices of the language that are in the directory to where downloaded voices are stored. Voices can be chosen
+	individually, so any of them will do, but there has to be at least one.
+*/
+fn import_piper_voices(language_index: usize, source_directory: &Path) -> Result<()> {
+	let destination_directory = voice_directory();
+	fs::create_dir_all(&destination_directory)?;
+
+	let found_voices = voices::voices_in_directory(source_directory, language_index);
+	if found_voices.is_empty() {
+		return Err(Error::MissingResource(format!("a voice of {} in {}", LANGUAGES[language_index].name, source_directory.display())));
+	}
+
+	for model in &found_voices {
+		if voices::is_installed(model) {
+			continue;
+		}
+		let file_names = voices::file_names(model);
+		let [_, configuration_file_name] = &file_names;
+
+		let configuration = fs::read(source_directory.join(configuration_file_name))?;
+		serde_json::from_slice::<serde_json::Value>(&configuration)
+			.map_err(|error| Error::from(error).context(format!("voice configuration {}", configuration_file_name)))?;
+
+		for file_name in &file_names {
+			// Copied under a temporary name first so that an interrupted copy is not mistaken for an installed voice.
+			let temporary = destination_directory.join(file_name.to_owned() + ".tmp");
+			fs::copy(source_directory.join(file_name), &temporary)?;
+			fs::rename(&temporary, destination_directory.join(file_name))?;
+		}
+	}
+	Ok(())
+}
+
+//----------------------------------------------------------------
+
 #[derive(Serialize, Deserialize)]
 pub enum SourceDataDownloadStatus {
 	DownloadingWords { progress: f32 },
@@ -517,19 +837,31 @@ pub enum SourceDataDownloadStatus {
 
 struct SourceDataDownloader<F: Fn(SourceDataDownloadStatus)> {
 	client: reqwest::Client,
+	downloader: Downloader,
+	// Used for Tatoeba's exports, which are only given out to requests that have a CSRF token.
+	tatoeba_downloader: Downloader,
+	config: SourceConfig,
 	target_language_index: usize,
 	info: SourceDataInfo,
 	status_callback: F
 }
 
-fn vec_with_optional_capacity<T>(capacity: Option<u64>) -> Vec<T> {
-	if let Some(capacity) = capacity { 
-		Vec::with_capacity(capacity as usize) 
-	} else {
-		Vec::new()
-	}
+/*
+	Word and sentence lists are downloaded to files in this directory rather than into memory, so that an interrupted download 
+	can be resumed. They are removed once the source data has been read from them.
+*/
+fn download_directory() -> PathBuf {
+	Path::new(SAVE_DIRECTORY).join("downloads")
+}
+
+fn tatoeba_headers() -> reqwest::header::HeaderMap {
+	let mut headers = reqwest::header::HeaderMap::new();
+	headers.insert("cookie", reqwest::header::HeaderValue::from_static("csrfToken=a"));
+	headers.insert("x-csrf-token", reqwest::header::HeaderValue::from_static("a"));
+	headers
 }
-fn optional_progress(length_received: usize, total_length: Option<u64>) -> f32 {
+
+fn optional_progress(length_received: u64, total_length: Option<u64>) -> f32 {
 	if let Some(total_length) = total_length {
 		length_received as f32 / total_length as f32
 	} else {
@@ -537,105 +869,120 @@ fn optional_progress(length_received: usize, total_length: Option<u64>) -> f32 {
 	} 
 }
 
+pub fn language_index(name: &str) -> Result<usize> {
+	LANGUAGES.iter().position(|language| language.name == name)
+		.ok_or_else(|| Error::MissingResource(format!("unknown language {}", name)))
+}
+
 impl<F: Fn(SourceDataDownloadStatus)> SourceDataDownloader<F> {
-	fn new(info: SourceDataInfo, status_callback: F) -> Self {
-		SourceDataDownloader {
-			client: reqwest::Client::new(),
-			target_language_index: LANGUAGES.iter().position(|language| language.name == info.target_language).unwrap(),
+	fn new(info: SourceDataInfo, status_callback: F) -> Result<Self> {
+		let client = reqwest::Client::new();
+		Ok(SourceDataDownloader {
+			downloader: Downloader::new(client.clone()),
+			tatoeba_downloader: Downloader::new(client.clone()).with_headers(tatoeba_headers()),
+			config: SourceConfig::load()?,
+			client,
+			target_language_index: language_index(&info.target_language)?,
 			info,
 			status_callback
-		}
+		})
 	}
 
-	async fn download(&self) -> SourceData {
-		let word_list = self.download_words().await;
-		let sentence_list = self.download_sentence_lists().await;
-		self.download_piper_voices().await;
+	async fn download(&self) -> Result<SourceData> {
+		let word_list_path = self.download_words().await?;
+		let sentence_list_paths = self.download_sentence_lists().await?;
+		self.download_piper_voices().await?;
+
+		let word_list = fs::read(&word_list_path)?;
+		let sentence_lists = read_downloaded_sentence_lists(sentence_list_paths)?;
+		remove_downloaded_file(&word_list_path);
 
-		SourceData {
+		Ok(SourceData {
 			language_index: self.target_language_index,
 			word_list,
-			sentence_list,
-		}
+			sentence_lists,
+			lemma_list: fs::read(SourceData::lemma_list_file_name(self.target_language_index)).ok(),
+		})
 	}
 	
-	async fn download_words(&self) -> Vec<u8> {
+	async fn download_words(&self) -> Result<PathBuf> {
 		let language = &LANGUAGES[self.target_language_index];
+		let path = download_directory().join(format!("{}_words.txt", language.name));
 
-		// GitHub repository for the word frequency data by Hermit Dave: https://github.com/hermitdave/FrequencyWords/tree/master.
-		let words_url = "https://raw.githubusercontent.com/hermitdave/FrequencyWords/master/content/2018";
-
-		let response = self.client.get(format!("{0}/{1}/{1}_50k.txt", words_url, language.id_2)).send().await;
-
-		let mut response = 
-			if let Ok(response) = response && response.status().is_success() { 
-				response
-			} else {
-				self.client.get(format!("{0}/{1}/{1}_full.txt", words_url, language.id_2)).send().await.unwrap()
-			};
-
-		let length = response.content_length();
-			
-		let mut word_list_data = vec_with_optional_capacity(length);
-
-		while let Ok(Some(chunk)) = response.chunk().await {
-			word_list_data.extend_from_slice(&chunk);
-
+		let progress = |received, total| {
 			(self.status_callback)(SourceDataDownloadStatus::DownloadingWords { 
-				progress: optional_progress(word_list_data.len(), length)
+				progress: optional_progress(received, total)
 			});
+		};
+
+		// A list left by an earlier attempt that got damaged since is replaced.
+		if path.exists() && let Err(error) = check_downloaded_list(&path, check_word_list) {
+			eprintln!("Downloading the word frequency list again: {}", error);
 		}
 
-		word_list_data
+		// Not every language has a list of the 50k most common words, in which case the full list is used.
+		let url = self.config.word_list_url(language.id_2, &format!("{}_50k.txt", language.id_2));
+		match self.downloader.download_file(&url, &path, progress).await {
+			Err(Error::MissingResource(_)) => {
+				let url = self.config.word_list_url(language.id_2, &format!("{}_full.txt", language.id_2));
+				self.downloader.download_file(&url, &path, progress).await
+					.map_err(|error| error.context("downloading the word frequency list"))?;
+			}
+			result => result.map_err(|error| error.context("downloading the word frequency list"))?,
+		}
+		check_downloaded_list(&path, check_word_list)?;
+
+		Ok(path)
 	}
 
-	async fn download_sentence_lists(&self) -> Vec<u8> {
-		let mut sentence_lists = Vec::new();
-		
-		let translation_languages = self.info.translation_languages.iter()
-			.map(|name| LANGUAGES.iter().find(|language| language.name == name).unwrap());
+	async fn download_sentence_lists(&self) -> Result<Vec<(usize, PathBuf)>> {
+		let mut paths = Vec::new();
 		
-		for translation_language in translation_languages {
-			(self.status_callback)(SourceDataDownloadStatus::PreparingSentenceFile { 
-				translation_language: translation_language.name.to_owned()
-			});
-			let list = self.download_sentence_list(translation_language).await;
-			// Strip BOM.
-			let list = list.strip_prefix("\u{feff}".as_bytes()).unwrap_or(&list);
-			sentence_lists.extend_from_slice(list);
+		for name in &self.info.translation_languages {
+			let translation_language_index = language_index(name)?;
+			let translation_language = &LANGUAGES[translation_language_index];
+
+			let path = download_directory().join(format!(
+				"{}_{}_sentences.tsv", LANGUAGES[self.target_language_index].name, translation_language.name
+			));
+			// The list may have been downloaded already by an earlier attempt that failed at a later step.
+			if path.exists() && let Err(error) = check_downloaded_list(&path, check_sentence_list) {
+				eprintln!("Downloading {} translations again: {}", translation_language.name, error);
+			}
+			if !path.exists() {
+				(self.status_callback)(SourceDataDownloadStatus::PreparingSentenceFile { 
+					translation_language: translation_language.name.to_owned()
+				});
+				self.download_sentence_list(translation_language, &path).await
+					.map_err(|error| error.context(format!("downloading {} translations", translation_language.name)))?;
+				check_downloaded_list(&path, check_sentence_list)?;
+			}
+			paths.push((translation_language_index, path));
 		}
 
-		sentence_lists
+		Ok(paths)
 	}
 
-	async fn download_sentence_list(&self, translation_language: &Language) -> Vec<u8> {
-		let list_id = self.prepare_sentence_list(translation_language).await;
+	async fn download_sentence_list(&self, translation_language: &Language, path: &Path) -> Result<()> {
+		let list_id = self.prepare_sentence_list(translation_language).await?;
 		
-		let filename = self.wait_for_sentence_list_filename(list_id).await;
-
-		let mut response = self.client.get(format!("https://tatoeba.org/en/exports/download/{}/{}", list_id, filename))
-			.header("cookie", "csrfToken=a")
-			.header("x-csrf-token", "a")
-			.send().await.unwrap();
-		
-		let length = response.content_length();
-		
-		let mut sentence_list_data = vec_with_optional_capacity(length);
-
-		while let Ok(Some(chunk)) = response.chunk().await {
-			sentence_list_data.extend_from_slice(&chunk);
+		let filename = self.wait_for_sentence_list_filename(list_id).await?;
 
+		/*
+			Tatoeba prepares a new export with a new URL every time, so a partial file from an earlier export isn't resumed, 
+			but retries within this download are.
+		*/
+		let url = self.config.tatoeba_export_url(&format!("download/{}/{}", list_id, filename));
+		self.tatoeba_downloader.download_file(&url, path, |received, total| {
 			(self.status_callback)(SourceDataDownloadStatus::DownloadingSentenceFile {
 				translation_language: translation_language.name.to_owned(),
-				progress: optional_progress(sentence_list_data.len(), length)
+				progress: optional_progress(received, total)
 			});
-		}
-		
-		sentence_list_data
+		}).await
 	}
 
-	async fn prepare_sentence_list(&self, translation_language: &Language) -> i64 {
-		let response = self.client.post("https://tatoeba.org/en/exports/add")
+	async fn prepare_sentence_list(&self, translation_language: &Language) -> Result<i64> {
+		let response = self.client.post(self.config.tatoeba_export_url("add"))
 		    .form(&[
 		        ("fields[]", "id"),
 		        ("fields[]", "text"),
@@ -646,84 +993,162 @@ impl<F: Fn(SourceDataDownloadStatus)> SourceDataDownloader<F> {
 		        ("to", &translation_language.id_3),
 		        ("type", "pairs")
 		    ])
-		    .header("cookie", "csrfToken=a")
-		    .header("x-csrf-token", "a")
-		    .send().await.unwrap()
-		    .json::<serde_json::Value>().await.unwrap();
+		    .headers(tatoeba_headers())
+		    .send().await?
+		    .error_for_status()?
+		    .json::<serde_json::Value>().await?;
 
-		response["export"]["id"].as_i64().unwrap()
+		response["export"]["id"].as_i64()
+			.ok_or_else(|| Error::Parse(format!("Tatoeba export response has no id: {}", response)))
 	}
 
-	async fn wait_for_sentence_list_filename(&self, id: i64) -> String {
+	async fn wait_for_sentence_list_filename(&self, id: i64) -> Result<String> {
 		loop {
-			let response = self.client.get(format!("https://tatoeba.org/en/exports/get/{}", id))
-				.header("cookie", "csrfToken=a")
-				.header("x-csrf-token", "a")
-				.send().await.unwrap()
-				.json::<serde_json::Value>().await.unwrap();
+			let response = self.client.get(self.config.tatoeba_export_url(&format!("get/{}", id)))
+				.headers(tatoeba_headers())
+				.send().await?
+				.error_for_status()?
+				.json::<serde_json::Value>().await?;
 			
 			let export = &response["export"];
 
 			if export["status"] == "online" {
-				return export["pretty_filename"].to_string();
+				return Ok(export["pretty_filename"].to_string());
+			}
+			if export["status"] == "failed" {
+				return Err(Error::Network(format!("Tatoeba failed to prepare export {}", id)));
 			}
 
 			std::thread::sleep(std::time::Duration::from_secs(1));
 		}
 	}
 
-	async fn download_piper_voices(&self) {
-		let language = &LANGUAGES[self.target_language_index];
+	/*
+		Only the best voice is downloaded with the language, others can be added later in the options.
+	*/
+	async fn download_piper_voices(&self) -> Result<()> {
+		let catalogue = VoiceCatalogue::load(&self.config).await?;
+		let Some(voice) = catalogue.default_voice(self.target_language_index) else {
+			return Ok(());
+		};
 
-		for (i, model) in language.piper_voices.iter().enumerate() {
-			let mut file_name = model.replace('/', "-") + ".onnx";
-			let mut url = format!("https://huggingface.co/rhasspy/piper-voices/resolve/v1.0.0/{}/{}/{}", language.id_2, model, file_name);
-			self.download_piper_voice(&url, &file_name, i, false).await;
+		let [model_file_name, configuration_file_name] = voices::file_names(&voice.name);
+		let url = self.config.piper_voices_file_url(&voice.model.path);
+		self.download_piper_voice(&url, &model_file_name, &voice.model.expected(), false).await?;
 
-			file_name += ".json";
-			url += ".json";
-			self.download_piper_voice(&url, &file_name, i, true).await;
-		}
+		let url = self.config.piper_voices_file_url(&voice.configuration.path);
+		self.download_piper_voice(&url, &configuration_file_name, &voice.configuration.expected(), true).await
 	}
 
-	async fn download_piper_voice(&self, url: &str, file_name: &str, index: usize, is_configuration: bool) {
-		let mut response = self.client.get(url).send().await.unwrap();
-
-		let length = response.content_length();
-
-		let directory = format!("{}/voices", SAVE_DIRECTORY);
-		fs::create_dir_all(&directory).unwrap();
-		
-		let file = fs::File::options().read(false).write(true).create_new(true).open(directory + "/" + file_name);
-		let mut file = match file {
-			Ok(file) => file,
-			Err(error) => match error.kind() {
-				std::io::ErrorKind::AlreadyExists => return,
-				_ => panic!("Error creating piper voice file: {}", error)
-			}
-		};
-
-		let mut file_data = vec_with_optional_capacity(length);
-
-		while let Ok(Some(chunk)) = response.chunk().await {
-			/*
-				I am not sure if we should write the chunks directly to the file or read them in a buffer first.
-				For now we do the latter to avoid partially written files if the program was shut down in the middle of downloading 
-				or something.
-			 */
-			// file.write_all(&chunk).unwrap();
-			file_data.extend_from_slice(&chunk);
+	async fn download_piper_voice(&self, url: &str, file_name: &str, expected: &ExpectedFile, is_configuration: bool) -> Result<()> {
+		let path = voice_directory().join(file_name);
 
+		// Voices that are already installed, for example because another language uses them, are not downloaded again.
+		self.downloader.download_verified_file(url, &path, expected, |received, total| {
 			// Configuration files are so small in comparison that they don't need any progress feedback.
 			if !is_configuration {
 				(self.status_callback)(SourceDataDownloadStatus::DownloadingVoiceModel { 
-					index, 
-					total: LANGUAGES[self.target_language_index].piper_voices.len(), 
-					progress: optional_progress(file_data.len(), length)
+					index: 0, 
+					total: 1, 
+					progress: optional_progress(received, total)
 				});
 			}
+		}).await
+		.map_err(|error| error.context(format!("downloading voice {}", file_name)))
+	}
+}
+
+//----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn word_lists_are_checked() {
+		assert!(check_word_list(b"the 120\nhouse 45\n").is_ok());
+		assert!(matches!(check_word_list(b"the 120\nhouse\n"), Err(Error::Parse(_))));
+		assert!(matches!(check_word_list(b"the many\n"), Err(Error::Parse(_))));
+		assert!(matches!(check_word_list(b""), Err(Error::Parse(_))));
+	}
+
+	#[test]
+	fn sentence_lists_are_checked() {
+		assert!(check_sentence_list(b"1\tThe house.\t2\tDas Haus.\n").is_ok());
+		assert!(check_sentence_list("\u{feff}1\tThe house.\t2\tDas Haus.\n".as_bytes()).is_ok());
+		assert!(matches!(check_sentence_list(b"1\tThe house.\tDas Haus.\n"), Err(Error::Parse(_))));
+		assert!(matches!(check_sentence_list(b"one\tThe house.\t2\tDas Haus.\n"), Err(Error::Parse(_))));
+		assert!(matches!(check_sentence_list("\u{feff}".as_bytes()), Err(Error::Parse(_))));
+	}
+
+	#[test]
+	fn truncated_downloads_are_removed() {
+		let path = std::env::temp_dir().join(format!("gurksaft_downloaded_list_test_{}", std::process::id()));
+		fs::write(&path, b"the 120\nhouse 45\n").unwrap();
+		assert!(check_downloaded_list(&path, check_word_list).is_ok());
+
+		fs::write(&path, b"the 120\nhou").unwrap();
+		assert!(matches!(check_downloaded_list(&path, check_word_list), Err(Error::Parse(_))));
+		assert!(!path.exists());
+	}
+
+	#[test]
+	fn shared_voices_are_kept() {
+		let language_index = |name| language_index(name).unwrap();
+		let bokmal = language_index("Norwegian Bokmål");
+		let danish = language_index("Danish");
+		let swedish = language_index("Swedish");
+
+		let directory = std::env::temp_dir().join(format!("gurksaft_shared_voices_test_{}", std::process::id()));
+		fs::create_dir_all(&directory).unwrap();
+		for file_name in voices::file_names("no_NO/talesyntese/medium") {
+			fs::write(directory.join(file_name), b"").unwrap();
 		}
+		let installed_voices = ["no_NO/talesyntese/medium".to_owned()];
+		let unused = |remaining_languages: &[usize], enabled_voices: &[String]| {
+			unused_voice_file_names(&installed_voices, &voices_in_use(&directory, remaining_languages, enabled_voices))
+		};
+
+		// Bokmål can use the voices of Nynorsk even if it hasn't enabled them.
+		assert!(unused(&[bokmal, swedish], &[]).is_empty());
+		assert!(unused(&[danish], &installed_voices).is_empty());
+		assert_eq!(unused(&[danish, swedish], &[]), ["no_NO-talesyntese-medium.onnx", "no_NO-talesyntese-medium.onnx.json"]);
+		fs::remove_dir_all(&directory).unwrap();
+	}
+
+	#[test]
+	fn missing_import_files_are_reported() {
+		let info = SourceDataImportInfo {
+			target_language: "English".to_owned(),
+			word_list_path: "does/not/exist.txt".to_owned(),
+			sentence_lists: vec![],
+			voice_directory: None,
+		};
+		assert!(matches!(SourceData::import(info), Err(Error::MissingResource(_))));
+	}
+
+	#[test]
+	fn imports_without_sentences_are_rejected() {
+		let directory = std::env::temp_dir().join(format!("gurksaft_empty_import_test_{}", std::process::id()));
+		fs::create_dir_all(&directory).unwrap();
+		let word_list_path = directory.join("words.txt");
+		let sentence_list_path = directory.join("sentences.tsv");
+		fs::write(&word_list_path, b"the 120\nhouse 45\n").unwrap();
+		fs::write(&sentence_list_path, b"").unwrap();
 
-		file.write_all(&file_data).unwrap();
+		let import = |sentence_list_paths: &[&Path]| SourceData::import(SourceDataImportInfo {
+			target_language: "English".to_owned(),
+			word_list_path: word_list_path.to_string_lossy().into_owned(),
+			sentence_lists: sentence_list_paths.iter()
+				.map(|path| SentenceListImportInfo {
+					translation_language: "German".to_owned(),
+					path: path.to_string_lossy().into_owned(),
+				})
+				.collect(),
+			voice_directory: None,
+		});
+		assert!(matches!(import(&[]), Err(Error::Parse(_))));
+		assert!(matches!(import(&[&sentence_list_path]), Err(Error::Parse(_))));
+		fs::remove_dir_all(&directory).unwrap();
 	}
 }
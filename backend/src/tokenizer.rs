@@ -0,0 +1,338 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+//----------------------------------------------------------------
+
+/*
+	A word within a piece of text, without any surrounding punctuation.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token<'a> {
+	pub text: &'a str,
+	// Number of characters before the word within the text. This is what the front-end uses to place the word.
+	pub position: usize,
+}
+
+impl<'a> Token<'a> {
+	fn new(text: &'a str, start: usize, end: usize) -> Self {
+		Token {
+			text: &text[start..end],
+			position: text[..start].chars().count(),
+		}
+	}
+}
+
+//----------------------------------------------------------------
+
+fn is_word_segment(segment: &str) -> bool {
+	segment.chars().any(char::is_alphanumeric)
+}
+
+/*
+	Hyphens that join two words into a single word, like in "well-known" or "peut-être". The word frequency lists contain
+	such compounds as single entries so we keep them together even though Unicode word segmentation splits them.
+*/
+fn is_joining_hyphen(segment: &str) -> bool {
+	matches!(segment, "-" | "\u{2010}" | "\u{2011}")
+}
+
+fn is_punctuation(c: char) -> bool {
+	c.is_ascii_punctuation() || matches!(c,
+		'¿' | '¡' | '«' | '»' | '‹' | '›' | '‘' | '’' | '‚' | '‛' | '“' | '”' | '„' | '‟' | '…' | '‐' | '‑' | '–' | '—' |
+		'、' | '。' | '「' | '」' | '『' | '』' | '（' | '）' | '！' | '？' | '،' | '؛' | '؟' | '।'
+	)
+}
+
+/*
+	Splits text into words using Unicode word segmentation (UAX #29). Words keep inner apostrophes and hyphens while any
+	leading or trailing punctuation is stripped, so "¿Qué?" gives "Qué" and "house," gives "house".
+*/
+pub fn words(text: &str) -> Vec<Token<'_>> {
+	let mut tokens = Vec::new();
+
+	// Byte range of the word that is currently being built.
+	let mut current: Option<(usize, usize)> = None;
+	// Whether the last segment was a hyphen directly after the current word.
+	let mut after_hyphen = false;
+
+	let mut finish_word = |range: Option<(usize, usize)>| {
+		if let Some((start, end)) = range {
+			let word = &text[start..end];
+			let trimmed_start = word.trim_start_matches(is_punctuation);
+			let start = start + word.len() - trimmed_start.len();
+			let end = start + trimmed_start.trim_end_matches(is_punctuation).len();
+			if start < end {
+				tokens.push(Token::new(text, start, end));
+			}
+		}
+	};
+
+	for (offset, segment) in text.split_word_bound_indices() {
+		if is_word_segment(segment) {
+			match current {
+				Some((start, _)) if after_hyphen => current = Some((start, offset + segment.len())),
+				_ => {
+					finish_word(current);
+					current = Some((offset, offset + segment.len()));
+				}
+			}
+			after_hyphen = false;
+		}
+		else if current.is_some() && !after_hyphen && is_joining_hyphen(segment) {
+			after_hyphen = true;
+		}
+		else {
+			finish_word(current.take());
+			after_hyphen = false;
+		}
+	}
+	finish_word(current);
+
+	tokens
+}
+
+//----------------------------------------------------------------
+
+/*
+	How a language separates words from each other.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum WordSegmentation {
+	// Words are separated by spaces and/or punctuation.
+	Unicode,
+	/*
+		Words are written without spaces in between (scriptio continua), like in Japanese and Thai. Words are found by 
+		repeatedly matching the longest word from the word frequency list at the current position in the text.
+	*/
+	LongestMatch,
+}
+
+// Longer words than this are not considered when looking for the longest match, to keep segmentation reasonably fast.
+const MAX_DICTIONARY_WORD_LENGTH: usize = 16;
+
+pub struct Dictionary {
+	words: HashSet<String>,
+	// Length in graphemes of the longest word in the dictionary.
+	max_word_length: usize,
+}
+
+impl Dictionary {
+	fn new<'a>(words: impl IntoIterator<Item = &'a str>) -> Self {
+		let words: HashSet<String> = words.into_iter().map(str::to_owned).collect();
+		let max_word_length = words.iter()
+			.map(|word| word.graphemes(true).count())
+			.max()
+			.unwrap_or(0)
+			.min(MAX_DICTIONARY_WORD_LENGTH);
+
+		Dictionary { words, max_word_length }
+	}
+
+	// The words are lowercase so the candidate is lowercased before looking it up.
+	fn contains(&self, candidate: &str) -> bool {
+		if candidate.chars().any(char::is_uppercase) {
+			self.words.contains(&candidate.to_lowercase())
+		}
+		else {
+			self.words.contains(candidate)
+		}
+	}
+}
+
+/*
+	Splits text into runs of characters that are not whitespace or punctuation, returning the byte offset of each run together with it.
+*/
+fn unspaced_chunks(text: &str) -> impl Iterator<Item = (usize, &str)> {
+	text.split(|c: char| c.is_whitespace() || is_punctuation(c))
+		.filter(|chunk| !chunk.is_empty())
+		.map(move |chunk| (chunk.as_ptr() as usize - text.as_ptr() as usize, chunk))
+}
+
+/*
+	Greedy longest match segmentation. At each position within a chunk we take the longest dictionary word that starts there.
+	Consecutive graphemes that don't start any dictionary word are kept together as a single unknown word.
+*/
+fn longest_match_words<'a>(text: &'a str, dictionary: &Dictionary) -> Vec<Token<'a>> {
+	let mut tokens = Vec::new();
+
+	for (chunk_offset, chunk) in unspaced_chunks(text) {
+		let boundaries: Vec<usize> = chunk.grapheme_indices(true)
+			.map(|(i, _grapheme)| i)
+			.chain(std::iter::once(chunk.len()))
+			.collect();
+
+		let mut push_word = |start: usize, end: usize| tokens.push(Token::new(text, chunk_offset + start, chunk_offset + end));
+
+		let mut unknown_start = None;
+		let mut i = 0;
+
+		while i + 1 < boundaries.len() {
+			let longest_end = (i + 1..=usize::min(i + dictionary.max_word_length, boundaries.len() - 1))
+				.rev()
+				.find(|&j| dictionary.contains(&chunk[boundaries[i]..boundaries[j]]));
+
+			if let Some(end) = longest_end {
+				if let Some(start) = unknown_start.take() {
+					push_word(start, boundaries[i]);
+				}
+				push_word(boundaries[i], boundaries[end]);
+				i = end;
+			}
+			else {
+				unknown_start.get_or_insert(boundaries[i]);
+				i += 1;
+			}
+		}
+		if let Some(start) = unknown_start {
+			push_word(start, chunk.len());
+		}
+	}
+
+	tokens
+}
+
+//----------------------------------------------------------------
+
+pub enum Tokenizer {
+	Unicode,
+	LongestMatch(Dictionary),
+}
+
+impl Tokenizer {
+	/*
+		The words are the lowercase entries of the word frequency list, which are used as the dictionary for languages that 
+		need one.
+	*/
+	pub fn new<'a>(segmentation: WordSegmentation, words: impl IntoIterator<Item = &'a str>) -> Self {
+		match segmentation {
+			WordSegmentation::Unicode => Tokenizer::Unicode,
+			WordSegmentation::LongestMatch => Tokenizer::LongestMatch(Dictionary::new(words)),
+		}
+	}
+
+	pub fn words<'a>(&self, text: &'a str) -> Vec<Token<'a>> {
+		match self {
+			Tokenizer::Unicode => words(text),
+			Tokenizer::LongestMatch(dictionary) => longest_match_words(text, dictionary),
+		}
+	}
+}
+
+//----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn word_texts(text: &str) -> Vec<&str> {
+		words(text).into_iter().map(|token| token.text).collect()
+	}
+	fn word_positions(text: &str) -> Vec<usize> {
+		words(text).into_iter().map(|token| token.position).collect()
+	}
+	fn contains_word(tokenizer: &Tokenizer, text: &str, word_to_find: &str) -> bool {
+		tokenizer.words(text).iter().any(|word| word.text == word_to_find)
+	}
+	fn longest_match_texts<'a>(dictionary: &[&str], text: &'a str) -> Vec<&'a str> {
+		let tokenizer = Tokenizer::new(WordSegmentation::LongestMatch, dictionary.iter().copied());
+		tokenizer.words(text).into_iter().map(|token| token.text).collect()
+	}
+
+	#[test]
+	fn whitespace() {
+		assert_eq!(word_texts("the quick  brown\tfox"), ["the", "quick", "brown", "fox"]);
+		assert_eq!(word_texts(""), Vec::<&str>::new());
+		assert_eq!(word_texts("   "), Vec::<&str>::new());
+	}
+
+	#[test]
+	fn punctuation() {
+		assert_eq!(word_texts("I saw the house, then I left."), ["I", "saw", "the", "house", "then", "I", "left"]);
+		assert_eq!(word_texts("¿Qué hora es?"), ["Qué", "hora", "es"]);
+		assert_eq!(word_texts("¡Hola!"), ["Hola"]);
+		assert_eq!(word_texts("\"Yes,\" he said... (quietly)"), ["Yes", "he", "said", "quietly"]);
+		assert_eq!(word_texts("«Bonjour», dit-il."), ["Bonjour", "dit-il"]);
+		assert_eq!(word_texts("Wait - what?!"), ["Wait", "what"]);
+	}
+
+	#[test]
+	fn apostrophes() {
+		assert_eq!(word_texts("Don't do that."), ["Don't", "do", "that"]);
+		assert_eq!(word_texts("Don’t do that."), ["Don’t", "do", "that"]);
+		assert_eq!(word_texts("C'est l'homme."), ["C'est", "l'homme"]);
+		assert_eq!(word_texts("'Quoted' words"), ["Quoted", "words"]);
+	}
+
+	#[test]
+	fn hyphens() {
+		assert_eq!(word_texts("A well-known fact."), ["A", "well-known", "fact"]);
+		assert_eq!(word_texts("Peut-être demain."), ["Peut-être", "demain"]);
+		assert_eq!(word_texts("mother-in-law"), ["mother-in-law"]);
+		assert_eq!(word_texts("pre- and post-war"), ["pre", "and", "post-war"]);
+		assert_eq!(word_texts("-- dashes --"), ["dashes"]);
+	}
+
+	#[test]
+	fn numbers() {
+		assert_eq!(word_texts("I have 3 cats and 2.5 dogs."), ["I", "have", "3", "cats", "and", "2.5", "dogs"]);
+	}
+
+	#[test]
+	fn non_latin_scripts() {
+		assert_eq!(word_texts("Привет, мир!"), ["Привет", "мир"]);
+		assert_eq!(word_texts("Καλημέρα κόσμε."), ["Καλημέρα", "κόσμε"]);
+		assert_eq!(word_texts("مرحبا بالعالم؟"), ["مرحبا", "بالعالم"]);
+		assert_eq!(word_texts("नमस्ते दुनिया।"), ["नमस्ते", "दुनिया"]);
+		assert_eq!(word_texts("שלום, עולם"), ["שלום", "עולם"]);
+	}
+
+	#[test]
+	fn positions() {
+		assert_eq!(word_positions("åäöasdf hej"), [0, 8]);
+		assert_eq!(word_positions("hej åäöasdf"), [0, 4]);
+		assert_eq!(word_positions("¿Qué hora es?"), [1, 5, 10]);
+		assert_eq!(word_positions("Привет, мир!"), [0, 8]);
+
+		let text = "«Bonjour», dit-il.";
+		for token in words(text) {
+			assert_eq!(text.chars().skip(token.position).take(token.text.chars().count()).collect::<String>(), token.text);
+		}
+	}
+	#[test]
+	fn longest_match_japanese() {
+		let dictionary = ["私", "は", "学", "学生", "です", "か"];
+		assert_eq!(longest_match_texts(&dictionary, "私は学生です。"), ["私", "は", "学生", "です"]);
+		assert_eq!(longest_match_texts(&dictionary, "「学生ですか？」"), ["学生", "です", "か"]);
+
+		let tokenizer = Tokenizer::new(WordSegmentation::LongestMatch, dictionary);
+		let positions: Vec<usize> = tokenizer.words("私は学生です。").into_iter().map(|token| token.position).collect();
+		assert_eq!(positions, [0, 1, 2, 4]);
+		assert!(contains_word(&tokenizer, "私は学生です。", "学生"));
+		assert!(!contains_word(&tokenizer, "私は学生です。", "学"));
+	}
+
+	#[test]
+	fn longest_match_thai() {
+		let dictionary = ["ฉัน", "รัก", "แมว", "แม่"];
+		assert_eq!(longest_match_texts(&dictionary, "ฉันรักแมว"), ["ฉัน", "รัก", "แมว"]);
+		assert_eq!(longest_match_texts(&dictionary, "ฉันรักแม่ ฉันรักแมว"), ["ฉัน", "รัก", "แม่", "ฉัน", "รัก", "แมว"]);
+	}
+
+	#[test]
+	fn longest_match_unknown_words() {
+		let dictionary = ["tom", "は", "学生"];
+		assert_eq!(longest_match_texts(&dictionary, "トムは学生"), ["トム", "は", "学生"]);
+		assert_eq!(longest_match_texts(&dictionary, "Tomは学生"), ["Tom", "は", "学生"]);
+		assert_eq!(longest_match_texts(&dictionary, "学生だ"), ["学生", "だ"]);
+		assert_eq!(longest_match_texts(&[], "学生だ"), ["学生だ"]);
+	}
+
+	#[test]
+	fn unicode_tokenizer() {
+		let tokenizer = Tokenizer::new(WordSegmentation::Unicode, []);
+		assert!(contains_word(&tokenizer, "¿qué hora es?", "qué"));
+		assert!(!contains_word(&tokenizer, "the houses", "house"));
+	}
+}
@@ -0,0 +1,307 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use serde::Deserialize;
+
+use crate::{
+	download::Downloader,
+	error::{Error, Result},
+	integrity::ExpectedFile,
+	save_file,
+	source_config::SourceConfig,
+	source_data::{self, LANGUAGES},
+	voices,
+};
+
+//----------------------------------------------------------------
+
+/*
+	The voices that are available in the piper-voices repository, read from the voices.json manifest at its root. The
+	manifest is downloaded once for every version of the repository set in SourceConfig and kept next to the voices.
+*/
+pub struct VoiceCatalogue {
+	voices: Vec<CatalogueVoice>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CatalogueVoice {
+	// The model path that the voice is known by elsewhere, such as "en_US/ryan/high", see voices.rs.
+	pub name: String,
+	// For example "en_US". The family is the part before the region, which is Language::id_2.
+	pub language_code: String,
+	pub language_family: String,
+	pub speaker: String,
+	pub quality: String,
+	// Some models have several speakers and each of them is used to read the sentences.
+	pub speaker_count: u32,
+	pub model: CatalogueFile,
+	pub configuration: CatalogueFile,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct CatalogueFile {
+	// Relative to the version of the repository, such as "en/en_US/ryan/high/en_US-ryan-high.onnx".
+	#[serde(skip)]
+	pub path: String,
+	pub size_bytes: u64,
+	pub md5_digest: String,
+}
+
+impl CatalogueVoice {
+	pub fn size_bytes(&self) -> u64 {
+		self.model.size_bytes + self.configuration.size_bytes
+	}
+}
+
+impl CatalogueFile {
+	pub fn expected(&self) -> ExpectedFile {
+		ExpectedFile { size_bytes: Some(self.size_bytes), md5_digest: Some(self.md5_digest.clone()) }
+	}
+}
+
+//----------------------------------------------------------------
+
+/*
+	The layout of voices.json, which maps keys like "en_US-ryan-high" to the voices.
+*/
+#[derive(Deserialize)]
+struct ManifestVoice {
+	name: String,
+	language: ManifestLanguage,
+	quality: String,
+	num_speakers: u32,
+	files: HashMap<String, CatalogueFile>,
+}
+
+#[derive(Deserialize)]
+struct ManifestLanguage {
+	code: String,
+	family: String,
+}
+
+impl VoiceCatalogue {
+	/*
+		Downloads the manifest if there isn't a copy of it yet.
+	*/
+	pub async fn load(config: &SourceConfig) -> Result<Self> {
+		let path = Self::path(config);
+		let downloader = Downloader::new(reqwest::Client::new());
+		downloader.download_file(&config.piper_voices_file_url("voices.json"), &path, |_, _| {}).await
+			.map_err(|error| error.context("downloading the voice catalogue"))?;
+
+		let data = fs::read(&path)?;
+		Self::parse(&data).or_else(|error| {
+			// Downloaded again next time in case the copy is damaged.
+			save_file::remove(&path)?;
+			Err(error.context(path.display()))
+		})
+	}
+
+	/*
+		Reads the copy of the manifest that was downloaded earlier without going online. None if there is no usable copy.
+	*/
+	pub fn load_downloaded(config: &SourceConfig) -> Option<Self> {
+		Self::parse(&fs::read(Self::path(config)).ok()?).ok()
+	}
+
+	fn path(config: &SourceConfig) -> PathBuf {
+		source_data::voice_directory().join(format!("voices-{}.json", config.piper_voices_version.replace('/', "-")))
+	}
+
+	/*
+		Voices that don't have the expected layout are skipped, so that one odd entry doesn't make the other voices 
+		unavailable. A catalogue without any usable voice is an error.
+	*/
+	fn parse(data: &[u8]) -> Result<Self> {
+		let manifest: HashMap<String, serde_json::Value> = serde_json::from_slice(data)?;
+
+		let mut voices = Vec::new();
+		for (key, voice) in manifest {
+			match Self::parse_voice(&key, voice) {
+				Ok(voice) => voices.push(voice),
+				Err(error) => eprintln!("Skipping {} in the voice catalogue: {}", key, error),
+			}
+		}
+		if voices.is_empty() {
+			return Err(Error::Parse("the voice catalogue contains no usable voices".to_owned()));
+		}
+		voices.sort_by(|a, b| a.name.cmp(&b.name));
+		Ok(Self { voices })
+	}
+
+	fn parse_voice(key: &str, voice: serde_json::Value) -> Result<CatalogueVoice> {
+		let voice: ManifestVoice = serde_json::from_value(voice)?;
+		let file = |extension: &str| {
+			voice.files.iter()
+				.find(|(path, _)| path.ends_with(&format!("{}{}", key, extension)))
+				.map(|(path, file)| CatalogueFile { path: path.clone(), ..file.clone() })
+				.ok_or_else(|| Error::Parse(format!("no {} file", extension)))
+		};
+		Ok(CatalogueVoice {
+			name: format!("{}/{}/{}", voice.language.code, voice.name, voice.quality),
+			model: file(".onnx")?,
+			configuration: file(".onnx.json")?,
+			language_code: voice.language.code,
+			language_family: voice.language.family,
+			speaker: voice.name,
+			quality: voice.quality,
+			speaker_count: voice.num_speakers,
+		})
+	}
+
+	/*
+		Returns the voices of the language with the preferred ones first, see voices::preference.
+	*/
+	pub fn language_voices(&self, language_index: usize) -> Vec<&CatalogueVoice> {
+		let mut voices: Vec<&CatalogueVoice> = self.voices.iter()
+			.filter(|voice| voice.language_family == LANGUAGES[language_index].id_2)
+			.collect();
+		voices.sort_by_key(|voice| voices::preference(language_index, &voice.name));
+		voices
+	}
+
+	pub fn find(&self, language_index: usize, name: &str) -> Result<&CatalogueVoice> {
+		self.language_voices(language_index).into_iter()
+			.find(|voice| voice.name == name)
+			.ok_or_else(|| Error::InvalidRequest(format!("{} is not a voice of {}", name, LANGUAGES[language_index].name)))
+	}
+
+	/*
+		The voice that is downloaded together with the language, or None if there is no voice for it.
+	*/
+	pub fn default_voice(&self, language_index: usize) -> Option<&CatalogueVoice> {
+		self.language_voices(language_index).into_iter().next()
+	}
+}
+
+//----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const MANIFEST: &[u8] = br#"{
+		"fi_FI-harri-low": {
+			"key": "fi_FI-harri-low",
+			"name": "harri",
+			"language": { "code": "fi_FI", "family": "fi", "region": "FI", "name_english": "Finnish" },
+			"quality": "low",
+			"num_speakers": 1,
+			"speaker_id_map": {},
+			"files": {
+				"fi/fi_FI/harri/low/fi_FI-harri-low.onnx": { "size_bytes": 63104526, "md5_digest": "bc8e0a4ce20c2e46ee1d6d8a8e6e3a9b" },
+				"fi/fi_FI/harri/low/fi_FI-harri-low.onnx.json": { "size_bytes": 4795, "md5_digest": "2ef4ae39a1e1e4d8c0ab2a3e8a5b0f2c" },
+				"fi/fi_FI/harri/low/MODEL_CARD": { "size_bytes": 274, "md5_digest": "d9b0f5cc3e5a3a8e43b3c3bcbb5b1c93" }
+			},
+			"aliases": []
+		},
+		"fi_FI-harri-medium": {
+			"key": "fi_FI-harri-medium",
+			"name": "harri",
+			"language": { "code": "fi_FI", "family": "fi", "region": "FI", "name_english": "Finnish" },
+			"quality": "medium",
+			"num_speakers": 1,
+			"speaker_id_map": {},
+			"files": {
+				"fi/fi_FI/harri/medium/fi_FI-harri-medium.onnx": { "size_bytes": 63201294, "md5_digest": "5e1a6d2b1f3f4f1c8e6c2b9d7a0c4e21" },
+				"fi/fi_FI/harri/medium/fi_FI-harri-medium.onnx.json": { "size_bytes": 4888, "md5_digest": "0f4e7bb4c3b2d1a08e7f6a5b4c3d2e1f" }
+			},
+			"aliases": []
+		},
+		"cs_CZ-jirka-medium": {
+			"key": "cs_CZ-jirka-medium",
+			"name": "jirka",
+			"language": { "code": "cs_CZ", "family": "cs", "region": "CZ", "name_english": "Czech" },
+			"quality": "medium",
+			"num_speakers": 1,
+			"speaker_id_map": {},
+			"files": {
+				"cs/cs_CZ/jirka/medium/cs_CZ-jirka-medium.onnx": { "size_bytes": 63201294, "md5_digest": "8e0b1c7d2a3f4e5d6c7b8a9f0e1d2c3b" },
+				"cs/cs_CZ/jirka/medium/cs_CZ-jirka-medium.onnx.json": { "size_bytes": 4920, "md5_digest": "1a2b3c4d5e6f708192a3b4c5d6e7f809" }
+			},
+			"aliases": []
+		},
+		"sv_SE-nst-medium": {
+			"key": "sv_SE-nst-medium",
+			"name": "nst",
+			"language": { "code": "sv_SE", "family": "sv", "region": "SE", "name_english": "Swedish" },
+			"quality": "medium",
+			"num_speakers": 1,
+			"speaker_id_map": {},
+			"files": {
+				"sv/sv_SE/nst/medium/sv_SE-nst-medium.onnx": { "size_bytes": 63201294, "md5_digest": "c4b1d5a6e7f8091a2b3c4d5e6f708192" },
+				"sv/sv_SE/nst/medium/sv_SE-nst-medium.onnx.json": { "size_bytes": 4954, "md5_digest": "9a8b7c6d5e4f30211203f4e5d6c7b8a9" }
+			},
+			"aliases": []
+		}
+	}"#;
+
+	#[test]
+	fn manifest_is_parsed() {
+		let catalogue = VoiceCatalogue::parse(MANIFEST).unwrap();
+		let finnish = source_data::language_index("Finnish").unwrap();
+
+		let voice = catalogue.find(finnish, "fi_FI/harri/medium").unwrap();
+		assert_eq!(voice.language_code, "fi_FI");
+		assert_eq!(voice.speaker, "harri");
+		assert_eq!(voice.quality, "medium");
+		assert_eq!(voice.model.path, "fi/fi_FI/harri/medium/fi_FI-harri-medium.onnx");
+		assert_eq!(voice.configuration.md5_digest, "0f4e7bb4c3b2d1a08e7f6a5b4c3d2e1f");
+		assert_eq!(voice.size_bytes(), 63201294 + 4888);
+
+		assert!(matches!(catalogue.find(finnish, "sv_SE/nst/medium"), Err(Error::InvalidRequest(_))));
+		assert!(matches!(VoiceCatalogue::parse(br#"{ "broken": {} }"#), Err(Error::Parse(_))));
+	}
+
+	#[test]
+	fn broken_voices_are_skipped() {
+		const MANIFEST_WITH_BROKEN_VOICES: &[u8] = br#"{
+			"fi_FI-harri-medium": {
+				"key": "fi_FI-harri-medium",
+				"name": "harri",
+				"language": { "code": "fi_FI", "family": "fi", "region": "FI", "name_english": "Finnish" },
+				"quality": "medium",
+				"num_speakers": 1,
+				"speaker_id_map": {},
+				"files": {
+					"fi/fi_FI/harri/medium/fi_FI-harri-medium.onnx": { "size_bytes": 63201294, "md5_digest": "5e1a6d2b1f3f4f1c8e6c2b9d7a0c4e21" },
+					"fi/fi_FI/harri/medium/fi_FI-harri-medium.onnx.json": { "size_bytes": 4888, "md5_digest": "0f4e7bb4c3b2d1a08e7f6a5b4c3d2e1f" }
+				},
+				"aliases": []
+			},
+			"fi_FI-harri-low": {
+				"key": "fi_FI-harri-low",
+				"name": "harri",
+				"language": { "code": "fi_FI", "family": "fi", "region": "FI", "name_english": "Finnish" },
+				"quality": "low",
+				"num_speakers": 1,
+				"speaker_id_map": {},
+				"files": {
+					"fi/fi_FI/harri/low/fi_FI-harri-low.onnx.json": { "size_bytes": 4795, "md5_digest": "2ef4ae39a1e1e4d8c0ab2a3e8a5b0f2c" }
+				},
+				"aliases": []
+			},
+			"sv_SE-nst-medium": {
+				"key": "sv_SE-nst-medium",
+				"name": "nst",
+				"quality": "medium"
+			}
+		}"#;
+
+		let catalogue = VoiceCatalogue::parse(MANIFEST_WITH_BROKEN_VOICES).unwrap();
+		let names: Vec<&str> = catalogue.voices.iter().map(|voice| voice.name.as_str()).collect();
+		assert_eq!(names, ["fi_FI/harri/medium"]);
+	}
+
+	#[test]
+	fn better_voices_come_first() {
+		let catalogue = VoiceCatalogue::parse(MANIFEST).unwrap();
+		let finnish = source_data::language_index("Finnish").unwrap();
+
+		let names: Vec<&str> = catalogue.language_voices(finnish).iter().map(|voice| voice.name.as_str()).collect();
+		assert_eq!(names, ["fi_FI/harri/medium", "fi_FI/harri/low"]);
+		assert_eq!(catalogue.default_voice(finnish).unwrap().name, "fi_FI/harri/medium");
+		assert!(catalogue.default_voice(source_data::language_index("Japanese").unwrap()).is_none());
+		// Czech has no voices listed in LANGUAGES, so its default comes from the catalogue alone.
+		assert_eq!(catalogue.default_voice(source_data::language_index("Czech").unwrap()).unwrap().name, "cs_CZ/jirka/medium");
+	}
+}
@@ -0,0 +1,286 @@
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+
+use crate::error::Result;
+
+//----------------------------------------------------------------
+
+pub const DEFAULT_ESTIMATED_MEMORY_BUDGET_BYTES: u64 = 1_000_000_000;
+
+/*
+	What is known about the model of a voice, see the get_voice_models command.
+*/
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct VoiceModelInfo {
+	pub voice: String,
+	pub loaded: bool,
+	// From the last time the model was loaded, or None if it hasn't been loaded since the app started.
+	pub load_time_ms: Option<u64>,
+	pub estimated_memory_bytes: Option<u64>,
+}
+
+struct LoadedModel<T> {
+	model: Arc<T>,
+	last_used: u64,
+}
+
+#[derive(Clone, Copy)]
+struct LoadStats {
+	load_time: Duration,
+	estimated_memory_bytes: u64,
+}
+
+/*
+	The voice models that are loaded, which are only loaded when they are first used. When their estimated memory use is over
+	the budget, the least recently used ones that aren't being used right now are unloaded.
+*/
+pub struct VoiceModels<T> {
+	loaded: HashMap<String, LoadedModel<T>>,
+	// Set once the model of the voice has finished loading, successfully or not, for whoever is waiting for it.
+	loading: HashMap<String, Arc<OnceCell<()>>>,
+	stats: HashMap<String, LoadStats>,
+	estimated_memory_budget_bytes: u64,
+	// Increases every time a model is used, which orders the models from least to most recently used.
+	use_counter: u64,
+}
+
+impl<T> VoiceModels<T> {
+	pub fn new(estimated_memory_budget_bytes: u64) -> Self {
+		Self {
+			loaded: HashMap::new(),
+			loading: HashMap::new(),
+			stats: HashMap::new(),
+			estimated_memory_budget_bytes,
+			use_counter: 0,
+		}
+	}
+
+	/*
+		Returns the model of the voice and loads it first if needed. load returns the model together with an estimate of how
+		much memory it takes up. Loading takes seconds, so the models are only locked to look the model up and to add it
+		afterwards, and other voices can be used in the meantime. Whoever needs a voice that is already being loaded waits
+		for that load instead of loading it again.
+	*/
+	pub fn get_or_load<F>(models: &Mutex<Self>, voice: &str, load: F) -> Result<Arc<T>>
+		where F: FnOnce() -> Result<(T, u64)>
+	{
+		let loading = loop {
+			let mut models_guard = models.lock().unwrap();
+			if let Some(model) = models_guard.get(voice) {
+				return Ok(model);
+			}
+			match models_guard.loading.get(voice).cloned() {
+				Some(loading) => {
+					drop(models_guard);
+					loading.wait();
+				}
+				None => {
+					let loading = Arc::new(OnceCell::new());
+					models_guard.loading.insert(voice.to_owned(), loading.clone());
+					break LoadingGuard { models, voice, loading };
+				}
+			}
+		};
+
+		let start_time = Instant::now();
+		let (model, estimated_memory_bytes) = load()?;
+		let model = Arc::new(model);
+
+		let mut models_guard = models.lock().unwrap();
+		models_guard.use_counter += 1;
+		let stats = LoadStats { load_time: start_time.elapsed(), estimated_memory_bytes };
+		models_guard.stats.insert(voice.to_owned(), stats);
+		let last_used = models_guard.use_counter;
+		models_guard.loaded.insert(voice.to_owned(), LoadedModel { model: model.clone(), last_used });
+		models_guard.unload_over_budget();
+		drop(models_guard);
+		drop(loading);
+		Ok(model)
+	}
+
+	fn get(&mut self, voice: &str) -> Option<Arc<T>> {
+		let loaded = self.loaded.get_mut(voice)?;
+		self.use_counter += 1;
+		loaded.last_used = self.use_counter;
+		Some(loaded.model.clone())
+	}
+
+	/*
+		Unloads the models of other voices, for example when the language is switched.
+	*/
+	pub fn retain_voices(&mut self, voices: &[String]) {
+		self.loaded.retain(|voice, _| voices.contains(voice));
+	}
+
+	pub fn set_memory_budget(&mut self, estimated_memory_budget_bytes: u64) {
+		self.estimated_memory_budget_bytes = estimated_memory_budget_bytes;
+		self.unload_over_budget();
+	}
+
+	pub fn info(&self, voices: &[String]) -> Vec<VoiceModelInfo> {
+		voices.iter()
+			.map(|voice| {
+				let stats = self.stats.get(voice);
+				VoiceModelInfo {
+					voice: voice.clone(),
+					loaded: self.loaded.contains_key(voice),
+					load_time_ms: stats.map(|stats| stats.load_time.as_millis() as u64),
+					estimated_memory_bytes: stats.map(|stats| stats.estimated_memory_bytes),
+				}
+			})
+			.collect()
+	}
+
+	fn estimated_memory_bytes(&self) -> u64 {
+		self.loaded.keys().filter_map(|voice| self.stats.get(voice)).map(|stats| stats.estimated_memory_bytes).sum()
+	}
+
+	/*
+		Models that are being used to generate speech are kept, since unloading them wouldn't free their memory until they
+		are done and the next clip of the voice would load a second copy. They count toward the budget until they are idle 
+		and unloaded the next time a model is loaded or the budget changes.
+	*/
+	fn unload_over_budget(&mut self) {
+		let mut idle_models: Vec<(String, u64)> = self.loaded.iter()
+			.filter(|(_, loaded)| Arc::strong_count(&loaded.model) == 1)
+			.map(|(voice, loaded)| (voice.clone(), loaded.last_used))
+			.collect();
+		idle_models.sort_by_key(|&(_, last_used)| last_used);
+
+		let mut estimated_memory_bytes = self.estimated_memory_bytes();
+		for (voice, _) in idle_models {
+			if estimated_memory_bytes <= self.estimated_memory_budget_bytes {
+				break;
+			}
+			self.loaded.remove(&voice);
+			estimated_memory_bytes -= self.stats.get(&voice).map_or(0, |stats| stats.estimated_memory_bytes);
+		}
+	}
+}
+
+/*
+	Ends a load when it is dropped, also when loading fails or panics, so that whoever waits for it stops waiting and loads
+	the model themselves if it isn't there.
+*/
+struct LoadingGuard<'a, T> {
+	models: &'a Mutex<VoiceModels<T>>,
+	voice: &'a str,
+	loading: Arc<OnceCell<()>>,
+}
+
+impl<T> Drop for LoadingGuard<'_, T> {
+	fn drop(&mut self) {
+		let mut models = self.models.lock().unwrap_or_else(|error| error.into_inner());
+		models.loading.remove(self.voice);
+		let _ = self.loading.set(());
+	}
+}
+
+//----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use std::{sync::mpsc, thread};
+
+	use super::*;
+	use crate::error::Error;
+
+	fn voices(names: &[&str]) -> Vec<String> {
+		names.iter().map(|&name| name.to_owned()).collect()
+	}
+
+	fn loaded_voices(models: &Mutex<VoiceModels<String>>, names: &[&str]) -> Vec<bool> {
+		models.lock().unwrap().info(&voices(names)).iter().map(|info| info.loaded).collect()
+	}
+
+	fn load(voice: &'static str) -> impl FnOnce() -> Result<(String, u64)> {
+		move || Ok((voice.to_uppercase(), 40))
+	}
+
+	#[test]
+	fn models_are_loaded_once_and_unloaded_over_budget() {
+		let models = Mutex::new(VoiceModels::new(100));
+
+		assert_eq!(*VoiceModels::get_or_load(&models, "a", load("a")).unwrap(), "A");
+		assert_eq!(*VoiceModels::get_or_load(&models, "b", load("b")).unwrap(), "B");
+		// Already loaded, so loading it again would be a mistake.
+		assert_eq!(*VoiceModels::get_or_load(&models, "a", || Err(Error::Cancelled)).unwrap(), "A");
+		VoiceModels::get_or_load(&models, "c", load("c")).unwrap();
+		assert_eq!(loaded_voices(&models, &["a", "b", "c"]), [true, false, true]);
+
+		let info = &models.lock().unwrap().info(&voices(&["b"]))[0];
+		assert_eq!(info.estimated_memory_bytes, Some(40));
+		assert!(info.load_time_ms.is_some());
+
+		models.lock().unwrap().set_memory_budget(10);
+		assert_eq!(loaded_voices(&models, &["a", "c"]), [false, false]);
+
+		VoiceModels::get_or_load(&models, "c", load("c")).unwrap();
+		models.lock().unwrap().retain_voices(&voices(&["a"]));
+		assert_eq!(loaded_voices(&models, &["c"]), [false]);
+		assert!(VoiceModels::get_or_load(&models, "d", || Err(Error::Cancelled)).is_err());
+		assert_eq!(
+			models.lock().unwrap().info(&voices(&["d"]))[0],
+			VoiceModelInfo { voice: "d".to_owned(), loaded: false, load_time_ms: None, estimated_memory_bytes: None }
+		);
+	}
+
+	#[test]
+	fn models_in_use_are_not_unloaded() {
+		let models = Mutex::new(VoiceModels::new(50));
+
+		let a = VoiceModels::get_or_load(&models, "a", load("a")).unwrap();
+		VoiceModels::get_or_load(&models, "b", load("b")).unwrap();
+		// Both were in use while b loaded, so neither could be unloaded and both count toward the budget.
+		assert_eq!(loaded_voices(&models, &["a", "b"]), [true, true]);
+		assert_eq!(models.lock().unwrap().estimated_memory_bytes(), 80);
+
+		// Unloading a would free nothing while it is used, so b is unloaded even though it was used more recently.
+		models.lock().unwrap().set_memory_budget(50);
+		assert_eq!(loaded_voices(&models, &["a", "b"]), [true, false]);
+		assert_eq!(models.lock().unwrap().estimated_memory_bytes(), 40);
+
+		drop(a);
+		VoiceModels::get_or_load(&models, "c", load("c")).unwrap();
+		assert_eq!(loaded_voices(&models, &["a", "b", "c"]), [false, false, true]);
+	}
+
+	#[test]
+	fn other_voices_are_used_while_a_model_loads() {
+		let models = Arc::new(Mutex::new(VoiceModels::new(1000)));
+		VoiceModels::get_or_load(&models, "a", load("a")).unwrap();
+
+		let (started_sender, started_receiver) = mpsc::channel();
+		let (finish_sender, finish_receiver) = mpsc::channel::<()>();
+		let loader = {
+			let models = models.clone();
+			thread::spawn(move || {
+				VoiceModels::get_or_load(&models, "b", move || {
+					started_sender.send(()).unwrap();
+					finish_receiver.recv().unwrap();
+					Ok(("B".to_owned(), 40))
+				}).unwrap()
+			})
+		};
+		started_receiver.recv().unwrap();
+
+		// b is still loading, but a can be used and the models can be looked at.
+		assert_eq!(*VoiceModels::get_or_load(&models, "a", || Err(Error::Cancelled)).unwrap(), "A");
+		assert_eq!(loaded_voices(&models, &["a", "b"]), [true, false]);
+
+		// A second request for b waits for the load that is running instead of loading b again.
+		let waiter = {
+			let models = models.clone();
+			thread::spawn(move || VoiceModels::get_or_load(&models, "b", || Err(Error::Cancelled)).unwrap())
+		};
+		finish_sender.send(()).unwrap();
+		assert_eq!(*loader.join().unwrap(), "B");
+		assert_eq!(*waiter.join().unwrap(), "B");
+	}
+}
@@ -0,0 +1,339 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	download::Downloader,
+	error::{Error, Result},
+	integrity::{self, DataVerification},
+	save_file,
+	source_config::SourceConfig,
+	source_data::{self, LANGUAGES},
+	voice_catalogue::VoiceCatalogue,
+};
+
+//----------------------------------------------------------------
+
+/*
+	Voices are named by their model path in the piper-voices repository without the language family, such as
+	"en_US/ryan/high". Each of them consists of a model file and a configuration file in source_data::voice_directory. Which
+	voices exist comes from the voice catalogue. The voices that are enabled for a language are kept in
+	Options::enabled_voices and only those are loaded for speech synthesis.
+*/
+
+#[derive(Debug, Serialize)]
+pub struct VoiceInfo {
+	pub name: String,
+	pub speaker: String,
+	// The quality tier from the model path: "x_low", "low", "medium" or "high". Higher quality models are bigger and slower.
+	pub quality: String,
+	pub installed: bool,
+	pub enabled: bool,
+	pub size_bytes: u64,
+}
+
+const QUALITY_TIERS: [&str; 4] = ["x_low", "low", "medium", "high"];
+
+fn quality(name: &str) -> &str {
+	name.rsplit('/').next().unwrap_or(name)
+}
+
+pub fn file_names(name: &str) -> [String; 2] {
+	let model_file_name = name.replace('/', "-") + ".onnx";
+	[model_file_name.clone(), model_file_name + ".json"]
+}
+
+/*
+	Left next to the files of a voice by downloads that haven't finished, see download::Downloader, and by interrupted
+	writes, see save_file::write_atomic.
+*/
+pub const PARTIAL_FILE_SUFFIXES: [&str; 3] = [".part.json", ".part", ".tmp"];
+
+/*
+	The voice that a file in the voice directory belongs to, whether it is complete or not.
+*/
+fn name_from_file_name(file_name: &str) -> Option<String> {
+	let file_name = PARTIAL_FILE_SUFFIXES.iter().find_map(|suffix| file_name.strip_suffix(suffix)).unwrap_or(file_name);
+	name_from_key(file_name.strip_suffix(".onnx.json").or_else(|| file_name.strip_suffix(".onnx"))?)
+}
+
+/*
+	The opposite of file_names, for a model file name without its extension like "en_US-ryan-high". Speaker names don't
+	contain dashes, but the language code and quality are split off from the ends anyway to be safe.
+*/
+fn name_from_key(key: &str) -> Option<String> {
+	let (language_code, rest) = key.split_once('-')?;
+	let (speaker, quality) = rest.rsplit_once('-')?;
+	Some(format!("{}/{}/{}", language_code, speaker, quality))
+}
+
+/*
+	Checks that the name is that of a voice of the language without asking the catalogue, so that voices can be managed
+	offline. The language family is the part of the language code before the region, like "en" in "en_US".
+*/
+pub fn check_voice(language_index: usize, name: &str) -> Result<()> {
+	let family = name.split('/').next().and_then(|code| code.split('_').next());
+	if name.split('/').count() != 3 || family != Some(LANGUAGES[language_index].id_2) {
+		return Err(Error::InvalidRequest(format!("{} is not a voice of {}", name, LANGUAGES[language_index].name)));
+	}
+	Ok(())
+}
+
+/*
+	Sort key that puts better voices first: the ones listed in Language::piper_voices in their order, then the others from
+	the highest quality tier to the lowest.
+*/
+pub fn preference(language_index: usize, name: &str) -> (usize, std::cmp::Reverse<Option<usize>>, String) {
+	let listed_position = LANGUAGES[language_index].piper_voices.iter().position(|&voice| voice == name);
+	let quality_tier = QUALITY_TIERS.iter().position(|&tier| tier == quality(name));
+	(listed_position.unwrap_or(usize::MAX), std::cmp::Reverse(quality_tier), name.to_owned())
+}
+
+/*
+	The speakers of an installed voice in the order of their IDs, read from its configuration so that the model doesn't have
+	to be loaded. Most voices have a single speaker, in which case none are listed.
+*/
+pub fn speakers(name: &str) -> Result<Vec<String>> {
+	let path = source_data::voice_directory().join(&file_names(name)[1]);
+	let data = fs::read(&path).map_err(|error| Error::MissingResource(format!("voice file {}: {}", path.display(), error)))?;
+	speakers_from_configuration(&data).map_err(|error| error.context(path.display()))
+}
+
+fn speakers_from_configuration(data: &[u8]) -> Result<Vec<String>> {
+	#[derive(Deserialize)]
+	struct Configuration {
+		#[serde(default)]
+		speaker_id_map: HashMap<String, i64>,
+	}
+
+	let configuration: Configuration = serde_json::from_slice(data)?;
+	let mut speakers: Vec<(String, i64)> = configuration.speaker_id_map.into_iter().collect();
+	speakers.sort_by_key(|&(_, id)| id);
+	Ok(speakers.into_iter().map(|(name, _)| name).collect())
+}
+
+pub fn is_installed(name: &str) -> bool {
+	file_names(name).iter().all(|file_name| source_data::voice_directory().join(file_name).exists())
+}
+
+/*
+	Finds the complete voices of the language in a directory, with the preferred ones first.
+*/
+pub fn voices_in_directory(directory: &Path, language_index: usize) -> Vec<String> {
+	let Ok(entries) = fs::read_dir(directory) else {
+		return Vec::new();
+	};
+
+	let mut voices: Vec<String> = entries
+		.filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+		.filter_map(|file_name| name_from_key(file_name.strip_suffix(".onnx")?))
+		.filter(|name| check_voice(language_index, name).is_ok())
+		.filter(|name| directory.join(&file_names(name)[1]).exists())
+		.collect();
+	voices.sort_by_key(|name| preference(language_index, name));
+	voices
+}
+
+/*
+	Finds the voices of the language that have any files in a directory, including unfinished downloads.
+*/
+pub fn voices_with_files_in_directory(directory: &Path, language_index: usize) -> Vec<String> {
+	let Ok(entries) = fs::read_dir(directory) else {
+		return Vec::new();
+	};
+
+	let mut voices: Vec<String> = entries
+		.filter_map(|entry| name_from_file_name(&entry.ok()?.file_name().into_string().ok()?))
+		.filter(|name| check_voice(language_index, name).is_ok())
+		.collect();
+	voices.sort_unstable();
+	voices.dedup();
+	voices
+}
+
+//----------------------------------------------------------------
+
+/*
+	The voices to enable for a language that was just added: the best one that is installed, or otherwise the default voice
+	from the downloaded voice catalogue. Language::piper_voices is only used when the catalogue has never been downloaded.
+*/
+pub fn initial_voices(language_index: usize) -> Vec<String> {
+	let installed_voices = voices_in_directory(&source_data::voice_directory(), language_index);
+	if let Some(voice) = installed_voices.into_iter().next() {
+		return vec![voice];
+	}
+
+	let catalogue = SourceConfig::load().ok().and_then(|config| VoiceCatalogue::load_downloaded(&config));
+	let default_voice = match catalogue {
+		Some(catalogue) => catalogue.default_voice(language_index).map(|voice| voice.name.clone()),
+		None => LANGUAGES[language_index].piper_voices.first().map(|&name| name.to_owned()),
+	};
+	default_voice.into_iter().collect()
+}
+
+/*
+	Lists the voices of the language in the catalogue with the preferred ones first.
+*/
+pub async fn list_voices(language_index: usize, enabled_voices: &[String]) -> Result<Vec<VoiceInfo>> {
+	let catalogue = VoiceCatalogue::load(&SourceConfig::load()?).await?;
+
+	Ok(catalogue.language_voices(language_index).into_iter()
+		.map(|voice| VoiceInfo {
+			name: voice.name.clone(),
+			speaker: voice.speaker.clone(),
+			quality: voice.quality.clone(),
+			installed: is_installed(&voice.name),
+			enabled: enabled_voices.contains(&voice.name),
+			size_bytes: voice.size_bytes(),
+		})
+		.collect())
+}
+
+/*
+	Downloads the model and configuration of the voice unless it is installed already. The progress callback receives the
+	fraction of the model that has been downloaded.
+*/
+pub async fn download_voice<F>(language_index: usize, name: &str, progress: F) -> Result<()>
+	where F: Fn(f32)
+{
+	let config = SourceConfig::load()?;
+	let catalogue = VoiceCatalogue::load(&config).await?;
+	let voice = catalogue.find(language_index, name)?;
+	let downloader = Downloader::new(reqwest::Client::new());
+
+	let [model_file_name, configuration_file_name] = file_names(name);
+	let url = config.piper_voices_file_url(&voice.model.path);
+	let path = source_data::voice_directory().join(&model_file_name);
+	downloader.download_verified_file(&url, &path, &voice.model.expected(), |received, total| {
+		if let Some(total) = total {
+			progress(received as f32 / total as f32);
+		}
+	}).await?;
+
+	// Configuration files are so small in comparison that they don't need any progress feedback.
+	let url = config.piper_voices_file_url(&voice.configuration.path);
+	let path = source_data::voice_directory().join(&configuration_file_name);
+	downloader.download_verified_file(&url, &path, &voice.configuration.expected(), |_, _| {}).await
+}
+
+/*
+	Deletes the files of the voice unless it is enabled for another language, since some voices are shared, for example by
+	Norwegian Bokmål and Nynorsk. Returns the number of bytes freed.
+*/
+pub fn delete_voice(language_index: usize, name: &str, voices_in_use: &[String]) -> Result<u64> {
+	check_voice(language_index, name)?;
+	if voices_in_use.iter().any(|voice| voice == name) {
+		return Ok(0);
+	}
+
+	let mut freed_bytes = 0;
+	for file_name in file_names(name) {
+		freed_bytes += save_file::remove(source_data::voice_directory().join(file_name))?;
+	}
+	Ok(freed_bytes)
+}
+
+/*
+	Checks the installed voices of the language against the catalogue and downloads the damaged ones again. Without the 
+	catalogue, for example when offline, only the configurations are checked.
+*/
+pub async fn verify_voices(language_index: usize, verification: &mut DataVerification) -> Result<()> {
+	let catalogue = VoiceCatalogue::load(&SourceConfig::load()?).await.ok();
+
+	for name in voices_in_directory(&source_data::voice_directory(), language_index) {
+		let [model_path, configuration_path] = file_names(&name).map(|file_name| source_data::voice_directory().join(file_name));
+
+		let mismatches = match catalogue.as_ref().and_then(|catalogue| catalogue.find(language_index, &name).ok()) {
+			Some(voice) => [
+				integrity::find_mismatch(&model_path, &voice.model.expected())?,
+				integrity::find_mismatch(&configuration_path, &voice.configuration.expected())?,
+			],
+			None => [None, configuration_problem(&configuration_path)],
+		};
+		if mismatches.iter().all(Option::is_none) {
+			verification.add(&model_path, None, false);
+			verification.add(&configuration_path, None, false);
+			continue;
+		}
+
+		// Only the damaged files are downloaded again, the others are kept since they match.
+		let repair_error = download_voice(language_index, &name, |_| {}).await.err();
+		for (path, mismatch) in [model_path, configuration_path].iter().zip(mismatches) {
+			let problem = mismatch.map(|mismatch| match &repair_error {
+				Some(error) => format!("{}, and downloading it again failed: {}", mismatch, error),
+				None => mismatch,
+			});
+			verification.add(path, problem, repair_error.is_none());
+		}
+	}
+	Ok(())
+}
+
+fn configuration_problem(path: &Path) -> Option<String> {
+	let result = fs::read(path).map_err(Error::from)
+		.and_then(|data| Ok(serde_json::from_slice::<serde_json::Value>(&data)?));
+	result.err().map(|error| error.to_string())
+}
+
+//----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn voice_names_map_to_files_and_quality() {
+		assert_eq!(quality("en_GB/semaine/medium"), "medium");
+		assert_eq!(quality("es_ES/carlfm/x_low"), "x_low");
+		assert_eq!(file_names("en_US/ryan/high"), ["en_US-ryan-high.onnx", "en_US-ryan-high.onnx.json"]);
+		assert_eq!(name_from_key("en_GB-northern_english_male-medium").unwrap(), "en_GB/northern_english_male/medium");
+		assert_eq!(name_from_key("voices"), None);
+		assert_eq!(name_from_file_name("en_US-ryan-high.onnx.json").unwrap(), "en_US/ryan/high");
+		assert_eq!(name_from_file_name("en_US-ryan-high.onnx.part").unwrap(), "en_US/ryan/high");
+		assert_eq!(name_from_file_name("en_US-ryan-high.onnx.json.part.json").unwrap(), "en_US/ryan/high");
+		assert_eq!(name_from_file_name("voices-v1.0.0.json"), None);
+	}
+
+	#[test]
+	fn speakers_are_read_from_the_configuration() {
+		let configuration = br#"{ "num_speakers": 3, "speaker_id_map": { "p239": 2, "p236": 0, "p264": 1 } }"#;
+		assert_eq!(speakers_from_configuration(configuration).unwrap(), ["p236", "p264", "p239"]);
+		assert!(speakers_from_configuration(br#"{ "num_speakers": 1, "speaker_id_map": {} }"#).unwrap().is_empty());
+		assert!(speakers_from_configuration(br#"{ "audio": { "sample_rate": 22050 } }"#).unwrap().is_empty());
+		assert!(speakers_from_configuration(b"{").is_err());
+	}
+
+	#[test]
+	fn unfinished_downloads_are_found() {
+		let finnish = source_data::language_index("Finnish").unwrap();
+		let directory = std::env::temp_dir().join(format!("gurksaft_voice_files_test_{}", std::process::id()));
+		fs::create_dir_all(&directory).unwrap();
+		for file_name in [
+			"fi_FI-harri-medium.onnx", "fi_FI-harri-medium.onnx.json",
+			"fi_FI-harri-low.onnx.part", "fi_FI-harri-low.onnx.part.json",
+			"sv_SE-nst-medium.onnx.json.tmp",
+		] {
+			fs::write(directory.join(file_name), b"").unwrap();
+		}
+
+		assert_eq!(voices_in_directory(&directory, finnish), ["fi_FI/harri/medium"]);
+		assert_eq!(voices_with_files_in_directory(&directory, finnish), ["fi_FI/harri/low", "fi_FI/harri/medium"]);
+		fs::remove_dir_all(&directory).unwrap();
+	}
+
+	#[test]
+	fn only_voices_of_the_language_are_accepted() {
+		let finnish = source_data::language_index("Finnish").unwrap();
+		assert!(check_voice(finnish, "fi_FI/harri/medium").is_ok());
+		assert!(matches!(check_voice(finnish, "sv_SE/nst/medium"), Err(Error::InvalidRequest(_))));
+		assert!(matches!(check_voice(finnish, "../../options"), Err(Error::InvalidRequest(_))));
+	}
+
+	#[test]
+	fn listed_voices_are_preferred() {
+		let english = source_data::language_index("English").unwrap();
+		let mut voices = vec!["en_US/amy/low", "en_GB/alba/medium", "en_US/bryce/medium", "en_US/ryan/high"];
+		voices.sort_by_key(|name| preference(english, name));
+		assert_eq!(voices, ["en_US/ryan/high", "en_GB/alba/medium", "en_US/bryce/medium", "en_US/amy/low"]);
+	}
+}
@@ -0,0 +1,412 @@
+use chrono::prelude::*;
+
+use rand::distributions::WeightedIndex;
+use rand::prelude::*;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	learning_data::{LearningWord, SentenceId},
+	options::Options,
+};
+
+//----------------------------------------------------------------
+
+/*
+	The available policies for choosing which word to review next. Chosen in the options.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum WordSelection {
+	// Words are sampled randomly according to their weights, which are multiplied by the weight factors after each review.
+	Weighted,
+	// The most frequent words that have not been learned yet are reviewed first.
+	FrequencyOrder,
+	// The words with the lowest long-term memory are reviewed first.
+	WeakestFirst,
+	// A fraction of the tasks introduce new words while the rest review words that have been seen before.
+	Mixed { new_word_ratio: f32 },
+	// Overdue words are reviewed according to their spaced repetition schedules.
+	SpacedRepetition,
+}
+
+pub struct SentenceCandidate {
+	pub id: SentenceId,
+	// How hard the sentence is to understand apart from the word being reviewed, see SentenceIndex::sentence_difficulty.
+	pub difficulty: f32,
+}
+
+/*
+	Chooses randomly among the sentences, making sentences less likely the more difficult they are. The bias determines how 
+	strong the preference for easier sentences is, and with zero bias all sentences are equally likely.
+*/
+pub fn choose_sentence_by_difficulty(candidates: &[SentenceCandidate], bias: f32, rng: &mut dyn RngCore) -> Option<SentenceId> {
+	let min_difficulty = candidates.iter().map(|candidate| candidate.difficulty).min_by(f32::total_cmp)?;
+
+	// Relative to the easiest sentence, so that the easiest one has weight 1 and the weights can't all underflow to zero.
+	let weights = candidates.iter().map(|candidate| f32::exp(-bias * (candidate.difficulty - min_difficulty)));
+
+	let index = match WeightedIndex::new(weights) {
+		Ok(index) => index.sample(rng),
+		Err(_) => rng.gen_range(0..candidates.len()),
+	};
+	Some(candidates[index].id)
+}
+
+/*
+	Chooses the word to review in the next task, and the sentence to review it in. The word list passed to the methods is
+	never empty and the indices of the words are their ids.
+*/
+pub trait WordSelector: Send {
+	fn choose_word(&mut self, words: &[LearningWord], options: &Options, rng: &mut dyn RngCore) -> usize;
+
+	/*
+		Chooses among the sentences that contain the word. By default easier sentences are preferred according to the 
+		difficulty bias from the options, which weakens as more words are learned.
+	*/
+	fn choose_sentence(&mut self, _word_id: usize, candidates: &[SentenceCandidate], difficulty_bias: f32, rng: &mut dyn RngCore) 
+		-> Option<SentenceId> 
+	{
+		choose_sentence_by_difficulty(candidates, difficulty_bias, rng)
+	}
+
+	// Called after the words with the given ids have been reviewed and updated.
+	fn words_updated(&mut self, _words: &[LearningWord], _word_ids: &[usize]) {}
+
+	// Called after a word has been removed from the word list, which shifts the ids of all following words.
+	fn word_removed(&mut self, _words: &[LearningWord], _word_id: usize) {}
+}
+
+pub fn create_word_selector(selection: WordSelection, words: &[LearningWord]) -> Box<dyn WordSelector> {
+	match selection {
+		WordSelection::Weighted => Box::new(WeightedSelector::new(words)),
+		WordSelection::FrequencyOrder => Box::new(FrequencyOrderSelector),
+		WordSelection::WeakestFirst => Box::new(WeakestFirstSelector),
+		WordSelection::Mixed { new_word_ratio } => Box::new(MixedSelector { new_word_ratio }),
+		WordSelection::SpacedRepetition => Box::new(SpacedRepetitionSelector),
+	}
+}
+
+//----------------------------------------------------------------
+
+/*
+	Samples a word id from the given weights, or returns None if all weights are zero.
+*/
+fn sample_weighted(weights: impl Iterator<Item = f64>, rng: &mut dyn RngCore) -> Option<usize> {
+	WeightedIndex::new(weights).ok().map(|index| index.sample(rng))
+}
+
+// Words that have never been reviewed can be assumed to be the more frequent the higher their initial weight is.
+fn frequency_weight(word: &LearningWord) -> f64 {
+	word.weight
+}
+
+//----------------------------------------------------------------
+
+/*
+	Keeps a weighted index of all words which is updated incrementally after each review. There is no index when the word
+	list is empty or all weights are zero, and then the words are equally likely.
+*/
+pub struct WeightedSelector {
+	index: Option<WeightedIndex<f64>>,
+}
+
+impl WeightedSelector {
+	pub fn new(words: &[LearningWord]) -> Self {
+		WeightedSelector {
+			index: WeightedIndex::new(words.iter().map(|word| word.weight)).ok()
+		}
+	}
+}
+
+impl WordSelector for WeightedSelector {
+	fn choose_word(&mut self, words: &[LearningWord], _options: &Options, rng: &mut dyn RngCore) -> usize {
+		match &self.index {
+			Some(index) => index.sample(rng),
+			None => rng.gen_range(0..words.len()),
+		}
+	}
+
+	fn words_updated(&mut self, words: &[LearningWord], word_ids: &[usize]) {
+		let Some(index) = &mut self.index else {
+			*self = Self::new(words);
+			return;
+		};
+
+		let mut updated_weights: Vec<_> = word_ids.iter().map(|&i| (i, &words[i].weight)).collect();
+
+		// The elements passed to updated_weights must be sorted by index (first element in the tuple), and I assume not contain duplicates.
+		updated_weights.sort_by_key(|(i, _weight)| *i);
+		updated_weights.dedup_by_key(|(i, _weight)| *i);
+
+		if index.update_weights(&updated_weights).is_err() {
+			// All weights are zero.
+			self.index = None;
+		}
+	}
+
+	fn word_removed(&mut self, words: &[LearningWord], _word_id: usize) {
+		*self = Self::new(words);
+	}
+}
+
+//----------------------------------------------------------------
+
+/*
+	Reviewing only the single most frequent word that has not been learned would show the same word over and over again,
+	so we choose randomly among this many of the most frequent words that haven't been learned.
+*/
+const FREQUENCY_ORDER_WINDOW: usize = 5;
+
+pub struct FrequencyOrderSelector;
+
+impl WordSelector for FrequencyOrderSelector {
+	fn choose_word(&mut self, words: &[LearningWord], options: &Options, rng: &mut dyn RngCore) -> usize {
+		let learned_threshold = options.word_memory_parameters.learned_threshold;
+
+		// The word list is sorted by frequency.
+		let candidates: Vec<usize> = words.iter()
+			.enumerate()
+			.filter(|(_i, word)| word.long_term_memory < learned_threshold)
+			.map(|(i, _word)| i)
+			.take(FREQUENCY_ORDER_WINDOW)
+			.collect();
+
+		candidates.choose(rng).copied().unwrap_or_else(|| rng.gen_range(0..words.len()))
+	}
+}
+
+//----------------------------------------------------------------
+
+// Same reasoning as for FREQUENCY_ORDER_WINDOW.
+const WEAKEST_FIRST_WINDOW: usize = 5;
+
+pub struct WeakestFirstSelector;
+
+impl WordSelector for WeakestFirstSelector {
+	fn choose_word(&mut self, words: &[LearningWord], _options: &Options, rng: &mut dyn RngCore) -> usize {
+		let mut ids: Vec<usize> = (0..words.len()).collect();
+		// The sort is stable, so more frequent words come first among words with the same memory.
+		ids.sort_by(|&a, &b| words[a].long_term_memory.total_cmp(&words[b].long_term_memory));
+		*ids[..usize::min(WEAKEST_FIRST_WINDOW, ids.len())].choose(rng).unwrap()
+	}
+}
+
+//----------------------------------------------------------------
+
+/*
+	New words are sampled by frequency and words that have been reviewed before are sampled by how badly they are
+	remembered. If there are no words of the chosen kind, a word of the other kind is chosen.
+*/
+pub struct MixedSelector {
+	new_word_ratio: f32,
+}
+
+impl WordSelector for MixedSelector {
+	fn choose_word(&mut self, words: &[LearningWord], _options: &Options, rng: &mut dyn RngCore) -> usize {
+		let new_word_weights = || words.iter()
+			.map(|word| if word.has_been_reviewed() { 0. } else { frequency_weight(word) });
+		let review_word_weights = || words.iter()
+			.map(|word| if word.has_been_reviewed() { 1. - word.long_term_memory as f64 } else { 0. });
+
+		let new_word = rng.gen_bool(self.new_word_ratio.clamp(0., 1.) as f64);
+
+		let chosen = if new_word {
+			sample_weighted(new_word_weights(), rng).or_else(|| sample_weighted(review_word_weights(), rng))
+		}
+		else {
+			sample_weighted(review_word_weights(), rng).or_else(|| sample_weighted(new_word_weights(), rng))
+		};
+		chosen.unwrap_or_else(|| rng.gen_range(0..words.len()))
+	}
+}
+
+//----------------------------------------------------------------
+
+/*
+	The word that has been overdue for the longest time is reviewed first. When no words are due, a new word is introduced,
+	more frequent words being more likely. If all words have been introduced, the word that is due the soonest is reviewed.
+*/
+pub struct SpacedRepetitionSelector;
+
+impl WordSelector for SpacedRepetitionSelector {
+	fn choose_word(&mut self, words: &[LearningWord], _options: &Options, rng: &mut dyn RngCore) -> usize {
+		let now = Utc::now();
+
+		let scheduled_words = || words.iter()
+			.enumerate()
+			.filter_map(|(i, word)| Some((i, word.schedule.as_ref()?)));
+
+		if let Some((i, _schedule)) = scheduled_words()
+			.filter(|(_i, schedule)| schedule.is_due(now))
+			.min_by_key(|(_i, schedule)| schedule.due)
+		{
+			return i;
+		}
+
+		let new_word_weights = words.iter().map(|word| if word.schedule.is_none() { frequency_weight(word) } else { 0. });
+		if let Some(i) = sample_weighted(new_word_weights, rng) {
+			return i;
+		}
+
+		scheduled_words()
+			.min_by_key(|(_i, schedule)| schedule.due)
+			.map_or_else(|| rng.gen_range(0..words.len()), |(i, _schedule)| i)
+	}
+}
+
+//----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::{
+		learning_data::WordReviewResult,
+		scheduler::{ReviewSchedule, SchedulingAlgorithm},
+	};
+
+	fn word(word: &str, weight: f64, long_term_memory: f32) -> LearningWord {
+		LearningWord {
+			word: word.to_owned(),
+			forms: Vec::new(),
+			weight,
+			long_term_memory,
+			last_review: Utc.timestamp_nanos(0),
+			schedule: None,
+		}
+	}
+
+	fn reviewed_word(word_text: &str, weight: f64, long_term_memory: f32, due_in_days: i64) -> LearningWord {
+		let now = Utc::now();
+		let mut schedule = ReviewSchedule::first_review(SchedulingAlgorithm::Sm2, WordReviewResult::Succeeded, now);
+		schedule.due = now + chrono::Duration::days(due_in_days);
+		LearningWord {
+			last_review: now,
+			schedule: Some(schedule),
+			..word(word_text, weight, long_term_memory)
+		}
+	}
+
+	fn choose_many(selector: &mut dyn WordSelector, words: &[LearningWord], seed: u64) -> Vec<usize> {
+		let options = Options::new(0);
+		let mut rng = StdRng::seed_from_u64(seed);
+		(0..100).map(|_| selector.choose_word(words, &options, &mut rng)).collect()
+	}
+
+	#[test]
+	fn selectors_are_deterministic_with_seeded_rng() {
+		let words: Vec<_> = (0..20).map(|i| word(&i.to_string(), 1. / (i + 1) as f64, (i % 7) as f32 / 7.)).collect();
+
+		for selection in [
+			WordSelection::Weighted,
+			WordSelection::FrequencyOrder,
+			WordSelection::WeakestFirst,
+			WordSelection::Mixed { new_word_ratio: 0.5 },
+			WordSelection::SpacedRepetition,
+		] {
+			let first = choose_many(create_word_selector(selection, &words).as_mut(), &words, 7);
+			let second = choose_many(create_word_selector(selection, &words).as_mut(), &words, 7);
+			assert_eq!(first, second, "{:?}", selection);
+			assert!(first.iter().all(|&i| i < words.len()));
+		}
+	}
+
+	#[test]
+	fn weighted_never_chooses_zero_weight() {
+		let mut words = vec![word("a", 1., 0.3), word("b", 0., 0.3), word("c", 2., 0.3)];
+		let mut selector = WeightedSelector::new(&words);
+		assert!(!choose_many(&mut selector, &words, 1).contains(&1));
+
+		// Updated weights are taken into account.
+		words[0].weight = 0.;
+		words[1].weight = 1.;
+		selector.words_updated(&words, &[1, 0, 1]);
+		assert!(!choose_many(&mut selector, &words, 2).contains(&0));
+
+		words.remove(1);
+		selector.word_removed(&words, 1);
+		assert!(choose_many(&mut selector, &words, 3).iter().all(|&i| i == 1));
+	}
+
+	#[test]
+	fn frequency_order_skips_learned_words() {
+		let learned = Options::new(0).word_memory_parameters.learned_threshold;
+		let mut words: Vec<_> = (0..10).map(|i| word(&i.to_string(), 1., 0.3)).collect();
+		words[0].long_term_memory = learned;
+		words[2].long_term_memory = learned + 0.05;
+
+		let chosen = choose_many(&mut FrequencyOrderSelector, &words, 3);
+		assert!(chosen.iter().all(|i| [1, 3, 4, 5, 6].contains(i)));
+	}
+
+	#[test]
+	fn weakest_first_chooses_weak_words() {
+		let mut words: Vec<_> = (0..10).map(|i| word(&i.to_string(), 1., 0.8)).collect();
+		words[9].long_term_memory = 0.1;
+		words[3].long_term_memory = 0.2;
+
+		let chosen = choose_many(&mut WeakestFirstSelector, &words, 4);
+		assert!(chosen.iter().all(|i| [9, 3, 0, 1, 2].contains(i)));
+		assert!(chosen.contains(&9) && chosen.contains(&3));
+	}
+
+	#[test]
+	fn mixed_respects_new_word_ratio() {
+		let words = vec![
+			reviewed_word("a", 1., 0.5, 1),
+			word("b", 1., 0.3),
+			reviewed_word("c", 1., 0.2, 1),
+			word("d", 1., 0.3),
+		];
+
+		let only_new = choose_many(&mut MixedSelector { new_word_ratio: 1. }, &words, 5);
+		assert!(only_new.iter().all(|&i| i == 1 || i == 3));
+
+		let only_review = choose_many(&mut MixedSelector { new_word_ratio: 0. }, &words, 5);
+		assert!(only_review.iter().all(|&i| i == 0 || i == 2));
+
+		// Falls back to the other kind when there are no words of the chosen kind.
+		let all_new = vec![word("a", 1., 0.3), word("b", 1., 0.3)];
+		assert!(choose_many(&mut MixedSelector { new_word_ratio: 0. }, &all_new, 6).iter().all(|&i| i < 2));
+	}
+
+	#[test]
+	fn spaced_repetition_prefers_overdue_words() {
+		let words = vec![
+			reviewed_word("a", 1., 0.5, 3),
+			reviewed_word("b", 1., 0.5, -1),
+			word("c", 1., 0.3),
+			reviewed_word("d", 1., 0.5, -5),
+		];
+		assert!(choose_many(&mut SpacedRepetitionSelector, &words, 8).iter().all(|&i| i == 3));
+
+		// Without overdue words, new words are introduced.
+		let words = vec![reviewed_word("a", 1., 0.5, 3), word("b", 1., 0.3), word("c", 0., 0.3)];
+		assert!(choose_many(&mut SpacedRepetitionSelector, &words, 9).iter().all(|&i| i == 1));
+
+		// Without new words, the word that is due the soonest is reviewed.
+		let words = vec![reviewed_word("a", 1., 0.5, 3), reviewed_word("b", 1., 0.5, 2)];
+		assert!(choose_many(&mut SpacedRepetitionSelector, &words, 10).iter().all(|&i| i == 1));
+	}
+	#[test]
+	fn sentence_difficulty_bias() {
+		let candidates = [
+			SentenceCandidate { id: 1, difficulty: 0.5 },
+			SentenceCandidate { id: 2, difficulty: 8. },
+			SentenceCandidate { id: 3, difficulty: 4. },
+		];
+		let count_easiest = |bias: f32| {
+			let mut rng = StdRng::seed_from_u64(11);
+			(0..300).filter(|_| choose_sentence_by_difficulty(&candidates, bias, &mut rng) == Some(1)).count()
+		};
+
+		// Without bias, all sentences are about equally likely.
+		assert!((70..130).contains(&count_easiest(0.)));
+		// With a strong bias, the easiest sentence is almost always chosen.
+		assert!(count_easiest(5.) > 290);
+		assert!(count_easiest(1.) > count_easiest(0.2));
+
+		let mut rng = StdRng::seed_from_u64(12);
+		assert_eq!(choose_sentence_by_difficulty(&[], 1., &mut rng), None);
+	}
+}
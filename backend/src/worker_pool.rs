@@ -0,0 +1,127 @@
+use std::{
+	panic::{self, AssertUnwindSafe},
+	sync::{mpsc, Arc, Mutex},
+	thread,
+};
+
+//----------------------------------------------------------------
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/*
+	A fixed number of threads for blocking work like speech synthesis, so that it neither blocks the async runtime nor takes
+	up every core. Jobs run in the order they were added. A job that panics only ends that job, whoever waits for its result
+	sees it drop, and the thread goes on with the next job. The threads finish the jobs that are left and exit when the pool
+	is dropped.
+*/
+pub struct WorkerPool {
+	sender: mpsc::Sender<Job>,
+	thread_count: usize,
+}
+
+impl WorkerPool {
+	pub fn new(name: &str, thread_count: usize) -> Self {
+		let thread_count = thread_count.max(1);
+		let (sender, receiver) = mpsc::channel::<Job>();
+		let receiver = Arc::new(Mutex::new(receiver));
+
+		for i in 0..thread_count {
+			let receiver = receiver.clone();
+			thread::Builder::new()
+				.name(format!("{} {}", name, i))
+				.spawn(move || loop {
+					// The lock is only held while waiting for a job, not while running it.
+					let job = receiver.lock().unwrap().recv();
+					match job {
+						Ok(job) => {
+							// The panic message has already been printed by the panic hook.
+							if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+								eprintln!("A job on {} panicked", thread::current().name().unwrap_or("a worker thread"));
+							}
+						}
+						Err(mpsc::RecvError) => return,
+					}
+				})
+				.expect("could not start a worker thread");
+		}
+		Self { sender, thread_count }
+	}
+
+	pub fn thread_count(&self) -> usize {
+		self.thread_count
+	}
+
+	/*
+		Runs the job on one of the threads once the jobs added before it have started. The job sends its result back itself,
+		for example through a channel, so that results can be used as soon as they are ready.
+	*/
+	pub fn spawn<F>(&self, job: F)
+		where F: FnOnce() + Send + 'static
+	{
+		// The threads only exit once the sender is dropped together with the pool, so this can't fail.
+		self.sender.send(Box::new(job)).expect("the worker threads have exited");
+	}
+}
+
+/*
+	Half of the cores, leaving the rest for the app and the system.
+*/
+pub fn default_thread_count() -> usize {
+	thread::available_parallelism().map_or(1, |count| count.get() / 2).max(1)
+}
+
+//----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		sync::atomic::{AtomicUsize, Ordering},
+		time::Duration,
+	};
+
+	use super::*;
+
+	#[test]
+	fn jobs_run_in_parallel_up_to_the_thread_count() {
+		let pool = WorkerPool::new("test worker", 2);
+		let running = Arc::new(AtomicUsize::new(0));
+		let most_running = Arc::new(AtomicUsize::new(0));
+		let (result_sender, result_receiver) = mpsc::channel();
+
+		for i in 0..6 {
+			let running = running.clone();
+			let most_running = most_running.clone();
+			let result_sender = result_sender.clone();
+			pool.spawn(move || {
+				let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+				most_running.fetch_max(now_running, Ordering::SeqCst);
+				thread::sleep(Duration::from_millis(20));
+				running.fetch_sub(1, Ordering::SeqCst);
+				result_sender.send(i * 10).unwrap();
+			});
+		}
+		drop(result_sender);
+
+		let mut results: Vec<i32> = result_receiver.iter().collect();
+		results.sort_unstable();
+		assert_eq!(results, [0, 10, 20, 30, 40, 50]);
+		assert!((1..=2).contains(&most_running.load(Ordering::SeqCst)));
+	}
+
+	#[test]
+	fn panicking_jobs_do_not_stop_the_threads() {
+		let pool = WorkerPool::new("test worker", 1);
+		let (result_sender, result_receiver) = mpsc::channel();
+
+		for i in 0..3 {
+			let result_sender = result_sender.clone();
+			pool.spawn(move || {
+				if i < 2 {
+					panic!("job {} failed", i);
+				}
+				result_sender.send(i).unwrap();
+			});
+		}
+		assert_eq!(result_receiver.recv_timeout(Duration::from_secs(10)), Ok(2));
+	}
+}
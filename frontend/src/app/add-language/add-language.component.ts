@@ -34,6 +34,11 @@ export class AddLanguageComponent implements AfterViewInit {
 	
 	targetLanguage: string = '';
 	translationLanguages: string[] = [];
+
+	// Paths of local files to import instead of downloading, with a sentence list for each translation language.
+	wordListPath: string = '';
+	sentenceListPaths: Record<string, string> = {};
+	voiceDirectory: string = '';
 	
 	constructor(private router: Router) {
 		appWindow.setTitle('Gurskaft - add language');
@@ -70,12 +75,27 @@ export class AddLanguageComponent implements AfterViewInit {
 		}
 	}
 	download(): void {
-		invoke('download_language_data', {
-			info: {
-				target_language: this.targetLanguage,
-				translation_languages: this.translationLanguages
-			}
-		});
-		this.router.navigate(['download-language-data']);
+		const info = {
+			target_language: this.targetLanguage,
+			translation_languages: this.translationLanguages
+		};
+		// The download page starts the download so that it can offer to retry it if it fails.
+		this.router.navigate(['download-language-data'], { state: { info } });
+	}
+	importFiles(): void {
+		const importInfo = {
+			target_language: this.targetLanguage,
+			word_list_path: this.wordListPath.trim(),
+			sentence_lists: this.translationLanguages.map(language => ({ 
+				translation_language: language, 
+				path: (this.sentenceListPaths[language] ?? '').trim() 
+			})),
+			voice_directory: this.voiceDirectory.trim() || null
+		};
+		this.router.navigate(['download-language-data'], { state: { importInfo } });
+	}
+	canImport(): boolean {
+		return this.targetLanguage != '' && this.translationLanguages.length > 0 && this.wordListPath.trim() != ''
+			&& this.translationLanguages.every(language => (this.sentenceListPaths[language] ?? '').trim() != '');
 	}
 }
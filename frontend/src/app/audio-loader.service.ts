@@ -1,9 +1,11 @@
 import { ChangeDetectorRef, Injectable } from '@angular/core';
 
 import { invoke } from '@tauri-apps/api';
-import { emit, listen } from '@tauri-apps/api/event';
+import { appWindow } from '@tauri-apps/api/window';
 import { Subject } from 'rxjs';
 
+import { BackendError } from './common';
+
 export class SentenceAudio {
 	private buffer!: AudioBuffer;
 
@@ -19,6 +21,11 @@ export class SentenceAudio {
 	}
 }
 
+interface SentenceAudioData {
+	request_id: number;
+	file_data: number[];
+}
+
 @Injectable({
 	providedIn: 'root'
 })
@@ -26,40 +33,46 @@ export class AudioLoaderService {
 	private context = new AudioContext();
 	clips: SentenceAudio[] = [];
 	index = -1;
-	// sentenceId = 0;
-	private load_promise!: Promise<void>;
-	private is_loading_done = true;
+	// Every sentence is loaded with a new request ID, and clips of earlier requests are ignored.
+	private requestId = 0;
+	private isLoading = false;
 
 	private newAudioData = new Subject<void>();
 	newAudioData$ = this.newAudioData.asObservable();
 
 	constructor() {
-		listen<number[]>('sentence_audio_data', data => {
-			console.log('Got new audio clip!');
-			this.clips.push(new SentenceAudio(this.context, Uint8Array.from(data.payload)));
+		appWindow.listen<SentenceAudioData>('sentence_audio_data', event => {
+			if (event.payload.request_id != this.requestId) {
+				return;
+			}
+			this.clips.push(new SentenceAudio(this.context, Uint8Array.from(event.payload.file_data)));
 			this.newAudioData.next();
 		});
 	}
 
 	newSentence(sentence: string, id: number): void {
-		const startLoading = () => {
-			this.clips = [];
-			this.index = -1;
-			this.is_loading_done = false;
-			this.load_promise = invoke('load_sentence_audio', { sentenceId: id, sentence }).then(_ => { this.is_loading_done = true; });
-		};
-		
-		if (this.is_loading_done) {
-			startLoading()
-		}
-		else {
-			emit('cancel_sentence_audio');
-			this.load_promise.finally(startLoading);
-		}
+		this.stopLoading();
+
+		const requestId = ++this.requestId;
+		this.clips = [];
+		this.index = -1;
+		this.isLoading = true;
+		invoke('load_sentence_audio', { requestId, sentenceId: id, sentence })
+			.catch((error: BackendError) => {
+				if (error.kind != 'Cancelled') {
+					console.error(error);
+				}
+			})
+			.finally(() => {
+				if (requestId == this.requestId) {
+					this.isLoading = false;
+				}
+			});
 	}
 	stopLoading(): void {
-		if (!this.is_loading_done) {
-			emit('cancel_sentence_audio');
+		if (this.isLoading) {
+			invoke('cancel_sentence_audio', { requestId: this.requestId });
+			this.isLoading = false;
 		}
 	}
 	play(): void {
@@ -5,6 +5,26 @@
 
 import { invoke } from "@tauri-apps/api";
 
+/*
+	The error that commands are rejected with, see error.rs in the back-end.
+*/
+export interface BackendError {
+	kind: 'Network' | 'Parse' | 'Io' | 'MissingResource' | 'Synthesis' | 'InvalidRequest' | 'Cancelled';
+	message?: string;
+}
+
+export function errorMessage(error: BackendError): string {
+	switch (error.kind) {
+		case 'Network': return `Could not connect to the internet. ${error.message}`;
+		case 'Parse': return `Some data has the wrong format. ${error.message}`;
+		case 'Io': return `Could not read or write a file. ${error.message}`;
+		case 'MissingResource': return `Something is missing: ${error.message}`;
+		case 'Synthesis': return `Speech could not be generated. ${error.message}`;
+		case 'InvalidRequest': return `That can't be done: ${error.message}`;
+		case 'Cancelled': return 'Cancelled.';
+	}
+}
+
 export function reciprocalEaseOutTransferFunction(t: number, sharpness: number = 0.8) {
 	return t/(t - Math.pow(1 - sharpness, 2)*(t - 1))
 }
@@ -2,13 +2,17 @@ import { ChangeDetectorRef, Component, NgZone } from '@angular/core';
 import { CommonModule } from '@angular/common';
 import { Router } from '@angular/router';
 
+import { invoke } from '@tauri-apps/api';
 import { listen, UnlistenFn } from '@tauri-apps/api/event';
 import { appWindow } from '@tauri-apps/api/window';
 
+import { BackendError, errorMessage } from '../common';
+import { RippleDirective } from '../ripple.directive';
+
 @Component({
 	selector: 'app-download-language-data',
 	standalone: true,
-	imports: [CommonModule],
+	imports: [CommonModule, RippleDirective],
 	templateUrl: './download-language-data.component.html',
 	styleUrls: ['./download-language-data.component.scss']
 })
@@ -16,9 +20,20 @@ export class DownloadLanguageDataComponent {
 	statusMessage: string = 'Loading...';
 	progress: number = 0;
 	unlisten?: UnlistenFn;
+	failed: boolean = false;
+	// What to do, passed from the add language page or the options page.
+	private info?: any;
+	private importInfo?: any;
+	private translationLanguages?: string[];
 	
 	constructor(private router: Router, private changeDetector: ChangeDetectorRef, private zone: NgZone) { 
 		appWindow.setTitle('Gurksaft - downloading data');
+
+		const state = router.getCurrentNavigation()?.extras.state;
+		this.info = state?.['info'];
+		this.importInfo = state?.['importInfo'];
+		this.translationLanguages = state?.['translationLanguages'];
+		this.download();
 		
 		const progress_to_string = (progress: number) => 
 			progress <= 1 ? `${Math.round(progress*100)}%` : `${progress} bytes`;
@@ -60,4 +75,28 @@ export class DownloadLanguageDataComponent {
 			changeDetector.detectChanges();
 		}).then(unlisten => this.unlisten = unlisten);
 	}
+
+	download(): void {
+		this.failed = false;
+		this.progress = 0;
+		this.statusMessage = 'Loading...';
+
+		const result = 
+			this.importInfo ? invoke('import_language_data', { info: this.importInfo }) :
+			this.translationLanguages ? invoke('set_translation_languages', { translationLanguages: this.translationLanguages }) :
+			invoke('download_language_data', { info: this.info });
+
+		result.catch((error: BackendError) => {
+			this.failed = true;
+			this.progress = 0;
+			this.statusMessage = `${this.importInfo ? 'Importing' : 'Downloading'} failed.\n${errorMessage(error)}`;
+			this.changeDetector.detectChanges();
+		});
+	}
+	cancel(): void {
+		if (this.unlisten) {
+			this.unlisten();
+		}
+		this.router.navigate([this.translationLanguages ? 'options' : 'add-language']);
+	}
 }
@@ -6,6 +6,7 @@ import { invoke } from '@tauri-apps/api';
 import { appWindow } from '@tauri-apps/api/window';
 
 import { AudioLoaderService } from '../audio-loader.service';
+import { BackendError, errorMessage } from '../common';
 import { RippleDirective } from '../ripple.directive';
 import { FormsModule } from '@angular/forms';
 
@@ -95,6 +96,7 @@ export class LearnComponent implements AfterViewInit {
 	buttonText = '';
 
 	translations: string[] = [];
+	taskStatus = '';
 
 	@ViewChildren('wordInput')
 	inputElements!: QueryList<ElementRef<HTMLInputElement>>;
@@ -161,7 +163,11 @@ export class LearnComponent implements AfterViewInit {
 			this.translations = task.translations;
 			
 			this.audioLoader.newSentence(task.sentence, task.sentence_id);
+			this.taskStatus = '';
 			
+			this.changeDetector.detectChanges();
+		}).catch((error: BackendError) => {
+			this.taskStatus = `Could not choose a phrase. ${errorMessage(error)}`;
 			this.changeDetector.detectChanges();
 		});
 	}
@@ -0,0 +1,62 @@
+import { AfterViewInit, ChangeDetectorRef, Component } from '@angular/core';
+import { CommonModule } from '@angular/common';
+import { Router } from '@angular/router';
+
+import { invoke } from '@tauri-apps/api';
+import { appWindow } from '@tauri-apps/api/window';
+
+import { BackendError, errorMessage } from '../common';
+import { RippleDirective } from '../ripple.directive';
+
+//----------------------------------------------------------------
+
+type BackupInfo = {
+	name: string,
+	time: string,
+	languages: string[],
+};
+
+/*
+	Shown at startup when the save data could not be loaded. Loading can be retried, or a backup can be restored first.
+*/
+@Component({
+	selector: 'app-load-error',
+	standalone: true,
+	imports: [CommonModule, RippleDirective],
+	templateUrl: './load-error.component.html',
+	styleUrls: ['./load-error.component.scss']
+})
+export class LoadErrorComponent implements AfterViewInit {
+	message: string = '';
+	backups: BackupInfo[] = [];
+
+	constructor(private router: Router, private changeDetector: ChangeDetectorRef) {
+		appWindow.setTitle('Gurksaft - could not load save data');
+	}
+
+	ngAfterViewInit(): void {
+		invoke<BackendError | null>('get_startup_error').then(error => {
+			if (error) {
+				this.showError(error);
+			}
+		});
+		invoke<BackupInfo[]>('list_backups').then(backups => {
+			this.backups = backups;
+			this.changeDetector.detectChanges();
+		});
+	}
+
+	showError(error: BackendError): void {
+		this.message = errorMessage(error);
+		this.changeDetector.detectChanges();
+	}
+	retry(): void {
+		invoke('retry_loading').then(() => this.router.navigate(['learn']), error => this.showError(error));
+	}
+	restore(backup: BackupInfo): void {
+		invoke('restore_backup', { name: backup.name }).then(() => this.retry(), error => this.showError(error));
+	}
+	formatTime(time: string): string {
+		return new Date(time).toLocaleString();
+	}
+}
@@ -1,11 +1,13 @@
-import { AfterViewInit, ChangeDetectorRef, Component, ViewChild } from '@angular/core';
+import { AfterViewInit, ChangeDetectorRef, Component, OnDestroy, ViewChild } from '@angular/core';
 import { CommonModule } from '@angular/common';
 import { FormsModule } from '@angular/forms';
-import { RouterModule } from '@angular/router';
+import { Router, RouterModule } from '@angular/router';
 
 import { invoke } from '@tauri-apps/api';
+import { listen, UnlistenFn } from '@tauri-apps/api/event';
 import { appWindow } from '@tauri-apps/api/window';
 
+import { BackendError, errorMessage } from '../common';
 import { DropdownComponent } from '../dropdown/dropdown.component';
 import { DropdownOptionComponent } from '../dropdown-option/dropdown-option.component';
 import { NumberInputDirective } from '../number-input.directive';
@@ -34,8 +36,52 @@ class WeightFactors {
 class Options {
 	current_language = "";
 	saved_languages: string[] = [];
+	translation_languages: string[] = [];
 	weight_factors = new WeightFactors();
 	word_memory_parameters = new WordMemoryParameters();
+	audio_cache_size_limit_bytes = 0;
+	audio_cache_size_bytes = 0;
+	synthesis_thread_count = 1;
+	voice_model_estimated_memory_budget_bytes = 0;
+}
+
+interface RemovedLanguage {
+	freed_bytes: number;
+	current_language: string;
+}
+
+interface Voice {
+	name: string;
+	speaker: string;
+	quality: string;
+	installed: boolean;
+	enabled: boolean;
+	size_bytes: number;
+}
+
+interface VoiceModel {
+	voice: string;
+	loaded: boolean;
+	load_time_ms?: number;
+	estimated_memory_bytes?: number;
+}
+
+interface VoiceDownloadProgress {
+	voice: string;
+	progress: number;
+}
+
+interface DataVerification {
+	checked_file_count: number;
+	problems: { path: string, problem: string, repaired: boolean }[];
+}
+
+interface SentenceChanges {
+	added_sentences: number;
+	removed_sentences: number;
+	updated_sentences: number;
+	added_translations: number;
+	removed_translations: number;
 }
 
 @Component({
@@ -46,30 +92,229 @@ class Options {
 	templateUrl: './options.component.html',
 	styleUrls: ['./options.component.scss']
 })
-export class OptionsComponent implements AfterViewInit {
+export class OptionsComponent implements AfterViewInit, OnDestroy {
 	@ViewChild('languageDropdown') 
 	private languageDropdown!: DropdownComponent;
 	options = new Options();
+	// Edited here and only applied when asked to since adding languages downloads sentences.
+	translationLanguages: string[] = [];
+	private languageList: string[] = [];
+	languageRemovalStatus = '';
+	refreshingSentences = false;
+	sentenceRefreshStatus = '';
+	voices: Voice[] = [];
+	// The voice being downloaded and how much of it has arrived, from 0 to 1.
+	downloadingVoice?: string;
+	voiceDownloadProgress = 0;
+	voiceStatus = '';
+	private unlistenVoiceDownload?: UnlistenFn;
+	verifyingData = false;
+	dataVerificationStatus = '';
+	dataProblems: string[] = [];
+	// In megabytes.
+	audioCacheSizeLimit = 0;
+	audioCacheStatus = '';
+	voiceModels: VoiceModel[] = [];
+	// In megabytes.
+	voiceModelMemoryBudget = 0;
+	voiceModelStatus = '';
 
-	constructor(private changeDetector: ChangeDetectorRef) {
+	constructor(private changeDetector: ChangeDetectorRef, private router: Router) {
 		appWindow.setTitle('Gurksaft - options');
+		invoke<string[]>('get_language_list').then(languages => this.languageList = languages);
+		listen<VoiceDownloadProgress>('voice_download_progress', event => {
+			this.voiceDownloadProgress = event.payload.progress;
+			this.changeDetector.detectChanges();
+		}).then(unlisten => this.unlistenVoiceDownload = unlisten);
 	}
 	
 	ngAfterViewInit(): void {
-		// console
+		this.loadOptions();
+	}
+	ngOnDestroy(): void {
+		if (this.unlistenVoiceDownload) {
+			this.unlistenVoiceDownload();
+		}
+	}
+
+	private loadOptions(): void {
 		invoke<Options>("get_options").then(options => {
 			// console.log("Got em");
 			this.options = options;
+			this.translationLanguages = [...options.translation_languages];
+			this.audioCacheSizeLimit = options.audio_cache_size_limit_bytes/1e6;
+			this.voiceModelMemoryBudget = options.voice_model_estimated_memory_budget_bytes/1e6;
 			this.changeDetector.detectChanges();
 			this.languageDropdown.select(options.current_language);
 			this.changeDetector.detectChanges();
 		});
+		this.loadVoices();
+		this.loadVoiceModels();
+	}
+
+	private loadVoices(): void {
+		invoke<Voice[]>('get_voices').then(voices => {
+			this.voices = voices;
+			this.changeDetector.detectChanges();
+		});
+	}
+
+	voiceSize(voice: Voice): string {
+		return `${(voice.size_bytes/1e6).toFixed(0)} MB`;
+	}
+	setVoiceEnabled(voice: Voice, enabled: boolean): void {
+		invoke('set_voice_enabled', { voice: voice.name, enabled }).then(() => {
+			voice.enabled = enabled;
+		}).catch((error: BackendError) => {
+			this.voiceStatus = errorMessage(error);
+		}).finally(() => this.changeDetector.detectChanges());
+	}
+	downloadVoice(voice: Voice): void {
+		this.downloadingVoice = voice.name;
+		this.voiceDownloadProgress = 0;
+		this.voiceStatus = '';
+		invoke('download_voice', { voice: voice.name }).catch((error: BackendError) => {
+			this.voiceStatus = `Could not download ${voice.name}. ${errorMessage(error)}`;
+		}).finally(() => {
+			this.downloadingVoice = undefined;
+			this.loadVoices();
+		});
+	}
+	deleteVoice(voice: Voice): void {
+		invoke<number>('delete_voice', { voice: voice.name }).then(freedBytes => {
+			this.voiceStatus = `Deleted ${voice.name} and freed ${(freedBytes/1e6).toFixed(1)} MB.`;
+		}).catch((error: BackendError) => {
+			this.voiceStatus = `Could not delete ${voice.name}. ${errorMessage(error)}`;
+		}).finally(() => this.loadVoices());
 	}
 
 	changeLanguage(option: DropdownOptionComponent): void {
 		// invoke("set_current_language", { languageName: option.value });
 	}
 
+	removeLanguage(): void {
+		const language = this.options.current_language;
+		if (!confirm(`Remove ${language} together with all progress on it?`)) {
+			return;
+		}
+		invoke<RemovedLanguage>('remove_language', { languageName: language }).then(removed => {
+			this.languageRemovalStatus = `Removed ${language} and freed ${(removed.freed_bytes/1e6).toFixed(1)} MB.`;
+			this.loadOptions();
+		}).catch((error: BackendError) => {
+			this.languageRemovalStatus = `Could not remove ${language}. ${errorMessage(error)}`;
+			this.changeDetector.detectChanges();
+		});
+	}
+
+	availableTranslationLanguages(): string[] {
+		return this.languageList.filter(language => 
+			language != this.options.current_language && !this.translationLanguages.includes(language));
+	}
+	addTranslationLanguage(option: DropdownOptionComponent): void {
+		this.translationLanguages.push(option.value);
+		option.dropdown.removeSelection();
+	}
+	removeTranslationLanguage(index: number): void {
+		this.translationLanguages.splice(index, 1);
+	}
+	translationLanguagesChanged(): boolean {
+		const saved = this.options.translation_languages;
+		return saved.length != this.translationLanguages.length 
+			|| saved.some(language => !this.translationLanguages.includes(language));
+	}
+	applyTranslationLanguages(): void {
+		// The download page shows the progress of downloading the added languages.
+		this.router.navigate(['download-language-data'], { state: { translationLanguages: this.translationLanguages } });
+	}
+
+	refreshSentences(): void {
+		this.refreshingSentences = true;
+		this.sentenceRefreshStatus = 'Downloading the latest sentences...';
+		invoke<SentenceChanges>('refresh_sentences').then(changes => {
+			this.sentenceRefreshStatus = `${changes.added_sentences} sentences added, ${changes.removed_sentences} removed and `
+				+ `${changes.updated_sentences} edited. ${changes.added_translations} translations added and `
+				+ `${changes.removed_translations} removed.`;
+		}).catch((error: BackendError) => {
+			this.sentenceRefreshStatus = `Updating failed. ${errorMessage(error)}`;
+		}).finally(() => {
+			this.refreshingSentences = false;
+			this.changeDetector.detectChanges();
+		});
+	}
+
+	verifyInstalledData(): void {
+		this.verifyingData = true;
+		this.dataVerificationStatus = 'Checking the installed data...';
+		this.dataProblems = [];
+		invoke<DataVerification>('verify_installed_data').then(verification => {
+			const unrepaired = verification.problems.filter(problem => !problem.repaired).length;
+			this.dataVerificationStatus = `Checked ${verification.checked_file_count} files. `
+				+ (verification.problems.length ? `${verification.problems.length} were damaged and ${unrepaired} could not be repaired.` : 'Everything is fine.');
+			this.dataProblems = verification.problems.map(problem => 
+				`${problem.path}: ${problem.problem}${problem.repaired ? ' (repaired)' : ''}`);
+		}).catch((error: BackendError) => {
+			this.dataVerificationStatus = `Checking failed. ${errorMessage(error)}`;
+		}).finally(() => {
+			this.verifyingData = false;
+			this.changeDetector.detectChanges();
+		});
+	}
+
+	audioCacheSize(): string {
+		return `${(this.options.audio_cache_size_bytes/1e6).toFixed(1)} MB`;
+	}
+	setAudioCacheSizeLimit(): void {
+		const limitBytes = Math.max(0, Math.round(this.audioCacheSizeLimit*1e6));
+		invoke('set_audio_cache_size_limit', { limitBytes }).then(() => {
+			this.audioCacheStatus = '';
+			this.loadOptions();
+		}).catch((error: BackendError) => {
+			this.audioCacheStatus = errorMessage(error);
+			this.changeDetector.detectChanges();
+		});
+	}
+	clearAudioCache(): void {
+		invoke<number>('clear_audio_cache').then(freedBytes => {
+			this.audioCacheStatus = `Freed ${(freedBytes/1e6).toFixed(1)} MB.`;
+			this.options.audio_cache_size_bytes = 0;
+		}).catch((error: BackendError) => {
+			this.audioCacheStatus = `Could not clear the audio cache. ${errorMessage(error)}`;
+		}).finally(() => this.changeDetector.detectChanges());
+	}
+
+	setSynthesisThreadCount(): void {
+		invoke('set_synthesis_thread_count', { threadCount: this.options.synthesis_thread_count }).then(() => {
+			this.audioCacheStatus = '';
+		}).catch((error: BackendError) => {
+			this.audioCacheStatus = errorMessage(error);
+			this.loadOptions();
+		}).finally(() => this.changeDetector.detectChanges());
+	}
+
+	private loadVoiceModels(): void {
+		invoke<VoiceModel[]>('get_voice_models').then(models => {
+			this.voiceModels = models;
+			this.changeDetector.detectChanges();
+		});
+	}
+	voiceModelDetails(model: VoiceModel): string {
+		if (model.load_time_ms === undefined || model.estimated_memory_bytes === undefined) {
+			return 'not loaded yet';
+		}
+		return `${model.loaded ? 'loaded' : 'unloaded'}, took ${(model.load_time_ms/1000).toFixed(1)} s to load, `
+			+ `about ${(model.estimated_memory_bytes/1e6).toFixed(0)} MB`;
+	}
+	setVoiceModelMemoryBudget(): void {
+		const estimatedBudgetBytes = Math.max(0, Math.round(this.voiceModelMemoryBudget*1e6));
+		invoke('set_voice_model_memory_budget', { estimatedBudgetBytes }).then(() => {
+			this.voiceModelStatus = '';
+			this.loadVoiceModels();
+		}).catch((error: BackendError) => {
+			this.voiceModelStatus = errorMessage(error);
+			this.changeDetector.detectChanges();
+		});
+	}
+
 	saveWeightFactors(): void {
 		// invoke("set_weight_factors", { factors: this.options.weightFactors });
 	}
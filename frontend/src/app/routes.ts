@@ -3,6 +3,7 @@ import { Routes } from '@angular/router';
 import { AddLanguageComponent } from './add-language/add-language.component';
 import { DownloadLanguageDataComponent } from './download-language-data/download-language-data.component';
 import { LearnComponent } from './learn/learn.component';
+import { LoadErrorComponent } from './load-error/load-error.component';
 import { OptionsComponent } from './options/options.component';
 import { StatisticsComponent } from './statistics/statistics.component';
 
@@ -19,6 +20,10 @@ export const routeConfig: Routes = [
 		path: 'learn',
 		component: LearnComponent
 	},
+	{
+		path: 'load-error',
+		component: LoadErrorComponent
+	},
 	{
 		path: 'options',
 		component: OptionsComponent